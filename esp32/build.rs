@@ -1,5 +1,6 @@
 fn main() {
     embuild::espidf::sysenv::output();
+    embed_ca_cert();
     slint_build::compile_with_config(
         "../ui/appwindow.slint",
         slint_build::CompilerConfiguration::new()
@@ -8,3 +9,17 @@ fn main() {
     )
     .unwrap();
 }
+
+/// Copy the PEM file named by `CA_CERT` into `OUT_DIR` for `src/http.rs`; empty when unset.
+fn embed_ca_cert() {
+    println!("cargo:rerun-if-env-changed=CA_CERT");
+    let pem = match std::env::var("CA_CERT") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={}", path);
+            std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("CA_CERT {}: {}", path, e))
+        }
+        _ => String::new(),
+    };
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("ca_cert.pem");
+    std::fs::write(out, pem).unwrap();
+}
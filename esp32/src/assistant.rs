@@ -0,0 +1,85 @@
+//! The voice assistant, configured at build time for this device.
+//!
+//! Every service is optional; stages without a server are skipped.
+
+use log::info;
+use slint_workshop_model::assistant::AssistantConfig;
+use slint_workshop_model::board::SpeakerProfile;
+use slint_workshop_model::chat::ChatConfig;
+use slint_workshop_model::playback::PlaybackQueue;
+use slint_workshop_model::stt::SttConfig;
+use slint_workshop_model::tts::TtsConfig;
+
+use crate::http::{tls_config, EspHttpClient};
+use crate::speaker::I2sSpeaker;
+
+/// Whisper-compatible server for transcribing recordings, set at build time with `STT_URL`.
+const STT_URL: Option<&str> = option_env!("STT_URL");
+const STT_API_KEY: Option<&str> = option_env!("STT_API_KEY");
+/// OpenAI-compatible chat server answering transcripts, set at build time with `LLM_URL`.
+const LLM_URL: Option<&str> = option_env!("LLM_URL");
+const LLM_API_KEY: Option<&str> = option_env!("LLM_API_KEY");
+const LLM_MODEL: Option<&str> = option_env!("LLM_MODEL");
+/// OpenAI-compatible speech server for reading replies aloud, set at build time with `TTS_URL`.
+const TTS_URL: Option<&str> = option_env!("TTS_URL");
+const TTS_API_KEY: Option<&str> = option_env!("TTS_API_KEY");
+const TTS_VOICE: Option<&str> = option_env!("TTS_VOICE");
+
+fn stt_config() -> Option<SttConfig> {
+    STT_URL.map(|url| SttConfig {
+        base_url: url.to_string(),
+        api_key: STT_API_KEY.map(str::to_string),
+        ..Default::default()
+    })
+}
+
+fn llm_config() -> Option<ChatConfig> {
+    let defaults = ChatConfig::default();
+    LLM_URL.map(|url| ChatConfig {
+        base_url: url.to_string(),
+        api_key: LLM_API_KEY.map(str::to_string),
+        model: LLM_MODEL.map_or(defaults.model.clone(), str::to_string),
+        ..defaults
+    })
+}
+
+fn tts_config() -> Option<TtsConfig> {
+    let defaults = TtsConfig::default();
+    TTS_URL.map(|url| TtsConfig {
+        base_url: url.to_string(),
+        api_key: TTS_API_KEY.map(str::to_string),
+        voice: TTS_VOICE.map_or(defaults.voice.clone(), str::to_string),
+        ..defaults
+    })
+}
+
+/// Speaks through the I2S amplifier.
+pub type Assistant = slint_workshop_model::assistant::Assistant<EspHttpClient, I2sSpeaker>;
+
+fn config() -> AssistantConfig {
+    AssistantConfig {
+        stt: stt_config(),
+        chat: llm_config(),
+        tts: tts_config(),
+    }
+}
+
+/// The assistant with the speaker set up if replies are to be spoken.
+pub fn new_assistant(speaker: Option<&SpeakerProfile>) -> Assistant {
+    let config = config();
+    let playback = match (&config.tts, speaker) {
+        (Some(_), Some(speaker)) => match I2sSpeaker::new(speaker).and_then(PlaybackQueue::new) {
+            Ok(playback) => Some(playback),
+            Err(e) => {
+                info!("Speaker unavailable, replies will not be spoken: {:?}", e);
+                None
+            }
+        },
+        (Some(_), None) => {
+            info!("No speaker on this board, replies will not be spoken");
+            None
+        }
+        (None, _) => None,
+    };
+    Assistant::new(EspHttpClient::new(tls_config()), config, playback)
+}
@@ -0,0 +1,220 @@
+//! Board resources: takes `Peripherals` once and hands out the drivers built from them.
+//!
+//! Pins come from the [`PROFILE`] picked with a `board-*` cargo feature. When the SD card
+//! shares SPI2 with the display, each gets its own device on the bus with its own chip
+//! select; ESP-IDF serialises their transactions, and [`BusLock`] keeps a recording being
+//! saved from interleaving with a frame being flushed. Otherwise the card gets SPI3.
+
+use std::sync::{Arc, Mutex};
+
+use esp_idf_svc::hal::gpio::*;
+use esp_idf_svc::hal::i2c::{I2cConfig, I2cDriver};
+use esp_idf_svc::hal::ledc::{config::TimerConfig, LedcChannel, LedcDriver, LedcTimer, LedcTimerDriver};
+use esp_idf_svc::hal::modem::Modem;
+use esp_idf_svc::hal::peripheral::Peripheral;
+use esp_idf_svc::hal::peripherals::Peripherals;
+use esp_idf_svc::hal::prelude::*;
+use esp_idf_svc::hal::spi::*;
+use log::info;
+use slint_workshop_model::board::{BoardProfile, ButtonAction, DisplayProfile, Gpio, SpiBusPins};
+
+use crate::storage::SdStorage;
+
+#[cfg(all(feature = "board-t-camera-plus-speaker", feature = "board-esp32-s3-box-3"))]
+compile_error!("enable at most one board-* feature");
+#[cfg(all(feature = "board-esp32-s3-box-3", not(esp32s3)))]
+compile_error!("the ESP32-S3-BOX-3 needs MCU=esp32s3 and the xtensa-esp32s3-espidf target");
+
+#[cfg(feature = "board-esp32-s3-box-3")]
+pub const PROFILE: &BoardProfile = &slint_workshop_model::board::ESP32_S3_BOX_3;
+#[cfg(feature = "board-t-camera-plus-speaker")]
+pub const PROFILE: &BoardProfile = &slint_workshop_model::board::T_CAMERA_PLUS_SPEAKER;
+#[cfg(not(any(feature = "board-t-camera-plus-speaker", feature = "board-esp32-s3-box-3")))]
+pub const PROFILE: &BoardProfile = &slint_workshop_model::board::T_CAMERA_PLUS;
+
+/// High enough not to flicker, low enough for the LEDC's 8-bit default resolution.
+const BACKLIGHT_PWM_HZ: u32 = 25_000;
+
+/// Largest DMA transfer on the bus; a full-width display row band or an SD sector fits.
+const SPI_DMA_BUFFER: usize = 4096;
+
+/// Held by whoever uses the shared SPI bus for more than a single transaction: the
+/// display while flushing a frame, the recorder while saving to the SD card.
+pub type BusLock = Arc<Mutex<()>>;
+
+pub type SpiBus = Arc<SpiDriver<'static>>;
+pub type DisplaySpi = SpiDeviceDriver<'static, SpiBus>;
+
+// The profile was validated, so no two drivers below get the same pin.
+fn output_pin(gpio: Gpio) -> AnyOutputPin {
+    unsafe { AnyOutputPin::new(gpio as i32) }
+}
+
+fn io_pin(gpio: Gpio) -> AnyIOPin {
+    unsafe { AnyIOPin::new(gpio as i32) }
+}
+
+fn input_pin(gpio: Gpio) -> AnyInputPin {
+    unsafe { AnyInputPin::new(gpio as i32) }
+}
+
+fn output(gpio: Gpio) -> anyhow::Result<PinDriver<'static, AnyOutputPin, Output>> {
+    Ok(PinDriver::output(output_pin(gpio))?)
+}
+
+fn spi_bus(spi: impl Peripheral<P = impl SpiAnyPins> + 'static, pins: &SpiBusPins) -> anyhow::Result<SpiBus> {
+    Ok(Arc::new(SpiDriver::new(
+        spi,
+        output_pin(pins.sclk),
+        output_pin(pins.mosi),
+        pins.miso.map(io_pin),
+        &SpiDriverConfig::new().dma(Dma::Auto(SPI_DMA_BUFFER)),
+    )?))
+}
+
+/// The display's SPI device, control pins and geometry.
+pub struct Display {
+    pub spi: DisplaySpi,
+    pub dc: PinDriver<'static, AnyOutputPin, Output>,
+    pub reset: Option<PinDriver<'static, AnyOutputPin, Output>>,
+    pub profile: DisplayProfile,
+}
+
+/// The display's backlight, dimmed with the LEDC's PWM.
+pub struct Backlight {
+    channel: LedcDriver<'static>,
+    percent: u8,
+}
+
+impl Backlight {
+    /// Start at full brightness.
+    fn new<T: LedcTimer + 'static>(
+        timer: impl Peripheral<P = T> + 'static,
+        channel: impl Peripheral<P = impl LedcChannel<SpeedMode = T::SpeedMode>> + 'static,
+        gpio: Gpio,
+    ) -> anyhow::Result<Self> {
+        // The channel borrows the timer for as long as it runs, which is forever.
+        let timer: &'static LedcTimerDriver<'static, T> = Box::leak(Box::new(LedcTimerDriver::new(
+            timer,
+            &TimerConfig::default().frequency(BACKLIGHT_PWM_HZ.Hz().into()),
+        )?));
+        let mut backlight = Self { channel: LedcDriver::new(channel, timer, output_pin(gpio))?, percent: 0 };
+        backlight.set_brightness(100)?;
+        Ok(backlight)
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.percent
+    }
+
+    pub fn set_brightness(&mut self, percent: u8) -> anyhow::Result<()> {
+        let percent = percent.min(100);
+        let duty = self.channel.get_max_duty() * percent as u32 / 100;
+        self.channel.set_duty(duty)?;
+        self.percent = percent;
+        Ok(())
+    }
+}
+
+/// Buttons from the profile, polled from a UI timer.
+pub struct Buttons {
+    pins: Vec<(ButtonAction, PinDriver<'static, AnyInputPin, Input>, bool)>,
+}
+
+impl Buttons {
+    fn new(profile: &BoardProfile) -> anyhow::Result<Self> {
+        let mut pins = Vec::new();
+        for button in profile.buttons {
+            let mut pin = PinDriver::input(input_pin(button.gpio))?;
+            pin.set_pull(Pull::Up)?;
+            pins.push((button.action, pin, false));
+        }
+        Ok(Self { pins })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Actions of the buttons pressed since the last call.
+    pub fn poll(&mut self) -> Vec<ButtonAction> {
+        let mut pressed = Vec::new();
+        for (action, pin, was_down) in &mut self.pins {
+            let down = pin.is_low();
+            if down && !*was_down {
+                pressed.push(*action);
+            }
+            *was_down = down;
+        }
+        pressed
+    }
+}
+
+pub struct Board {
+    pub display: Display,
+    /// `None` when the profile has no backlight pin.
+    pub backlight: Option<Backlight>,
+    /// Not mounted yet; `None` on boards without an SD slot.
+    pub sd_card: Option<SdStorage>,
+    pub buttons: Buttons,
+    /// The bus an indoor sensor may be on; `None` when the profile has none.
+    pub sensor_i2c: Option<I2cDriver<'static>>,
+    pub modem: Modem,
+    pub bus_lock: BusLock,
+}
+
+impl Board {
+    /// Take the peripherals and set up the display, the SD card's bus, the buttons and the sensor bus.
+    pub fn take(profile: &BoardProfile, display_clock_hz: u32) -> anyhow::Result<Self> {
+        profile.validate()?;
+        info!("Board: {}", profile.name);
+        let peripherals = Peripherals::take()?;
+
+        let display_bus = spi_bus(peripherals.spi2, &profile.display.bus)?;
+        let display_config = config::Config::new()
+            .baudrate(Hertz(display_clock_hz)) // 10MHz by default; 40MHz is unreliable on the ESP32
+            .data_mode(embedded_hal::spi::MODE_0);
+        let display = Display {
+            spi: SpiDeviceDriver::new(display_bus.clone(), Some(output_pin(profile.display.cs)), &display_config)?,
+            dc: output(profile.display.dc)?,
+            reset: profile.display.reset.map(output).transpose()?,
+            profile: profile.display,
+        };
+        let backlight = profile
+            .display
+            .backlight
+            .map(|gpio| Backlight::new(peripherals.ledc.timer0, peripherals.ledc.channel0, gpio))
+            .transpose()?;
+
+        let bus_lock: BusLock = Arc::new(Mutex::new(()));
+        let sd_card = match profile.sd {
+            Some(sd) => {
+                let bus = if profile.sd_shares_display_bus() {
+                    display_bus
+                } else {
+                    spi_bus(peripherals.spi3, &sd.bus)?
+                };
+                Some(SdStorage::new(bus, sd.cs, bus_lock.clone()))
+            }
+            None => None,
+        };
+
+        let sensor_i2c = match profile.sensor_i2c {
+            Some(pins) => {
+                let config = I2cConfig::new().baudrate(100.kHz().into());
+                Some(I2cDriver::new(peripherals.i2c0, io_pin(pins.sda), io_pin(pins.scl), &config)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            display,
+            backlight,
+            sd_card,
+            buttons: Buttons::new(profile)?,
+            sensor_i2c,
+            modem: peripherals.modem,
+            bus_lock,
+        })
+    }
+}
@@ -7,7 +7,11 @@ use esp_idf_svc::hal::delay::*;
 use embedded_hal::delay::DelayNs;
 use slint::PhysicalPosition;
 
-// ST7789 Commands
+use slint_workshop_model::board::{DisplayController, DisplayProfile};
+
+use crate::board::{BusLock, Display, DisplaySpi};
+
+// ST7789 Commands; the ILI9342 uses the same ones
 const ST7789_SWRESET: u8 = 0x01;
 const ST7789_SLPOUT: u8 = 0x11;
 const ST7789_COLMOD: u8 = 0x3A;
@@ -16,14 +20,16 @@ const ST7789_CASET: u8 = 0x2A;
 const ST7789_RASET: u8 = 0x2B;
 const ST7789_RAMWR: u8 = 0x2C;
 const ST7789_DISPON: u8 = 0x29;
+const ST7789_INVON: u8 = 0x21;
 
 pub struct EspPlatform {
     display_width: usize,
     display_height: usize,
     // SPI display components - wrapped in RefCell for interior mutability
-    spi_device: std::cell::RefCell<esp_idf_svc::hal::spi::SpiDeviceDriver<'static, SpiDriver<'static>>>,
+    spi_device: std::cell::RefCell<DisplaySpi>,
     dc_pin: std::cell::RefCell<PinDriver<'static, AnyOutputPin, Output>>,
-    backlight_pin: PinDriver<'static, AnyOutputPin, Output>,
+    /// Shared with the SD card; held while a frame is flushed.
+    bus_lock: BusLock,
     window: alloc::rc::Rc<slint::platform::software_renderer::MinimalSoftwareWindow>,
     timer: esp_idf_svc::timer::EspTimerService<esp_idf_svc::timer::Task>,
     pub wifi: std::rc::Rc<
@@ -32,38 +38,17 @@ pub struct EspPlatform {
 }
 
 impl EspPlatform {
-    /// Create a new instance of the platform with ST7789 SPI display
-    pub fn new() -> std::boxed::Box<Self> {
-        use esp_idf_svc::hal::prelude::*;
-
-
-        let peripherals = Peripherals::take().unwrap();
-
-        // Initialize SPI for ST7789 display
-        // ESP32 pin mapping for LilyGo Camera Plus
-        let spi = SpiDriver::new(
-            peripherals.spi2,
-            peripherals.pins.gpio21, // SCLK
-            peripherals.pins.gpio19, // MOSI  
-            Some(peripherals.pins.gpio18), // MISO (not used but required - using GPIO18)
-            &SpiDriverConfig::new()
-        ).unwrap();
-
-        let cs_pin = peripherals.pins.gpio12;
-        let dc_pin = PinDriver::output(peripherals.pins.gpio15.downgrade_output()).unwrap();
-        let mut backlight_pin = PinDriver::output(peripherals.pins.gpio2.downgrade_output()).unwrap();
-
-        // Turn on backlight
-        backlight_pin.set_high().unwrap();
-
-        let spi_config = config::Config::new()
-            .baudrate(10.MHz().into()) // Reduced from 40MHz to 10MHz for ESP32 compatibility
-            .data_mode(embedded_hal::spi::MODE_0);
-
-        let spi_device = SpiDeviceDriver::new(spi, Some(cs_pin), &spi_config).unwrap();
-
-        // Initialize ST7789 display - we need to do this after creating RefCells
-        log::info!("Creating SPI device completed, initializing ST7789...");
+    /// Create a new instance of the platform with an ST7789 or ILI9342 SPI display
+    pub fn new(
+        display: Display,
+        modem: esp_idf_svc::hal::modem::Modem,
+        nvs: esp_idf_svc::nvs::EspDefaultNvsPartition,
+        bus_lock: BusLock,
+    ) -> std::boxed::Box<Self> {
+        let Display { spi: spi_device, dc: dc_pin, reset, profile } = display;
+
+        // Initialize the display - we need to do this after creating RefCells
+        log::info!("Initializing {:?}...", profile.controller);
 
         // Wrap SPI device and DC pin in RefCell for interior mutability
         let spi_device = std::cell::RefCell::new(spi_device);
@@ -71,17 +56,17 @@ impl EspPlatform {
 
         // Now initialize the display using the RefCell wrapped components
         {
+            let _bus = bus_lock.lock().unwrap();
             let mut spi_dev = spi_device.borrow_mut();
             let mut dc = dc_pin_cell.borrow_mut();
-            Self::init_st7789(&mut *spi_dev, &mut *dc).unwrap();
+            Self::init_st7789(&mut *spi_dev, &mut *dc, reset, &profile).unwrap();
         }
 
         // Remove all touch-related code since we don't need it
         log::info!("Skipping touch controller - display only mode");
 
-        // Setup the window for 240x240 ST7789
-        let display_width = 240;
-        let display_height = 240;
+        let display_width = profile.width as usize;
+        let display_height = profile.height as usize;
 
         let window = slint::platform::software_renderer::MinimalSoftwareWindow::new(Default::default());
         window.set_size(slint::PhysicalSize::new(display_width as u32, display_height as u32));
@@ -96,11 +81,10 @@ impl EspPlatform {
 
         // Initialize WiFi
         let sys_loop = esp_idf_svc::eventloop::EspSystemEventLoop::take().unwrap();
-        let nvs = esp_idf_svc::nvs::EspDefaultNvsPartition::take().unwrap();
 
         let wifi = std::rc::Rc::new(std::cell::RefCell::new(
             esp_idf_svc::wifi::BlockingWifi::wrap(
-                esp_idf_svc::wifi::EspWifi::new(peripherals.modem, sys_loop.clone(), Some(nvs))
+                esp_idf_svc::wifi::EspWifi::new(modem, sys_loop.clone(), Some(nvs))
                     .unwrap(),
                 sys_loop,
             ).unwrap(),
@@ -111,7 +95,7 @@ impl EspPlatform {
             display_height,
             spi_device,
             dc_pin: dc_pin_cell,
-            backlight_pin,
+            bus_lock,
             window,
             timer: esp_idf_svc::timer::EspTimerService::new().unwrap(),
             wifi,
@@ -119,14 +103,22 @@ impl EspPlatform {
     }
 
     fn init_st7789(
-        spi: &mut SpiDeviceDriver<'static, SpiDriver<'static>>,
+        spi: &mut DisplaySpi,
         dc_pin: &mut PinDriver<'static, AnyOutputPin, Output>,
+        reset: Option<PinDriver<'static, AnyOutputPin, Output>>,
+        profile: &DisplayProfile,
     ) -> Result<(), esp_idf_svc::sys::EspError> {
         let mut delay = FreeRtos {};
 
-        log::info!("Starting ST7789 initialization...");
+        log::info!("Starting {:?} initialization...", profile.controller);
 
-        // Hardware reset (if reset pin is available, implement here)
+        // Hardware reset. The driver is forgotten so dropping it doesn't release the pin
+        if let Some(mut reset) = reset {
+            reset.set_low()?;
+            delay.delay_ms(10_u32);
+            reset.set_high()?;
+            core::mem::forget(reset);
+        }
         delay.delay_ms(120_u32);
 
         // Software reset
@@ -141,31 +133,41 @@ impl EspPlatform {
 
         // Color mode: 16-bit RGB565
         log::info!("Setting color mode...");
-        Self::write_command(spi, dc_pin, ST7789_COLMOD, &[0x05])?;
+        let colmod = match profile.controller {
+            DisplayController::St7789 => 0x05,
+            DisplayController::Ili9342 => 0x55,
+        };
+        Self::write_command(spi, dc_pin, ST7789_COLMOD, &[colmod])?;
 
-        // Memory access control (adjust rotation/mirroring as needed)
+        // Memory access control: rotation, mirroring and color order
         log::info!("Setting memory access control...");
-        Self::write_command(spi, dc_pin, ST7789_MADCTL, &[0x00])?;
+        Self::write_command(spi, dc_pin, ST7789_MADCTL, &[profile.madctl])?;
 
-        // Column address set (0 to 239)
+        if profile.invert_colors {
+            Self::write_command(spi, dc_pin, ST7789_INVON, &[])?;
+        }
+
+        // Column address set (0 to width - 1)
         log::info!("Setting column address...");
-        Self::write_command(spi, dc_pin, ST7789_CASET, &[0x00, 0x00, 0x00, 0xEF])?;
+        let [x1_high, x1_low] = (profile.width - 1).to_be_bytes();
+        Self::write_command(spi, dc_pin, ST7789_CASET, &[0x00, 0x00, x1_high, x1_low])?;
 
-        // Row address set (0 to 239)  
+        // Row address set (0 to height - 1)
         log::info!("Setting row address...");
-        Self::write_command(spi, dc_pin, ST7789_RASET, &[0x00, 0x00, 0x00, 0xEF])?;
+        let [y1_high, y1_low] = (profile.height - 1).to_be_bytes();
+        Self::write_command(spi, dc_pin, ST7789_RASET, &[0x00, 0x00, y1_high, y1_low])?;
 
         // Display on
         log::info!("Turning display on...");
         Self::write_command(spi, dc_pin, ST7789_DISPON, &[])?;
         delay.delay_ms(120_u32);
 
-        log::info!("ST7789 display initialized successfully");
+        log::info!("{:?} display initialized successfully", profile.controller);
         Ok(())
     }
 
     fn write_command(
-        spi: &mut SpiDeviceDriver<'static, SpiDriver<'static>>,
+        spi: &mut DisplaySpi,
         dc_pin: &mut PinDriver<'static, AnyOutputPin, Output>,
         command: u8,
         data: &[u8],
@@ -211,6 +213,8 @@ impl EspPlatform {
     pub fn fill_screen(&self, color: u16) -> Result<(), esp_idf_svc::sys::EspError> {
         log::info!("Filling screen with color: 0x{:04X}", color);
         
+        let _bus = self.bus_lock.lock().unwrap();
+
         // Set address window to entire screen
         self.set_address_window(0, 0, (self.display_width - 1) as u16, (self.display_height - 1) as u16)?;
         
@@ -261,7 +265,8 @@ fn run_event_loop(&self) -> Result<(), slint::PlatformError> {
             // Render to buffer
             let region = renderer.render(&mut buffer, self.display_width);
 
-            // Send buffer to ST7789 display
+            // Send buffer to the display, keeping the SD card off the bus meanwhile
+            let _bus = self.bus_lock.lock().unwrap();
             for (origin, size) in region.iter() {
                 if let Err(e) = self.update_display_region(&origin, &size, &buffer) {
                     log::error!("Failed to update display: {:?}", e);
@@ -0,0 +1,156 @@
+//! `HttpClient` implementation on top of the ESP-IDF HTTP client.
+//!
+//! Servers are verified against the ESP-IDF certificate bundle, or against the CA built
+//! in from `CA_CERT`. ESP-TLS takes one or the other per connection, so unlike the desktop,
+//! which adds `CA_CERT` to its roots, only the clients from [`tls_config`] use it and all
+//! others keep the bundle. Plain HTTP needs `ALLOW_HTTP=1` at build time.
+
+use std::io;
+use std::time::Duration;
+
+use embedded_svc::http::client::Client;
+use embedded_svc::http::{Headers, Method as EspMethod, Status};
+use esp_idf_svc::http::client::{Configuration as HttpConfig, EspHttpConnection};
+use esp_idf_svc::io::{Read, Write};
+use esp_idf_svc::tls::X509;
+use slint_workshop_model::http::{HttpClient, Method, Request, Response, TlsConfig};
+
+/// Response headers copied into [`Response`]; ESP-IDF only supports lookups by name.
+const RESPONSE_HEADERS: [&str; 4] = ["Content-Type", "Content-Length", "Retry-After", "Location"];
+
+/// PEM file named by `CA_CERT` at build time, copied by `build.rs`; empty when unset.
+const CA_CERT: &str = include_str!(concat!(env!("OUT_DIR"), "/ca_cert.pem"));
+const ALLOW_HTTP: Option<&str> = option_env!("ALLOW_HTTP");
+
+/// The TLS settings chosen at build time, for clients talking to our own servers: the
+/// assistant and the uploader. Public services such as the weather keep the default.
+pub fn tls_config() -> TlsConfig {
+    TlsConfig {
+        ca_certs: (!CA_CERT.trim().is_empty()).then(|| CA_CERT.to_string()),
+        allow_http: ALLOW_HTTP == Some("1"),
+    }
+}
+
+fn esp_error(error: impl std::fmt::Debug) -> io::Error {
+    io::Error::other(format!("{:?}", error))
+}
+
+#[derive(Clone)]
+pub struct EspHttpClient {
+    pub timeout: Duration,
+    tls: TlsConfig,
+    /// `tls.ca_certs` NUL-terminated, as ESP-IDF wants it.
+    ca_cert: Option<&'static [u8]>,
+}
+
+impl Default for EspHttpClient {
+    /// HTTPS only, trusting the certificate bundle.
+    fn default() -> Self {
+        Self::new(TlsConfig::default())
+    }
+}
+
+impl EspHttpClient {
+    /// A custom CA replaces the certificate bundle for this client, so it can no longer
+    /// reach servers with publicly issued certificates.
+    pub fn new(tls: TlsConfig) -> Self {
+        // Clients are created once at startup, so leaking the certificate is bounded.
+        let ca_cert = tls
+            .ca_certs
+            .as_ref()
+            .map(|pem| &*Box::leak(format!("{}\0", pem).into_bytes().into_boxed_slice()));
+        Self {
+            timeout: Duration::from_secs(30),
+            tls,
+            ca_cert,
+        }
+    }
+}
+
+impl HttpClient for EspHttpClient {
+    fn execute(&self, request: Request) -> io::Result<Response> {
+        let mut body = Vec::new();
+        let mut response = self.execute_streaming(request, &mut |data| {
+            body.extend_from_slice(data);
+            Ok(())
+        })?;
+        if response.is_success() {
+            response.body = body;
+        }
+        Ok(response)
+    }
+
+    fn execute_streaming(
+        &self,
+        request: Request,
+        on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<Response> {
+        self.tls.check_url(&request.url)?;
+        let config = HttpConfig {
+            crt_bundle_attach: match self.ca_cert {
+                Some(_) => None,
+                None => Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+            },
+            server_certificate: self.ca_cert.map(X509::pem_until_nul),
+            timeout: Some(request.timeout.unwrap_or(self.timeout)),
+            ..Default::default()
+        };
+        let connection = EspHttpConnection::new(&config).map_err(esp_error)?;
+        let mut client = Client::wrap(connection);
+
+        let content_length = request.body.len().to_string();
+        let mut headers: Vec<(&str, &str)> = request
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        headers.push(("Content-Length", &content_length));
+
+        let method = match request.method {
+            Method::Get => EspMethod::Get,
+            Method::Post => EspMethod::Post,
+            Method::Put => EspMethod::Put,
+            Method::Delete => EspMethod::Delete,
+        };
+        let mut esp_request = client
+            .request(method, &request.url, &headers)
+            .map_err(esp_error)?;
+        esp_request.write_all(&request.body).map_err(esp_error)?;
+        esp_request.flush().map_err(esp_error)?;
+
+        let mut esp_response = esp_request.submit().map_err(esp_error)?;
+        let status = esp_response.status();
+        let headers = RESPONSE_HEADERS
+            .iter()
+            .filter_map(|&name| {
+                esp_response
+                    .header(name)
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        let success = (200..300).contains(&status);
+        let mut body = Vec::new();
+        let mut buffer = [0u8; 1024];
+        let mut received = 0;
+        loop {
+            let read = esp_response.read(&mut buffer).map_err(esp_error)?;
+            if read == 0 {
+                break;
+            }
+            received += read;
+            request.check_response_size(received)?;
+            if success {
+                on_data(&buffer[..read])?;
+            } else {
+                body.extend_from_slice(&buffer[..read]);
+            }
+        }
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
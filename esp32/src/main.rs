@@ -1,243 +1,161 @@
+mod assistant;
+mod board;
 mod esp32;
+mod http;
+mod mic;
+mod mqtt;
+mod nvs;
+mod sensor;
+mod speaker;
+mod storage;
+mod upload;
 
 slint::include_modules!();
-use esp_idf_svc::sys::configTICK_RATE_HZ;
 use log::info;
 use esp_idf_svc::wifi::ClientConfiguration;
-use embedded_svc::http::client::Client;
-use esp_idf_svc::io::Read;
-use esp_idf_svc::http::client::{Configuration as HttpConfig, EspHttpConnection};
-use std::fs::File;
-use std::io::Write;
-use esp_idf_svc::hal::prelude::*;
-use esp_idf_svc::hal::spi::*;
-use esp_idf_svc::sd::spi::*;
-use esp_idf_svc::sd::*;
+use esp_idf_svc::sntp::{EspSntp, SyncStatus};
+use slint_workshop_model::clock::{is_valid_unix, unix_now, ClockDisplay, PosixTz};
+use slint_workshop_model::assistant::{AssistantWorker, ChatEvent, ChatLog};
+use slint_workshop_model::board::ButtonAction;
+use slint_workshop_model::capture::{AudioSource, CaptureRecorder};
+use slint_workshop_model::history::{History, Trend, HISTORY_FILE};
+use slint_workshop_model::recordings::{RecordingIndex, RetentionPolicy};
+use slint_workshop_model::chat::Role;
+use slint_workshop_model::navigation::Page;
+use slint_workshop_model::recording::{Recorder, RecordingSession};
+use slint_workshop_model::settings::{SettingField, Settings, SettingsStore};
+use slint_workshop_model::storage::{StorageEvent, StorageManager};
+use slint_workshop_model::telemetry::{Command, RecordingEvent};
+use slint_workshop_model::upload::Uploader;
+use slint_workshop_model::weather::OpenMeteo;
+use slint_workshop_model::{Model as AppModel, WifiNetworkProvider};
+use nvs::NvsSettingsStore;
+use assistant::Assistant;
+use mic::I2sMic;
+use std::sync::{Arc, Mutex};
 
 type Wifi = esp_idf_svc::wifi::BlockingWifi<esp_idf_svc::wifi::EspWifi<'static>>;
 
 pub struct Model {
     wifi: std::rc::Rc<std::cell::RefCell<Wifi>>,
-    audio_recorder: std::rc::Rc<std::cell::RefCell<Option<AudioRecorder>>>,
+    /// Settings, weather, navigation and the recorder, shared with the desktop build.
+    app: std::cell::RefCell<AppModel<CaptureRecorder>>,
+    /// Transcribes recordings and answers them; shared with the assistant worker.
+    assistant: Arc<Assistant>,
+    /// Keeps the SD card mounted while the app runs; `None` on boards without a slot.
+    storage: std::cell::RefCell<Option<StorageManager>>,
+    /// Held around card access from the UI, as the capture thread and the uploader do.
+    bus_lock: board::BusLock,
+    /// Sends recordings to `UPLOAD_URL`; `None` when it isn't set.
+    uploader: Option<Arc<Uploader>>,
+    /// Publishes to `MQTT_URL` and takes commands from it; `None` when it isn't set.
+    telemetry: Option<Arc<mqtt::EspTelemetry>>,
+    /// When health was last published; again after every reconnect.
+    last_health: std::cell::Cell<Option<std::time::Instant>>,
+    backlight: std::cell::RefCell<Option<board::Backlight>>,
+    /// Kept alive so SNTP keeps the system clock in sync.
+    sntp: std::cell::RefCell<Option<EspSntp<'static>>>,
+    timezone: PosixTz,
+    /// Recent messages shown on the chat page.
+    chat_log: std::cell::RefCell<ChatLog>,
+    /// Pipeline stage reported by the assistant; recording and speaking take precedence.
+    assistant_state: std::cell::Cell<AssistantState>,
 }
 
-pub struct AudioRecorder {
-    sd_mounted: bool,
-}
+const SAMPLE_RATE: u32 = 16000;
+/// POSIX TZ string for the on-screen clock, overridable at build time with `TIMEZONE`.
+const TIMEZONE: &str = match option_env!("TIMEZONE") {
+    Some(tz) => tz,
+    None => "EST5EDT,M3.2.0,M11.1.0",
+};
+const MAX_CHAT_MESSAGES: usize = 20;
+/// Hours shown on the history page.
+const HISTORY_HOURS: usize = 24;
+/// How often heap, PSRAM and signal strength are published over MQTT.
+const HEALTH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// How often the indoor sensor is read.
+const SENSOR_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// How often the SD card is checked for removal.
+const STORAGE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// Time between mount attempts while no card is mounted.
+const STORAGE_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Keeps the card from filling up; the oldest recordings are deleted first. The size
+/// limit comes from the free space of the card, see `card_retention`.
+const RETENTION_POLICY: RetentionPolicy = RetentionPolicy {
+    max_count: Some(200),
+    max_bytes: None,
+    max_age: Some(std::time::Duration::from_secs(30 * 24 * 60 * 60)),
+};
+/// Left free on the card for the index and the other small files kept there.
+const RESERVED_BYTES: u64 = 16 * 1024 * 1024;
 
-impl AudioRecorder {
+/// Install the microphone driver. Without an SD card recordings are handed to the
+/// assistant but not kept.
+fn new_recorder(
+    index: Option<RecordingIndex>,
+    retention: RetentionPolicy,
+    bus_lock: board::BusLock,
+    assistant: Arc<Assistant>,
+    uploader: Option<Arc<Uploader>>,
+    telemetry: Option<Arc<mqtt::EspTelemetry>>,
+) -> anyhow::Result<CaptureRecorder> {
+    info!("Initializing audio recorder...");
 
-fn new(peripherals: &mut esp_idf_svc::hal::peripherals::Peripherals) -> anyhow::Result<Self> {
-        info!("Initializing audio recorder...");
-        
-        let sd_mounted = Self::init_sd_card_hal(peripherals)?;
-        let i2s_initialized = Self::init_i2s()?;
-        
-        if !i2s_initialized {
-            info!("Failed to initialize I2S, audio recording will be disabled");
-        }
-        
-        info!("Audio recorder initialized successfully - SD: {}, I2S: {}", sd_mounted, i2s_initialized);
-        
-        Ok(Self {
-            sd_mounted: sd_mounted && i2s_initialized,
-        })
-    }
+    let profile = board::PROFILE.mic.ok_or_else(|| anyhow::anyhow!("no microphone on this board"))?;
+    let mic = I2sMic::install(SAMPLE_RATE, &profile)?;
+    let assistant = AssistantWorker::new(assistant)?;
 
-    
+    let mut recorder = CaptureRecorder::new(
+        RecordingSession::new(SAMPLE_RATE, Settings::default().recording_length()),
+        move || Ok(Box::new(mic) as Box<dyn AudioSource>),
+    )
+    .with_storage_lock(bus_lock)
+    .with_retention(retention);
+    if index.is_none() {
+        info!("SD card not available, recordings will not be saved");
+    }
+    recorder.set_index(index);
 
+    Ok(recorder.on_saved(move |saved| {
+        if let Some(uploader) = &uploader {
+            uploader.enqueue(&saved.file_name);
+        }
+        if let Some(telemetry) = &telemetry {
+            let _ = telemetry.publish_recording(&RecordingEvent::from(&saved));
+        }
+        assistant.submit(&saved.file_name, saved.format, saved.encoded)
+    }))
+}
 
-fn init_sd_card_hal(peripherals: &mut esp_idf_svc::hal::peripherals::Peripherals) -> anyhow::Result<bool> {
-    info!("Initializing SD card using HAL...");
-    
-    // Use high-level SPI driver
-    let spi_driver = SpiDriver::new(
-        peripherals.spi2.take().unwrap(),
-        19, // MOSI
-        22, // MISO  
-        21, // SCLK
-        &DriverConfig::default(),
-    )?;
-    
-    // Create SD SPI device
-    let sd_device = SpiDevice::new(
-        spi_driver,
-        0, // CS pin
-        Option::<esp_idf_svc::hal::gpio::AnyInputPin>::None, // CD
-        Option::<esp_idf_svc::hal::gpio::AnyInputPin>::None, // WP
-        Option::<esp_idf_svc::hal::gpio::AnyInputPin>::None, // INT
-    );
-    
-    // Use default SD configuration
-    let config = SdConfiguration::new();
-    
-    // Mount the SD card
-    match SdCard::mount(config, sd_device) {
-        Ok(_card) => {
-            info!("SD card mounted successfully using HAL");
-            Ok(true)
+/// The recordings index of a freshly mounted card, which may repair the files on it.
+fn open_recordings_index(storage: &StorageManager, bus_lock: &board::BusLock) -> Option<RecordingIndex> {
+    let _bus = bus_lock.lock().unwrap();
+    match RecordingIndex::open(storage.root()) {
+        Ok(index) => {
+            info!("{} recordings on SD card ({} bytes)", index.entries().len(), index.total_bytes());
+            Some(index)
         }
         Err(e) => {
-            info!("SD card mount failed: {:?}", e);
-            Ok(false)
+            info!("Failed to open recordings index: {:?}", e);
+            None
         }
     }
 }
 
-    
-    fn init_i2s() -> anyhow::Result<bool> {
-        info!("Initializing I2S for microphone...");
-        
-        unsafe {
-            let mut i2s_config: esp_idf_svc::sys::i2s_config_t = std::mem::zeroed();
-            i2s_config.mode = esp_idf_svc::sys::i2s_mode_t_I2S_MODE_MASTER 
-                | esp_idf_svc::sys::i2s_mode_t_I2S_MODE_RX;
-            i2s_config.sample_rate = 16000;
-            i2s_config.bits_per_sample = esp_idf_svc::sys::i2s_bits_per_sample_t_I2S_BITS_PER_SAMPLE_16BIT;
-            i2s_config.channel_format = esp_idf_svc::sys::i2s_channel_fmt_t_I2S_CHANNEL_FMT_ONLY_LEFT;
-            i2s_config.communication_format = esp_idf_svc::sys::i2s_comm_format_t_I2S_COMM_FORMAT_STAND_I2S;
-            i2s_config.intr_alloc_flags = 0;
-            
-            // Fixed I2S configuration field access
-            i2s_config.__bindgen_anon_1.dma_buf_count = 8;
-            i2s_config.__bindgen_anon_1.dma_buf_len = 1024;
-            
-            i2s_config.use_apll = false;
-            i2s_config.tx_desc_auto_clear = false;
-            i2s_config.fixed_mclk = 0;
-            i2s_config.mclk_multiple = esp_idf_svc::sys::i2s_mclk_multiple_t_I2S_MCLK_MULTIPLE_256;
-            i2s_config.bits_per_chan = esp_idf_svc::sys::i2s_bits_per_chan_t_I2S_BITS_PER_CHAN_DEFAULT;
-            
-            let pin_config = esp_idf_svc::sys::i2s_pin_config_t {
-                bck_io_num: 14,
-                ws_io_num: 32,
-                data_out_num: esp_idf_svc::sys::I2S_PIN_NO_CHANGE,
-                data_in_num: 33,
-                mck_io_num: esp_idf_svc::sys::I2S_PIN_NO_CHANGE,
-            };
-            
-            let ret = esp_idf_svc::sys::i2s_driver_install(
-                esp_idf_svc::sys::i2s_port_t_I2S_NUM_1,
-                &i2s_config,
-                0,
-                std::ptr::null_mut(),
-            );
-            
-            if ret != esp_idf_svc::sys::ESP_OK {
-                info!("I2S driver install failed: {}", ret);
-                return Ok(false);
-            }
-            
-            let ret = esp_idf_svc::sys::i2s_set_pin(
-                esp_idf_svc::sys::i2s_port_t_I2S_NUM_1,
-                &pin_config,
-            );
-            
-            if ret != esp_idf_svc::sys::ESP_OK {
-                info!("I2S pin config failed: {}", ret);
-                info!("Continuing despite pin config failure...");
-            }
-            
-            info!("I2S initialized successfully");
-            Ok(true)
-        }
-    }
-    
-    fn record_audio(&mut self) -> anyhow::Result<()> {
-        if !self.sd_mounted {
-            info!("SD card not available, simulating recording (no save)");
-        } else {
-            info!("Starting 10-second audio recording to SD card...");
-        }
-        
-        let sample_rate = 16000;
-        let duration_seconds = 10;
-        let bytes_per_sample = 2;
-        let total_samples = sample_rate * duration_seconds;
-        let total_bytes = total_samples * bytes_per_sample;
-        
-        let mut audio_buffer = Vec::with_capacity(total_bytes);
-        let mut temp_buffer = [0u8; 2048];
-        
-        info!("Recording {} samples ({} bytes)...", total_samples, total_bytes);
-        
-        let start_time = std::time::Instant::now();
-        while audio_buffer.len() < total_bytes && start_time.elapsed().as_secs() < 10 {
-            let mut bytes_read = 0;
-            
-            unsafe {
-                let ret = esp_idf_svc::sys::i2s_read(
-                    esp_idf_svc::sys::i2s_port_t_I2S_NUM_1,
-                    temp_buffer.as_mut_ptr() as *mut std::ffi::c_void,
-                    temp_buffer.len(),
-                    &mut bytes_read,
-                    (1000 * configTICK_RATE_HZ) / 1000,
-                );
-                
-                if ret == esp_idf_svc::sys::ESP_OK && bytes_read > 0 {
-                    let remaining_bytes = total_bytes - audio_buffer.len();
-                    let bytes_to_copy = bytes_read.min(remaining_bytes);
-                    audio_buffer.extend_from_slice(&temp_buffer[..bytes_to_copy]);
-                    
-                    if audio_buffer.len() % (sample_rate * bytes_per_sample) == 0 {
-                        let seconds_recorded = audio_buffer.len() / (sample_rate * bytes_per_sample);
-                        info!("Recorded {} seconds...", seconds_recorded);
-                    }
-                } else {
-                    info!("I2S read error or timeout: {}, bytes_read: {}", ret, bytes_read);
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
-            }
-            
-            esp_idf_svc::hal::task::do_yield();
-        }
-        
-        info!("Recorded {} bytes in {:?}", audio_buffer.len(), start_time.elapsed());
-        
-        if self.sd_mounted {
-            let filename = format!("/sdcard/rec_{}.wav", 
-                                  std::time::SystemTime::now()
-                                      .duration_since(std::time::UNIX_EPOCH)
-                                      .unwrap_or_default()
-                                      .as_secs());
-            
-            self.save_wav_file(&filename, &audio_buffer, sample_rate as u32)?;
-            info!("Audio saved to: {}", filename);
-        } else {
-            info!("Audio recording completed (not saved - no SD card)");
-        }
-        
-        Ok(())
+/// Recordings may fill the card up to `RESERVED_BYTES`, counting the space they take
+/// already. Without the free space only the count and age limits apply.
+fn card_retention(storage: &StorageManager, index: &RecordingIndex) -> RetentionPolicy {
+    match storage.info() {
+        Some(info) => RETENTION_POLICY.within_free_space(index, &info, RESERVED_BYTES),
+        None => RETENTION_POLICY,
     }
-    
-    fn save_wav_file(&self, filename: &str, audio_data: &[u8], sample_rate: u32) -> anyhow::Result<()> {
-        let mut file = File::create(filename)?;
-        
-        let data_size = audio_data.len() as u32;
-        let file_size = 36 + data_size;
-        
-        // RIFF header
-        file.write_all(b"RIFF")?;
-        file.write_all(&file_size.to_le_bytes())?;
-        file.write_all(b"WAVE")?;
-        
-        // fmt chunk
-        file.write_all(b"fmt ")?;
-        file.write_all(&16u32.to_le_bytes())?;
-        file.write_all(&1u16.to_le_bytes())?;
-        file.write_all(&1u16.to_le_bytes())?;
-        file.write_all(&sample_rate.to_le_bytes())?;
-        file.write_all(&(sample_rate * 2).to_le_bytes())?;
-        file.write_all(&2u16.to_le_bytes())?;
-        file.write_all(&16u16.to_le_bytes())?;
-        
-        // data chunk
-        file.write_all(b"data")?;
-        file.write_all(&data_size.to_le_bytes())?;
-        file.write_all(audio_data)?;
-        
-        file.flush()?;
-        Ok(())
+}
+
+/// Keep the weather history on the card, after what was saved there before.
+fn attach_history(app: &mut AppModel<CaptureRecorder>, storage: &StorageManager) {
+    match app.history_mut().attach(storage.root().join(HISTORY_FILE)) {
+        Ok(()) => info!("{} weather samples on SD card", app.history().len()),
+        Err(e) => info!("Failed to open weather history: {:?}", e),
     }
 }
 
@@ -269,90 +187,572 @@ impl Model {
         info!("WiFi connected!");
         Ok(())
     }
+
+    /// Start syncing the system clock. SNTP keeps polling in the background.
+    fn start_sntp(&self) -> anyhow::Result<()> {
+        if self.sntp.borrow().is_none() {
+            info!("Starting SNTP time sync...");
+            *self.sntp.borrow_mut() = Some(EspSntp::new_default()?);
+        }
+        Ok(())
+    }
+
+    fn update_clock_ui(&self, ui: &MainWindow) {
+        let synced = self
+            .sntp
+            .borrow()
+            .as_ref()
+            .is_some_and(|sntp| sntp.get_sync_status() == SyncStatus::Completed);
+        let mut display = ClockDisplay::new(unix_now(), &self.timezone);
+        // Before the first sync the clock still shows time since boot.
+        display.synced &= synced;
+        ui.set_clock_time(display.time.into());
+        ui.set_clock_date(display.date.into());
+        ui.set_clock_synced(display.synced);
+    }
     
     fn start_audio_recording(&self) -> anyhow::Result<()> {
-        if let Some(recorder) = self.audio_recorder.borrow_mut().as_mut() {
-            recorder.record_audio()?;
+        if self.app.borrow().is_recording() {
+            info!("Recording already in progress");
+            return Ok(());
+        }
+        let format = self.app.borrow().settings().recording_format;
+        self.app.borrow_mut().start_recording(format)?;
+        // Keep the speaker out of the new recording.
+        self.assistant.stop_speaking();
+        if let Some(telemetry) = &self.telemetry {
+            let _ = telemetry.publish_recording(&RecordingEvent::Started);
+        }
+        Ok(())
+    }
+
+    fn stop_audio_recording(&self) {
+        self.app.borrow_mut().stop_recording();
+    }
+
+    /// "14:05" in the configured timezone, or nothing before the clock was synced.
+    fn message_time(&self, timestamp: u64) -> String {
+        if is_valid_unix(timestamp) {
+            self.timezone.to_local(timestamp as i64).time_label()
         } else {
-            info!("Audio recorder not available");
+            String::new()
+        }
+    }
+
+    /// Stop the reply that is currently streaming in or being spoken.
+    fn cancel_reply(&self) {
+        self.assistant.cancel();
+    }
+
+    /// Advance the recording state machine and copy its state into the UI.
+    fn update_recording_ui(&self, ui: &MainWindow) {
+        self.app.borrow_mut().poll_recording();
+        // Events may switch pages, which needs the app model, so it is not borrowed here.
+        let mut chat_changed = false;
+        for event in self.assistant.take_events() {
+            chat_changed |= self.chat_log.borrow_mut().apply(&event);
+            match event {
+                ChatEvent::Transcribing => {
+                    self.assistant_state.set(AssistantState::Transcribing);
+                    ui.set_chat_status("".into());
+                    self.navigate(ui, |app| app.open_page(Page::Chat));
+                }
+                ChatEvent::Thinking => {
+                    self.assistant_state.set(AssistantState::Thinking);
+                }
+                ChatEvent::Failed(error) => {
+                    ui.set_chat_status(error.into());
+                }
+                ChatEvent::Done => {
+                    self.assistant_state.set(AssistantState::Idle);
+                }
+                ChatEvent::Message(_) | ChatEvent::Token(_) | ChatEvent::Reply(_) => {}
+            }
+        }
+        if chat_changed {
+            let items: Vec<ChatMessage> = self
+                .chat_log
+                .borrow()
+                .messages()
+                .iter()
+                .map(|m| ChatMessage {
+                    from_user: m.role == Role::User,
+                    text: m.content.as_str().into(),
+                    time: self.message_time(m.timestamp).into(),
+                })
+                .collect();
+            ui.set_chat_messages(std::rc::Rc::new(slint::VecModel::from(items)).into());
+        }
+
+        let app = self.app.borrow();
+        let Some(recorder) = app.recorder() else {
+            return;
+        };
+        ui.set_assistant_state(if recorder.session().is_recording() {
+            AssistantState::Listening
+        } else if self.assistant.is_speaking() {
+            AssistantState::Speaking
+        } else {
+            self.assistant_state.get()
+        });
+
+        let session = recorder.session();
+        let now = std::time::Instant::now();
+        ui.set_recording(session.is_recording());
+        ui.set_recording_elapsed(session.elapsed(now).as_secs() as i32);
+        ui.set_recording_limit(session.max_duration().as_secs() as i32);
+        let levels = session.levels();
+        ui.set_audio_levels(AudioLevels {
+            rms: levels.rms,
+            peak: levels.peak,
+            clip_count: levels.clip_count as i32,
+        });
+        ui.set_waveform(std::rc::Rc::new(slint::VecModel::from(session.waveform())).into());
+    }
+
+    fn recordings_index(&self) -> Option<Arc<Mutex<RecordingIndex>>> {
+        self.app.borrow().recorder().and_then(|recorder| recorder.index().cloned())
+    }
+
+    /// Show the recordings on the SD card, newest first.
+    fn update_recordings_ui(&self, ui: &MainWindow) {
+        let Some(index) = self.recordings_index() else {
+            ui.set_recordings(std::rc::Rc::new(slint::VecModel::<RecordingSummary>::default()).into());
+            ui.set_recordings_usage("No SD card".into());
+            return;
+        };
+        let index = index.lock().unwrap();
+        let recordings: Vec<RecordingSummary> = index
+            .entries()
+            .iter()
+            .rev()
+            .map(|entry| RecordingSummary {
+                name: entry.file_name.as_str().into(),
+                duration: entry.duration_label().into(),
+                size: entry.size_label().into(),
+                format: format!("{:?}", entry.format).into(),
+                created: entry.created_label(&self.timezone).into(),
+                peak: entry.peak,
+                uploaded: entry.uploaded,
+            })
+            .collect();
+        ui.set_recordings_usage(
+            format!("{} files, {:.1} MB", recordings.len(), index.total_bytes() as f64 / (1024.0 * 1024.0)).into(),
+        );
+        ui.set_recordings(std::rc::Rc::new(slint::VecModel::from(recordings)).into());
+    }
+
+    /// Apply a navigation request and show the resulting page.
+    fn navigate(&self, ui: &MainWindow, change: impl FnOnce(&mut AppModel<CaptureRecorder>) -> bool) {
+        let changed = change(&mut self.app.borrow_mut());
+        if changed {
+            self.show_page(ui);
+        }
+    }
+
+    fn show_page(&self, ui: &MainWindow) {
+        let page = self.app.borrow().current_page();
+        info!("Showing {} page", page.title());
+        ui.set_current_page(app_page(page));
+        ui.set_page_index(page.index() as i32);
+        ui.set_page_count(Page::ALL.len() as i32);
+        match page {
+            Page::History => self.update_history_ui(ui),
+            Page::Recordings => self.update_recordings_ui(ui),
+            Page::Settings => self.update_settings_ui(ui),
+            Page::Diagnostics => self.update_diagnostics_ui(ui),
+            _ => {}
+        }
+    }
+
+    /// Fetch the weather for the configured location; a failure keeps the last reading.
+    fn refresh_weather(&self, ui: &MainWindow) {
+        match self.app.borrow_mut().refresh_weather() {
+            Ok(weather) => {
+                ui.set_weather(WeatherInfo {
+                    temperature: weather.temperature as f32,
+                    humidity: weather.humidity as f32,
+                    wind_speed: weather.wind_speed as f32,
+                });
+                if let Some(telemetry) = &self.telemetry {
+                    let _ = telemetry.publish_weather(weather);
+                }
+                info!("Weather updated");
+            }
+            Err(e) => info!("Weather fetch error: {:?}", e),
+        }
+        if self.app.borrow().current_page() == Page::History {
+            self.update_history_ui(ui);
+        }
+    }
+
+    fn update_history_ui(&self, ui: &MainWindow) {
+        let app = self.app.borrow();
+        let history = app.history();
+        let now = unix_now();
+        let series = |trend: Trend, unit: &str, decimals: usize| TrendSeries {
+            summary: match trend.stats {
+                Some(stats) => stats.label(unit, decimals).into(),
+                None => "No data".into(),
+            },
+            values: std::rc::Rc::new(slint::VecModel::from(trend.values)).into(),
+        };
+        ui.set_history_temperature(series(history.trend(now, HISTORY_HOURS, |a| a.temperature), "°C", 1));
+        ui.set_history_humidity(series(history.trend(now, HISTORY_HOURS, |a| a.humidity), "%", 0));
+        let status = if !is_valid_unix(now) {
+            "Waiting for the clock".to_string()
+        } else {
+            match history.path() {
+                Some(_) => format!("{} samples on SD card", history.len()),
+                None => format!("{} samples, no SD card", history.len()),
+            }
+        };
+        ui.set_history_status(status.into());
+    }
+
+    /// Read the indoor sensor; a failure keeps the last reading.
+    fn refresh_indoor(&self, ui: &MainWindow) {
+        match self.app.borrow_mut().refresh_indoor() {
+            Ok(reading) => ui.set_indoor(IndoorInfo {
+                available: true,
+                temperature: reading.temperature as f32,
+                has_humidity: reading.humidity.is_some(),
+                humidity: reading.humidity.unwrap_or_default() as f32,
+                has_pressure: reading.pressure.is_some(),
+                pressure: reading.pressure.unwrap_or_default() as f32,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => info!("Indoor sensor read error: {:?}", e),
+        }
+    }
+
+    fn update_settings_ui(&self, ui: &MainWindow) {
+        let app = self.app.borrow();
+        let settings = app.settings();
+        let items: Vec<SettingItem> = SettingField::ALL
+            .iter()
+            .map(|&field| SettingItem {
+                label: field.label().into(),
+                value: settings.value_label(field).into(),
+            })
+            .collect();
+        ui.set_settings(std::rc::Rc::new(slint::VecModel::from(items)).into());
+        ui.set_location(settings.location.name.as_str().into());
+        ui.set_weather_status(
+            format!("Auto-updating every {}", settings.value_label(SettingField::FetchInterval)).into(),
+        );
+    }
+
+    /// Step a setting, apply what can change at runtime and save the result.
+    fn adjust_setting(&self, ui: &MainWindow, field: SettingField, delta: i32) {
+        let result = self.app.borrow_mut().adjust_setting(field, delta);
+        let status = match result {
+            Ok(()) if field.needs_restart() => "Saved, restart to apply".to_string(),
+            Ok(()) => "Saved".to_string(),
+            Err(e) => {
+                info!("Failed to save settings: {:?}", e);
+                format!("Not saved: {}", e)
+            }
+        };
+        ui.set_settings_status(status.into());
+        self.update_settings_ui(ui);
+        if field == SettingField::Location {
+            self.refresh_weather(ui);
+        }
+    }
+
+    fn scan_wifi(&self, ui: &MainWindow) {
+        let networks: Vec<WifiNetwork> = self
+            .app
+            .borrow_mut()
+            .scan_wifi()
+            .iter()
+            .map(|network| WifiNetwork { ssid: network.ssid.as_str().into() })
+            .collect();
+        ui.set_wifi_status(format!("{} networks found", networks.len()).into());
+        ui.set_wifi_networks(std::rc::Rc::new(slint::VecModel::from(networks)).into());
+    }
+
+    /// Heap, uptime and peripheral state for the diagnostics page.
+    fn update_diagnostics_ui(&self, ui: &MainWindow) {
+        let (free_heap, min_free_heap, uptime_us) = unsafe {
+            (
+                esp_idf_svc::sys::esp_get_free_heap_size(),
+                esp_idf_svc::sys::esp_get_minimum_free_heap_size(),
+                esp_idf_svc::sys::esp_timer_get_time(),
+            )
+        };
+        let uptime = uptime_us / 1_000_000;
+        let wifi = match self.wifi.borrow().is_connected() {
+            Ok(true) => "Connected",
+            _ => "Disconnected",
+        };
+        let sd_card = match self.storage.borrow().as_ref() {
+            Some(storage) => match storage.info() {
+                Some(info) => info.label(),
+                None if storage.is_mounted() => "Mounted".to_string(),
+                None => "Not mounted".to_string(),
+            },
+            None => "No slot".to_string(),
+        };
+        let recordings = match self.recordings_index() {
+            Some(index) => index.lock().unwrap().entries().len().to_string(),
+            None => "-".to_string(),
+        };
+        let uploads = match self.uploader.as_ref().map(|uploader| uploader.status()) {
+            Some(status) => match status.last_error {
+                Some(e) if status.pending > 0 => format!("{} pending ({})", status.pending, e),
+                _ => format!("{} pending", status.pending),
+            },
+            None => "Off".to_string(),
+        };
+        let mqtt = match &self.telemetry {
+            Some(telemetry) if telemetry.is_connected() => "Connected",
+            Some(_) => "Disconnected",
+            None => "Off",
+        };
+        let items = vec![
+            ("Free heap", format!("{} KB", free_heap / 1024)),
+            ("Min free heap", format!("{} KB", min_free_heap / 1024)),
+            ("Uptime", format!("{}h {:02}m {:02}s", uptime / 3600, uptime / 60 % 60, uptime % 60)),
+            ("Wi-Fi", wifi.to_string()),
+            ("SD card", sd_card),
+            ("Recordings", recordings),
+            ("Weather history", format!("{} samples", self.app.borrow().history().len())),
+            ("Uploads", uploads),
+            ("MQTT", mqtt.to_string()),
+            ("Indoor sensor", self.app.borrow().sensor_name().unwrap_or("None").to_string()),
+        ];
+        let items: Vec<DiagnosticItem> = items
+            .into_iter()
+            .map(|(label, value)| DiagnosticItem { label: label.into(), value: value.into() })
+            .collect();
+        ui.set_diagnostics(std::rc::Rc::new(slint::VecModel::from(items)).into());
+    }
+
+    /// Follow the SD card being pulled or put back.
+    fn poll_storage(&self, ui: &MainWindow) {
+        let event = match self.storage.borrow_mut().as_mut() {
+            Some(storage) => match storage.poll(std::time::Instant::now()) {
+                Some(StorageEvent::Mounted(_)) => {
+                    attach_history(&mut self.app.borrow_mut(), storage);
+                    let index = open_recordings_index(storage, &self.bus_lock);
+                    if let Some(index) = &index {
+                        let retention = card_retention(storage, index);
+                        if let Some(recorder) = self.app.borrow_mut().recorder_mut() {
+                            recorder.set_retention(retention);
+                        }
+                    }
+                    Some(index)
+                }
+                Some(StorageEvent::Removed) => {
+                    self.app.borrow_mut().history_mut().detach();
+                    Some(None)
+                }
+                None => None,
+            },
+            None => None,
+        };
+        let Some(index) = event else {
+            return;
+        };
+        if let Some(recorder) = self.app.borrow_mut().recorder_mut() {
+            recorder.set_index(index);
+        }
+        if let Some(uploader) = &self.uploader {
+            uploader.set_index(self.recordings_index());
+        }
+        match self.app.borrow().current_page() {
+            Page::History => self.update_history_ui(ui),
+            Page::Recordings => self.update_recordings_ui(ui),
+            Page::Diagnostics => self.update_diagnostics_ui(ui),
+            _ => {}
+        }
+    }
+
+    /// Let uploads run while Wi-Fi is up.
+    fn poll_uploads(&self) {
+        if let Some(uploader) = &self.uploader {
+            uploader.set_online(matches!(self.wifi.borrow().is_connected(), Ok(true)));
+        }
+    }
+
+    /// Run commands from MQTT, announce the current state after a reconnect and publish
+    /// health periodically.
+    fn poll_telemetry(&self) {
+        let Some(telemetry) = &self.telemetry else {
+            return;
+        };
+        let was_connected = telemetry.is_connected();
+        for command in telemetry.poll() {
+            match command {
+                Command::Record => {
+                    if let Err(e) = self.start_audio_recording() {
+                        info!("Audio recording failed: {:?}", e);
+                    }
+                }
+                Command::Brightness(percent) => self.set_brightness(percent),
+            }
+        }
+        if !telemetry.is_connected() {
+            return;
+        }
+        if !was_connected {
+            if let Some(weather) = self.app.borrow().weather() {
+                let _ = telemetry.publish_weather(weather);
+            }
+            if let Some(backlight) = self.backlight.borrow().as_ref() {
+                let _ = telemetry.publish_brightness(backlight.brightness());
+            }
+        }
+        let now = std::time::Instant::now();
+        let due = match self.last_health.get() {
+            Some(last) => now.duration_since(last) >= HEALTH_INTERVAL,
+            None => true,
+        };
+        if (!was_connected || due) && telemetry.publish_health(&mqtt::device_health()).is_ok() {
+            self.last_health.set(Some(now));
+        }
+    }
+
+    fn set_brightness(&self, percent: u8) {
+        let mut backlight = self.backlight.borrow_mut();
+        let Some(backlight) = backlight.as_mut() else {
+            info!("Ignoring brightness {}%: no backlight pin on this board", percent);
+            return;
+        };
+        if let Err(e) = backlight.set_brightness(percent) {
+            info!("Failed to set brightness: {:?}", e);
+            return;
+        }
+        if let Some(telemetry) = &self.telemetry {
+            let _ = telemetry.publish_brightness(backlight.brightness());
+        }
+    }
+
+    fn delete_recording(&self, file_name: &str) -> anyhow::Result<()> {
+        if let Some(index) = self.recordings_index() {
+            let _bus = self.bus_lock.lock().unwrap();
+            if index.lock().unwrap().remove(file_name)? {
+                info!("Deleted recording {}", file_name);
+            }
         }
         Ok(())
     }
 }
 
-fn fetch_weather_simple() -> Result<(f64, f64, f64), Box<dyn std::error::Error>> {
-    info!("Fetching weather data...");
-    
-    let config = HttpConfig {
-        crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
-        ..Default::default()
-    };
-    
-    let connection = EspHttpConnection::new(&config)?;
-    let mut client = Client::wrap(connection);
+fn app_page(page: Page) -> AppPage {
+    match page {
+        Page::Weather => AppPage::Weather,
+        Page::Forecast => AppPage::Forecast,
+        Page::History => AppPage::History,
+        Page::Chat => AppPage::Chat,
+        Page::Recordings => AppPage::Recordings,
+        Page::Wifi => AppPage::Wifi,
+        Page::Settings => AppPage::Settings,
+        Page::Diagnostics => AppPage::Diagnostics,
+    }
+}
 
-    let url = "http://api.open-meteo.com/v1/forecast?latitude=43.45&longitude=-80.49&current=temperature_2m,relative_humidity_2m,wind_speed_10m";
-    
-    info!("Making http request...");
-    let request = client.get(url)?;
-    let mut response = request.submit()?;
-    
-    let status = response.status();
-    info!("Response status: {}", status);
-    
-    if status != 200 {
-        return Err(format!("HTTP error: {}", status).into());
+fn model_page(page: AppPage) -> Page {
+    match page {
+        AppPage::Weather => Page::Weather,
+        AppPage::Forecast => Page::Forecast,
+        AppPage::History => Page::History,
+        AppPage::Chat => Page::Chat,
+        AppPage::Recordings => Page::Recordings,
+        AppPage::Wifi => Page::Wifi,
+        AppPage::Settings => Page::Settings,
+        AppPage::Diagnostics => Page::Diagnostics,
     }
-    
-    let mut buf = Vec::new();
-    let mut temp_buf = [0u8; 256];
-    
-    loop {
-        match response.read(&mut temp_buf) {
-            Ok(0) => break,
-            Ok(n) => buf.extend_from_slice(&temp_buf[..n]),
-            Err(e) => return Err(Box::new(e)),
+}
+
+/// Lists access points through the station interface.
+struct EspWifiScanner(std::rc::Rc<std::cell::RefCell<Wifi>>);
+
+impl WifiNetworkProvider for EspWifiScanner {
+    fn scan_wifi_networks(&self) -> Vec<slint_workshop_model::WifiNetwork> {
+        match self.0.borrow_mut().scan() {
+            Ok(access_points) => access_points
+                .iter()
+                .map(|ap| slint_workshop_model::WifiNetwork { ssid: ap.ssid.to_string() })
+                .collect(),
+            Err(e) => {
+                info!("Wi-Fi scan failed: {:?}", e);
+                Vec::new()
+            }
         }
     }
-    
-    let response_str = String::from_utf8_lossy(&buf);
-    info!("Response: {}", response_str);
-    
-    use serde_json::Value;
-    let v: Value = serde_json::from_str(&response_str)?;
-    
-    let temp = v["current"]["temperature_2m"].as_f64().ok_or("Missing temperature")?;
-    let humidity = v["current"]["relative_humidity_2m"].as_f64().ok_or("Missing humidity")?;
-    let wind = v["current"]["wind_speed_10m"].as_f64().ok_or("Missing wind speed")?;
-    
-    info!("Weather: {}°C, {}%, {} m/s", temp, humidity, wind);
-    
-    Ok((temp, humidity, wind))
 }
 
 struct App {
     ui: MainWindow,
     model: Model,
+    buttons: board::Buttons,
 }
 
 impl App {
-    fn new(wifi: std::rc::Rc<std::cell::RefCell<Wifi>>) -> anyhow::Result<Self> {
+    fn new(
+        wifi: std::rc::Rc<std::cell::RefCell<Wifi>>,
+        settings: Settings,
+        settings_store: Option<NvsSettingsStore>,
+        sd_card: Option<storage::SdStorage>,
+        bus_lock: board::BusLock,
+        buttons: board::Buttons,
+        backlight: Option<board::Backlight>,
+        sensor_i2c: Option<esp_idf_svc::hal::i2c::I2cDriver<'static>>,
+    ) -> anyhow::Result<Self> {
         let ui = MainWindow::new().map_err(|e| anyhow::anyhow!(e))?;
         
-        let audio_recorder = match AudioRecorder::new() {
-            Ok(recorder) => Some(recorder),
+        let settings_store = settings_store.map(|store| Box::new(store) as Box<dyn SettingsStore>);
+        let mut app = AppModel::new(Box::new(OpenMeteo::new(http::EspHttpClient::default())), Box::new(EspWifiScanner(wifi.clone())))
+            .with_settings(settings, settings_store)
+            .with_history(History::default().with_storage_lock(bus_lock.clone()));
+        let assistant = Arc::new(assistant::new_assistant(board::PROFILE.speaker.as_ref()));
+        let mut storage = sd_card.map(|card| StorageManager::new(card, STORAGE_RETRY_INTERVAL));
+        let mut retention = RETENTION_POLICY;
+        let index = storage.as_mut().and_then(|storage| {
+            storage.poll(std::time::Instant::now())?;
+            attach_history(&mut app, storage);
+            let index = open_recordings_index(storage, &bus_lock)?;
+            retention = card_retention(storage, &index);
+            Some(index)
+        });
+        let uploader = upload::new_uploader(bus_lock.clone()).map(Arc::new);
+        let telemetry = mqtt::new_telemetry().map(Arc::new);
+        match new_recorder(index, retention, bus_lock.clone(), assistant.clone(), uploader.clone(), telemetry.clone()) {
+            Ok(recorder) => app = app.with_recorder(recorder),
             Err(e) => {
                 info!("Failed to initialize audio recorder: {:?}", e);
-                None
             }
-        };
+        }
+        if let Some(sensor) = sensor::new_sensor(sensor_i2c) {
+            app = app.with_sensor(sensor);
+        }
+        if let Some(uploader) = &uploader {
+            uploader.set_index(app.recorder().and_then(|recorder| recorder.index().cloned()));
+        }
         
         let model = Model { 
             wifi,
-            audio_recorder: std::rc::Rc::new(std::cell::RefCell::new(audio_recorder)),
+            app: std::cell::RefCell::new(app),
+            sntp: std::cell::RefCell::new(None),
+            assistant,
+            storage: std::cell::RefCell::new(storage),
+            bus_lock,
+            uploader,
+            telemetry,
+            last_health: std::cell::Cell::new(None),
+            backlight: std::cell::RefCell::new(backlight),
+            chat_log: std::cell::RefCell::new(ChatLog::new(MAX_CHAT_MESSAGES)),
+            assistant_state: std::cell::Cell::new(AssistantState::Idle),
+            timezone: PosixTz::parse(TIMEZONE).unwrap_or_else(|e| {
+                info!("Invalid timezone {:?}: {}, using UTC", TIMEZONE, e);
+                PosixTz::utc()
+            }),
         };
         
-        Ok(Self { ui, model })
+        Ok(Self { ui, model, buttons })
     }
 
     fn run(self) -> anyhow::Result<()> {
@@ -372,54 +772,40 @@ impl App {
         };
         
         if wifi_connected {
-            match fetch_weather_simple() {
-                Ok((temp, humidity, wind)) => {
-                    let weather_info = WeatherInfo {
-                        temperature: temp as f32,
-                        humidity: humidity as f32,
-                        wind_speed: wind as f32,
-                    };
-                    self.ui.set_weather(weather_info);
-                    info!("Initial weather data loaded");
-                }
-                Err(e) => {
-                    info!("Initial weather fetch failed: {:?}", e);
-                }
+            if let Err(e) = model_rc.start_sntp() {
+                info!("SNTP start failed: {:?}", e);
             }
+
+            model_rc.refresh_weather(&self.ui);
         }
+        model_rc.refresh_indoor(&self.ui);
         
+        let model_weather = model_rc.clone();
         let ui_weak_weather = ui_weak.clone();
-        let weather_timer = slint::Timer::default();
+        let weather_timer = std::rc::Rc::new(slint::Timer::default());
         
         weather_timer.start(
             slint::TimerMode::Repeated,
-            std::time::Duration::from_secs(30),
+            model_rc.app.borrow().settings().fetch_interval(),
             move || {
                 info!("Timer triggered - fetching weather...");
-                match fetch_weather_simple() {
-                    Ok((temp, humidity, wind)) => {
-                        let weather_info = WeatherInfo {
-                            temperature: temp as f32,
-                            humidity: humidity as f32,
-                            wind_speed: wind as f32,
-                        };
-                        ui_weak_weather.unwrap().set_weather(weather_info);
-                        info!("Weather updated via timer");
-                    }
-                    Err(e) => {
-                        info!("Weather fetch error: {:?}", e);
-                    }
+                if let Some(ui) = ui_weak_weather.upgrade() {
+                    model_weather.refresh_weather(&ui);
                 }
             },
         );
         
         let model_audio = model_rc.clone();
-        let audio_timer = slint::Timer::default();
+        let audio_timer = std::rc::Rc::new(slint::Timer::default());
         
         audio_timer.start(
             slint::TimerMode::Repeated,
-            std::time::Duration::from_secs(60),
+            // Keeps ticking while automatic recordings are off so they can be turned back on.
+            model_rc.app.borrow().settings().recording_interval().unwrap_or(std::time::Duration::from_secs(60)),
             move || {
+                if model_audio.app.borrow().settings().recording_interval().is_none() {
+                    return;
+                }
                 info!("Audio timer triggered - starting recording...");
                 if let Err(e) = model_audio.start_audio_recording() {
                     info!("Audio recording failed: {:?}", e);
@@ -427,6 +813,172 @@ impl App {
             },
         );
         
+        let model_start = model_rc.clone();
+        self.ui.on_start_recording(move || {
+            if let Err(e) = model_start.start_audio_recording() {
+                info!("Audio recording failed: {:?}", e);
+            }
+        });
+
+        let model_stop = model_rc.clone();
+        self.ui.on_stop_recording(move || model_stop.stop_audio_recording());
+
+        let model_cancel = model_rc.clone();
+        self.ui.on_cancel_reply(move || model_cancel.cancel_reply());
+
+        let model_clock = model_rc.clone();
+        let ui_weak_clock = ui_weak.clone();
+        let clock_timer = slint::Timer::default();
+        clock_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_secs(1),
+            move || {
+                if let Some(ui) = ui_weak_clock.upgrade() {
+                    model_clock.update_clock_ui(&ui);
+                    if model_clock.app.borrow().current_page() == Page::Diagnostics {
+                        model_clock.update_diagnostics_ui(&ui);
+                    }
+                }
+            },
+        );
+
+        model_rc.update_settings_ui(&self.ui);
+        model_rc.show_page(&self.ui);
+
+        let model_settings = model_rc.clone();
+        let ui_weak_settings = ui_weak.clone();
+        let weather_timer_settings = weather_timer.clone();
+        let audio_timer_settings = audio_timer.clone();
+        self.ui.on_adjust_setting(move |index, delta| {
+            let (Some(ui), Some(field)) = (ui_weak_settings.upgrade(), SettingField::from_index(index as usize)) else {
+                return;
+            };
+            model_settings.adjust_setting(&ui, field, delta);
+            let app = model_settings.app.borrow();
+            let settings = app.settings();
+            if weather_timer_settings.interval() != settings.fetch_interval() {
+                weather_timer_settings.set_interval(settings.fetch_interval());
+            }
+            if let Some(interval) = settings.recording_interval() {
+                if audio_timer_settings.interval() != interval {
+                    audio_timer_settings.set_interval(interval);
+                }
+            }
+        });
+
+        let model_navigate = model_rc.clone();
+        let ui_weak_navigate = ui_weak.clone();
+        self.ui.on_navigate(move |delta| {
+            if let Some(ui) = ui_weak_navigate.upgrade() {
+                model_navigate.navigate(&ui, |app| app.navigate(delta));
+            }
+        });
+
+        let model_open = model_rc.clone();
+        let ui_weak_open = ui_weak.clone();
+        self.ui.on_open_page(move |page| {
+            if let Some(ui) = ui_weak_open.upgrade() {
+                model_open.navigate(&ui, |app| app.open_page(model_page(page)));
+            }
+        });
+
+        let model_back = model_rc.clone();
+        let ui_weak_back = ui_weak.clone();
+        self.ui.on_navigate_back(move || {
+            if let Some(ui) = ui_weak_back.upgrade() {
+                model_back.navigate(&ui, |app| app.navigate_back());
+            }
+        });
+
+        let model_scan = model_rc.clone();
+        let ui_weak_scan = ui_weak.clone();
+        self.ui.on_scan_wifi(move || {
+            if let Some(ui) = ui_weak_scan.upgrade() {
+                model_scan.scan_wifi(&ui);
+            }
+        });
+
+        let model_delete = model_rc.clone();
+        let ui_weak_delete = ui_weak.clone();
+        self.ui.on_delete_recording(move |name| {
+            if let Err(e) = model_delete.delete_recording(&name) {
+                info!("Failed to delete {}: {:?}", name, e);
+            }
+            if let Some(ui) = ui_weak_delete.upgrade() {
+                model_delete.update_recordings_ui(&ui);
+            }
+        });
+
+        let model_recording_ui = model_rc.clone();
+        let ui_weak_recording = ui_weak.clone();
+        let recording_ui_timer = slint::Timer::default();
+        recording_ui_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_millis(100),
+            move || {
+                if let Some(ui) = ui_weak_recording.upgrade() {
+                    model_recording_ui.update_recording_ui(&ui);
+                }
+            },
+        );
+
+        let model_storage = model_rc.clone();
+        let ui_weak_storage = ui_weak.clone();
+        let storage_timer = slint::Timer::default();
+        storage_timer.start(slint::TimerMode::Repeated, STORAGE_POLL_INTERVAL, move || {
+            if let Some(ui) = ui_weak_storage.upgrade() {
+                model_storage.poll_storage(&ui);
+            }
+            model_storage.poll_uploads();
+        });
+
+        let model_indoor = model_rc.clone();
+        let ui_weak_indoor = ui_weak.clone();
+        let indoor_timer = slint::Timer::default();
+        if model_rc.app.borrow().sensor_name().is_some() {
+            indoor_timer.start(slint::TimerMode::Repeated, SENSOR_INTERVAL, move || {
+                if let Some(ui) = ui_weak_indoor.upgrade() {
+                    model_indoor.refresh_indoor(&ui);
+                }
+            });
+        }
+
+        let model_telemetry = model_rc.clone();
+        let telemetry_timer = slint::Timer::default();
+        telemetry_timer.start(slint::TimerMode::Repeated, std::time::Duration::from_millis(500), move || {
+            model_telemetry.poll_telemetry();
+        });
+
+        let button_timer = slint::Timer::default();
+        if !self.buttons.is_empty() {
+            let model_buttons = model_rc.clone();
+            let ui_weak_buttons = ui_weak.clone();
+            let mut buttons = self.buttons;
+            button_timer.start(
+                slint::TimerMode::Repeated,
+                std::time::Duration::from_millis(50),
+                move || {
+                    let Some(ui) = ui_weak_buttons.upgrade() else {
+                        return;
+                    };
+                    for action in buttons.poll() {
+                        match action {
+                            ButtonAction::Record if model_buttons.app.borrow().is_recording() => {
+                                model_buttons.stop_audio_recording();
+                            }
+                            ButtonAction::Record => {
+                                if let Err(e) = model_buttons.start_audio_recording() {
+                                    info!("Audio recording failed: {:?}", e);
+                                }
+                            }
+                            ButtonAction::NextPage => model_buttons.navigate(&ui, |app| app.navigate(1)),
+                            ButtonAction::PreviousPage => model_buttons.navigate(&ui, |app| app.navigate(-1)),
+                        }
+                    }
+                },
+            );
+        }
+
         let model_first_audio = model_rc.clone();
         let first_audio_timer = slint::Timer::default();
         first_audio_timer.start(
@@ -450,14 +1002,34 @@ fn main() -> anyhow::Result<()> {
 
     info!("Starting Slint Workshop ESP with ST7789 display and audio recording");
 
-    let platform = esp32::EspPlatform::new();
+    // Settings are needed before the display comes up, for its SPI clock.
+    let nvs = esp_idf_svc::nvs::EspDefaultNvsPartition::take()?;
+    let settings_store = match NvsSettingsStore::new(nvs.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            info!("Failed to open settings storage: {:?}", e);
+            None
+        }
+    };
+    let settings = match settings_store.as_ref().map(|store| Settings::load(store)) {
+        Some(Ok(settings)) => settings,
+        Some(Err(e)) => {
+            info!("Failed to load settings, using defaults: {:?}", e);
+            Settings::default()
+        }
+        None => Settings::default(),
+    };
+    info!("Settings: {:?}", settings);
+
+    let board = board::Board::take(board::PROFILE, settings.spi_clock_hz)?;
+    let platform = esp32::EspPlatform::new(board.display, board.modem, nvs, board.bus_lock.clone());
     let wifi = platform.wifi.clone();
 
     slint::platform::set_platform(platform).unwrap();
 
     info!("Platform initialized, creating app");
 
-    let app = App::new(wifi)?;
+    let app = App::new(wifi, settings, settings_store, board.sd_card, board.bus_lock, board.buttons, board.backlight, board.sensor_i2c)?;
 
     info!("App created, starting main loop with Slint UI and audio recording");
 
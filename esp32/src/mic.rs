@@ -0,0 +1,101 @@
+//! I2S microphone input on I2S1, e.g. an INMP441 or the T-Camera Plus MEMS microphone.
+//!
+//! Codec microphones like the ES7210 on the ESP32-S3-BOX-3 are not supported yet.
+
+use std::io;
+
+use esp_idf_svc::sys::{self, configTICK_RATE_HZ};
+use log::info;
+use slint_workshop_model::board::MicProfile;
+use slint_workshop_model::capture::AudioSource;
+
+const PORT: sys::i2s_port_t = sys::i2s_port_t_I2S_NUM_1;
+/// How long a read waits for the DMA before reporting a timeout.
+const READ_TIMEOUT_MS: u32 = 1000;
+
+/// Handle to the installed driver; the driver stays installed for the life of the firmware.
+#[derive(Debug, Clone, Copy)]
+pub struct I2sMic {
+    sample_rate: u32,
+}
+
+impl I2sMic {
+    pub fn install(sample_rate: u32, profile: &MicProfile) -> io::Result<Self> {
+        if !profile.kind.is_supported() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} microphones are not supported", profile.kind),
+            ));
+        }
+        info!("Initializing I2S for microphone...");
+
+        unsafe {
+            let mut i2s_config: sys::i2s_config_t = std::mem::zeroed();
+            i2s_config.mode = sys::i2s_mode_t_I2S_MODE_MASTER | sys::i2s_mode_t_I2S_MODE_RX;
+            i2s_config.sample_rate = sample_rate;
+            i2s_config.bits_per_sample = sys::i2s_bits_per_sample_t_I2S_BITS_PER_SAMPLE_16BIT;
+            i2s_config.channel_format = sys::i2s_channel_fmt_t_I2S_CHANNEL_FMT_ONLY_LEFT;
+            i2s_config.communication_format = sys::i2s_comm_format_t_I2S_COMM_FORMAT_STAND_I2S;
+            i2s_config.intr_alloc_flags = 0;
+            i2s_config.__bindgen_anon_1.dma_buf_count = 8;
+            i2s_config.__bindgen_anon_1.dma_buf_len = 1024;
+            i2s_config.use_apll = false;
+            i2s_config.tx_desc_auto_clear = false;
+            i2s_config.fixed_mclk = 0;
+            i2s_config.mclk_multiple = sys::i2s_mclk_multiple_t_I2S_MCLK_MULTIPLE_256;
+            i2s_config.bits_per_chan = sys::i2s_bits_per_chan_t_I2S_BITS_PER_CHAN_DEFAULT;
+
+            let ret = sys::i2s_driver_install(PORT, &i2s_config, 0, std::ptr::null_mut());
+            if ret != sys::ESP_OK {
+                return Err(io::Error::other(format!("I2S driver install failed: {}", ret)));
+            }
+
+            let pin_config = sys::i2s_pin_config_t {
+                bck_io_num: profile.i2s.bclk as i32,
+                ws_io_num: profile.i2s.ws as i32,
+                data_out_num: sys::I2S_PIN_NO_CHANGE,
+                data_in_num: profile.data_in as i32,
+                mck_io_num: profile.i2s.mclk.map_or(sys::I2S_PIN_NO_CHANGE, i32::from),
+            };
+            let ret = sys::i2s_set_pin(PORT, &pin_config);
+            if ret != sys::ESP_OK {
+                info!("I2S pin config failed: {}", ret);
+                info!("Continuing despite pin config failure...");
+            }
+        }
+
+        info!("I2S initialized successfully");
+        Ok(Self { sample_rate })
+    }
+}
+
+impl AudioSource for I2sMic {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, buf: &mut [i16]) -> io::Result<usize> {
+        let mut bytes_read = 0;
+        let ret = unsafe {
+            sys::i2s_read(
+                PORT,
+                buf.as_mut_ptr() as *mut std::ffi::c_void,
+                std::mem::size_of_val(buf),
+                &mut bytes_read,
+                READ_TIMEOUT_MS * configTICK_RATE_HZ / 1000,
+            )
+        };
+        // Let the UI task run between DMA buffers.
+        esp_idf_svc::hal::task::do_yield();
+
+        if ret == sys::ESP_OK && bytes_read > 0 {
+            // The ESP32 is little endian, so the DMA bytes already are `i16` samples.
+            Ok(bytes_read / 2)
+        } else if ret == sys::ESP_OK || ret == sys::ESP_ERR_TIMEOUT as sys::esp_err_t {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            Err(io::Error::new(io::ErrorKind::TimedOut, "I2S read timed out"))
+        } else {
+            Err(io::Error::other(format!("I2S read failed: {}", ret)))
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Telemetry over ESP-IDF's MQTT client, to the broker set at build time with `MQTT_URL`.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, QoS};
+use slint_workshop_model::mqtt::{MqttClient, MqttEvent};
+use slint_workshop_model::telemetry::{DeviceHealth, Telemetry, TelemetryConfig};
+
+/// `mqtt://host:port` or `mqtts://host:port`; without it there is no telemetry.
+const MQTT_URL: Option<&str> = option_env!("MQTT_URL");
+const MQTT_USERNAME: Option<&str> = option_env!("MQTT_USERNAME");
+const MQTT_PASSWORD: Option<&str> = option_env!("MQTT_PASSWORD");
+/// Defaults to `esp32-` and the last three bytes of the MAC address.
+const MQTT_DEVICE_ID: Option<&str> = option_env!("MQTT_DEVICE_ID");
+
+pub type EspTelemetry = Telemetry<EspMqtt>;
+
+/// `EspMqttClient` behind the model's [`MqttClient`]; ESP-IDF reconnects by itself.
+pub struct EspMqtt {
+    client: Mutex<EspMqttClient<'static>>,
+    connected: Arc<AtomicBool>,
+    events: Arc<Mutex<Vec<MqttEvent>>>,
+}
+
+impl MqttClient for EspMqtt {
+    fn publish(&self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "not connected to the MQTT broker"));
+        }
+        // Queued for the MQTT task rather than written from the UI thread.
+        self.client.lock().unwrap().enqueue(topic, QoS::AtMostOnce, retain, payload).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn subscribe(&self, topic: &str) -> io::Result<()> {
+        self.client.lock().unwrap().subscribe(topic, QoS::AtMostOnce).map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn take_events(&self) -> Vec<MqttEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+fn device_id() -> String {
+    if let Some(id) = MQTT_DEVICE_ID {
+        return id.to_string();
+    }
+    let mut mac = [0u8; 6];
+    unsafe {
+        esp_idf_svc::sys::esp_efuse_mac_get_default(mac.as_mut_ptr());
+    }
+    format!("esp32-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5])
+}
+
+/// Connect to the broker, or `None` if none was configured.
+pub fn new_telemetry() -> Option<EspTelemetry> {
+    let url = MQTT_URL?;
+    let mut config = TelemetryConfig::new(device_id());
+    config.model = crate::board::PROFILE.name.to_string();
+    let will = config.will();
+
+    let connected = Arc::new(AtomicBool::new(false));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mqtt_config = MqttClientConfiguration {
+        client_id: Some(&config.device_id),
+        username: MQTT_USERNAME,
+        password: MQTT_PASSWORD,
+        lwt: Some(LwtConfiguration {
+            topic: &will.topic,
+            payload: &will.payload,
+            qos: QoS::AtMostOnce,
+            retain: will.retain,
+        }),
+        ..Default::default()
+    };
+    let client = EspMqttClient::new_cb(url, &mqtt_config, {
+        let connected = connected.clone();
+        let events = events.clone();
+        move |event| {
+            let event = match event.payload() {
+                EventPayload::Connected(_) => {
+                    connected.store(true, Ordering::Relaxed);
+                    MqttEvent::Connected
+                }
+                EventPayload::Disconnected => {
+                    connected.store(false, Ordering::Relaxed);
+                    MqttEvent::Disconnected
+                }
+                EventPayload::Received { topic: Some(topic), data, .. } => {
+                    MqttEvent::Message { topic: topic.to_string(), payload: data.to_vec() }
+                }
+                EventPayload::Error(e) => {
+                    log::info!("MQTT error: {:?}", e);
+                    return;
+                }
+                _ => return,
+            };
+            events.lock().unwrap().push(event);
+        }
+    });
+    match client {
+        Ok(client) => {
+            log::info!("Publishing telemetry to {} as {}", url, config.base_topic);
+            Some(Telemetry::new(EspMqtt { client: Mutex::new(client), connected, events }, config))
+        }
+        Err(e) => {
+            log::info!("Failed to start MQTT: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Signal strength, heap and PSRAM as reported by ESP-IDF.
+pub fn device_health() -> DeviceHealth {
+    use esp_idf_svc::sys::*;
+
+    let mut ap = wifi_ap_record_t::default();
+    let rssi = (unsafe { esp_wifi_sta_get_ap_info(&mut ap) } == ESP_OK).then_some(ap.rssi);
+    let (free_heap, min_free_heap, psram_size, free_psram, uptime_us) = unsafe {
+        (
+            esp_get_free_heap_size(),
+            esp_get_minimum_free_heap_size(),
+            heap_caps_get_total_size(MALLOC_CAP_SPIRAM),
+            heap_caps_get_free_size(MALLOC_CAP_SPIRAM),
+            esp_timer_get_time(),
+        )
+    };
+    DeviceHealth {
+        rssi,
+        free_heap: Some(free_heap as u64),
+        min_free_heap: Some(min_free_heap as u64),
+        free_psram: (psram_size > 0).then_some(free_psram as u64),
+        uptime: (uptime_us / 1_000_000) as u64,
+    }
+}
@@ -0,0 +1,43 @@
+//! Settings stored as a JSON blob in the default NVS partition.
+
+use std::io;
+use std::sync::Mutex;
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use slint_workshop_model::settings::SettingsStore;
+
+const NAMESPACE: &str = "app";
+const SETTINGS_KEY: &str = "settings";
+
+pub struct NvsSettingsStore {
+    nvs: Mutex<EspNvs<NvsDefault>>,
+}
+
+impl NvsSettingsStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> anyhow::Result<Self> {
+        Ok(Self {
+            nvs: Mutex::new(EspNvs::new(partition, NAMESPACE, true)?),
+        })
+    }
+}
+
+impl SettingsStore for NvsSettingsStore {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        let nvs = self.nvs.lock().unwrap();
+        let Some(len) = nvs.blob_len(SETTINGS_KEY).map_err(io::Error::other)? else {
+            return Ok(None);
+        };
+        let mut buf = vec![0; len];
+        let data = nvs.get_blob(SETTINGS_KEY, &mut buf).map_err(io::Error::other)?;
+        Ok(data.map(<[u8]>::to_vec))
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        // NVS commits the whole blob or keeps the old one, so no temp key is needed.
+        self.nvs
+            .lock()
+            .unwrap()
+            .set_blob(SETTINGS_KEY, data)
+            .map_err(io::Error::other)
+    }
+}
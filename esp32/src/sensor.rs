@@ -0,0 +1,48 @@
+//! The indoor sensor on the board's sensor I2C bus, read through the model's drivers.
+
+use std::io;
+
+use esp_idf_svc::hal::delay::TickType;
+use esp_idf_svc::hal::i2c::I2cDriver;
+use log::info;
+use slint_workshop_model::sensors::{self, I2cBus, SensorSource};
+
+/// Far longer than any transfer of the supported sensors at 100 kHz.
+const TIMEOUT_MS: u64 = 50;
+
+/// `I2cDriver` behind the model's [`I2cBus`].
+pub struct EspI2c(I2cDriver<'static>);
+
+impl EspI2c {
+    fn timeout() -> u32 {
+        TickType::new_millis(TIMEOUT_MS).ticks()
+    }
+}
+
+impl I2cBus for EspI2c {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()> {
+        self.0.write(address, bytes, Self::timeout()).map_err(io::Error::other)
+    }
+
+    fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+        self.0.read(address, buf, Self::timeout()).map_err(io::Error::other)
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> io::Result<()> {
+        self.0.write_read(address, bytes, buf, Self::timeout()).map_err(io::Error::other)
+    }
+}
+
+/// Probe the bus for a BME280, SHT3x or AHT20; `None` without a bus or a sensor.
+pub fn new_sensor(i2c: Option<I2cDriver<'static>>) -> Option<Box<dyn SensorSource + Send>> {
+    match sensors::detect(EspI2c(i2c?)) {
+        Ok(sensor) => {
+            info!("Indoor sensor: {}", sensor.name());
+            Some(sensor)
+        }
+        Err(e) => {
+            info!("No indoor sensor: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! I2S output to a MAX98357-style class D amplifier.
+//!
+//! The microphone owns I2S1 as RX, so the speaker uses I2S0 as TX. I2S0 also drives the
+//! camera interface on the T-Camera boards; the camera is not used by this firmware.
+//! Codecs like the ES8311 on the ESP32-S3-BOX-3 are not supported yet.
+
+use std::io;
+
+use esp_idf_svc::hal::gpio::{AnyOutputPin, Output, PinDriver};
+use esp_idf_svc::sys;
+use slint_workshop_model::board::SpeakerProfile;
+use slint_workshop_model::playback::AudioSink;
+
+const PORT: sys::i2s_port_t = sys::i2s_port_t_I2S_NUM_0;
+const DEFAULT_SAMPLE_RATE: u32 = 24000;
+
+fn esp_check(ret: sys::esp_err_t, what: &str) -> io::Result<()> {
+    if ret == sys::ESP_OK {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed: {}", what, ret)))
+    }
+}
+
+pub struct I2sSpeaker {
+    sample_rate: u32,
+    /// Holds the amplifier on.
+    _enable: Option<PinDriver<'static, AnyOutputPin, Output>>,
+}
+
+impl I2sSpeaker {
+    pub fn new(profile: &SpeakerProfile) -> io::Result<Self> {
+        if !profile.kind.is_supported() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} speakers are not supported", profile.kind),
+            ));
+        }
+        unsafe {
+            let mut i2s_config: sys::i2s_config_t = std::mem::zeroed();
+            i2s_config.mode = sys::i2s_mode_t_I2S_MODE_MASTER | sys::i2s_mode_t_I2S_MODE_TX;
+            i2s_config.sample_rate = DEFAULT_SAMPLE_RATE;
+            i2s_config.bits_per_sample = sys::i2s_bits_per_sample_t_I2S_BITS_PER_SAMPLE_16BIT;
+            // The MAX98357 plays the left channel with SD_MODE pulled high.
+            i2s_config.channel_format = sys::i2s_channel_fmt_t_I2S_CHANNEL_FMT_ONLY_LEFT;
+            i2s_config.communication_format = sys::i2s_comm_format_t_I2S_COMM_FORMAT_STAND_I2S;
+            i2s_config.__bindgen_anon_1.dma_buf_count = 6;
+            i2s_config.__bindgen_anon_1.dma_buf_len = 512;
+            // Output silence instead of repeating the last buffer when starved.
+            i2s_config.tx_desc_auto_clear = true;
+            i2s_config.mclk_multiple = sys::i2s_mclk_multiple_t_I2S_MCLK_MULTIPLE_256;
+            i2s_config.bits_per_chan = sys::i2s_bits_per_chan_t_I2S_BITS_PER_CHAN_DEFAULT;
+
+            esp_check(
+                sys::i2s_driver_install(PORT, &i2s_config, 0, std::ptr::null_mut()),
+                "I2S TX driver install",
+            )?;
+
+            let pin_config = sys::i2s_pin_config_t {
+                bck_io_num: profile.i2s.bclk as i32,
+                ws_io_num: profile.i2s.ws as i32,
+                data_out_num: profile.data_out as i32,
+                data_in_num: sys::I2S_PIN_NO_CHANGE,
+                mck_io_num: profile.i2s.mclk.map_or(sys::I2S_PIN_NO_CHANGE, i32::from),
+            };
+            if let Err(e) = esp_check(sys::i2s_set_pin(PORT, &pin_config), "I2S TX pin config") {
+                sys::i2s_driver_uninstall(PORT);
+                return Err(e);
+            }
+        }
+        // The board profile was validated, so nothing else drives this pin.
+        let enable = match profile.enable {
+            Some(gpio) => {
+                let mut pin = PinDriver::output(unsafe { AnyOutputPin::new(gpio as i32) })
+                    .map_err(io::Error::other)?;
+                pin.set_high().map_err(io::Error::other)?;
+                Some(pin)
+            }
+            None => None,
+        };
+        Ok(Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            _enable: enable,
+        })
+    }
+}
+
+impl AudioSink for I2sSpeaker {
+    fn begin(&mut self, sample_rate: u32) -> io::Result<()> {
+        if sample_rate != self.sample_rate {
+            esp_check(
+                unsafe { sys::i2s_set_sample_rates(PORT, sample_rate) },
+                "I2S sample rate change",
+            )?;
+            self.sample_rate = sample_rate;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut bytes: &[u8] = unsafe {
+            std::slice::from_raw_parts(samples.as_ptr().cast(), std::mem::size_of_val(samples))
+        };
+        while !bytes.is_empty() {
+            let mut written = 0;
+            esp_check(
+                unsafe {
+                    sys::i2s_write(
+                        PORT,
+                        bytes.as_ptr().cast(),
+                        bytes.len(),
+                        &mut written,
+                        sys::TickType_t::MAX,
+                    )
+                },
+                "I2S write",
+            )?;
+            bytes = &bytes[written..];
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        esp_check(unsafe { sys::i2s_zero_dma_buffer(PORT) }, "I2S flush")
+    }
+}
+
+impl Drop for I2sSpeaker {
+    fn drop(&mut self) {
+        unsafe {
+            sys::i2s_driver_uninstall(PORT);
+        }
+    }
+}
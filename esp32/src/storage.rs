@@ -0,0 +1,130 @@
+//! The SD card as a [`Storage`]: mounted for as long as it answers, remounted when
+//! it is put back.
+//!
+//! None of the supported boards wire up card detect, so a pulled card is noticed by
+//! its status (CMD13) failing.
+
+use std::io;
+use std::path::Path;
+
+use esp_idf_svc::fs::fatfs::Fatfs;
+use esp_idf_svc::hal::gpio::AnyIOPin;
+use esp_idf_svc::io::vfs::MountedFatfs;
+use esp_idf_svc::sd::spi::SdSpiHostDriver;
+use esp_idf_svc::sd::{SdCardConfiguration, SdCardDriver};
+use esp_idf_svc::sys;
+use slint_workshop_model::board::Gpio;
+use slint_workshop_model::storage::{Storage, StorageInfo};
+
+use crate::board::{BusLock, SpiBus};
+
+/// Where the SD card's FAT filesystem is mounted.
+const SD_MOUNT_POINT: &str = "/sdcard";
+/// Files the SD card may have open at once: a recording, its sidecar and the index.
+const SD_MAX_OPEN_FILES: usize = 4;
+/// FATFS drive number, also used in the `"0:"` paths passed to FATFS directly.
+const FATFS_DRIVE: u8 = 0;
+/// SD cards always use 512-byte sectors.
+const SECTOR_SIZE: u64 = 512;
+
+/// Boxed so the card keeps its address; FATFS and [`Mounted::card`] point at it.
+type SdCardBox = Box<SdCardDriver<SdSpiHostDriver<'static, SpiBus>>>;
+
+struct Mounted {
+    _fs: MountedFatfs<Fatfs<SdCardBox>>,
+    card: *mut sys::sdmmc_card_t,
+}
+
+/// An SD card in SPI mode, on its own bus or sharing the display's.
+pub struct SdStorage {
+    bus: SpiBus,
+    cs: Gpio,
+    bus_lock: BusLock,
+    mounted: Option<Mounted>,
+}
+
+// The card pointer is only used through `&mut self`, and points into the boxed driver
+// that `Mounted` owns.
+unsafe impl Send for SdStorage {}
+
+impl SdStorage {
+    pub fn new(bus: SpiBus, cs: Gpio, bus_lock: BusLock) -> Self {
+        Self {
+            bus,
+            cs,
+            bus_lock,
+            mounted: None,
+        }
+    }
+}
+
+impl Storage for SdStorage {
+    fn root(&self) -> &Path {
+        Path::new(SD_MOUNT_POINT)
+    }
+
+    fn is_mounted(&self) -> bool {
+        self.mounted.is_some()
+    }
+
+    fn is_present(&mut self) -> bool {
+        let Some(mounted) = &self.mounted else {
+            return false;
+        };
+        // Whoever holds the bus is using the card, so it is there.
+        let Ok(_bus) = self.bus_lock.try_lock() else {
+            return true;
+        };
+        unsafe { sys::sdmmc_get_status(mounted.card) == sys::ESP_OK }
+    }
+
+    fn mount(&mut self) -> io::Result<()> {
+        if self.mounted.is_some() {
+            return Ok(());
+        }
+        let _bus = self.bus_lock.lock().unwrap();
+        let mount = || -> Result<Mounted, sys::EspError> {
+            let host = SdSpiHostDriver::new(
+                self.bus.clone(),
+                // Nothing else uses the pin; the board profile was validated.
+                Some(unsafe { AnyIOPin::new(self.cs as i32) }),
+                AnyIOPin::none(), // CD
+                AnyIOPin::none(), // WP
+                AnyIOPin::none(), // INT
+                None,
+            )?;
+            let card: SdCardBox = Box::new(SdCardDriver::new_spi(host, &SdCardConfiguration::new())?);
+            let card_ptr = card.card() as *const sys::sdmmc_card_t as *mut sys::sdmmc_card_t;
+            let fs = MountedFatfs::mount(Fatfs::new_sdcard(FATFS_DRIVE, card)?, SD_MOUNT_POINT, SD_MAX_OPEN_FILES)?;
+            Ok(Mounted { _fs: fs, card: card_ptr })
+        };
+        self.mounted = Some(mount().map_err(io::Error::other)?);
+        Ok(())
+    }
+
+    fn unmount(&mut self) {
+        let _bus = self.bus_lock.lock().unwrap();
+        self.mounted = None;
+    }
+
+    fn info(&self) -> io::Result<StorageInfo> {
+        if self.mounted.is_none() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "SD card not mounted"));
+        }
+        let _bus = self.bus_lock.lock().unwrap();
+        let mut free_clusters: sys::DWORD = 0;
+        let mut fs: *mut sys::FATFS = std::ptr::null_mut();
+        let path = format!("{}:\0", FATFS_DRIVE);
+        let ret = unsafe { sys::f_getfree(path.as_ptr() as *const _, &mut free_clusters, &mut fs) };
+        if ret != sys::FRESULT_FR_OK || fs.is_null() {
+            return Err(io::Error::other(format!("f_getfree failed: {}", ret)));
+        }
+        let (cluster_sectors, fat_entries) = unsafe { ((*fs).csize as u64, (*fs).n_fatent as u64) };
+        // The first two FAT entries are reserved.
+        let cluster_bytes = cluster_sectors * SECTOR_SIZE;
+        Ok(StorageInfo {
+            total_bytes: fat_entries.saturating_sub(2) * cluster_bytes,
+            free_bytes: free_clusters as u64 * cluster_bytes,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Uploads recordings from the SD card to the server set at build time with `UPLOAD_URL`.
+
+use slint_workshop_model::upload::{UploadClient, UploadConfig, UploadMode, Uploader};
+
+use crate::board::BusLock;
+use crate::http::{tls_config, EspHttpClient};
+
+/// Where recordings are sent; without it nothing is uploaded.
+const UPLOAD_URL: Option<&str> = option_env!("UPLOAD_URL");
+const UPLOAD_API_KEY: Option<&str> = option_env!("UPLOAD_API_KEY");
+/// `put` (the default) for resumable chunked PUTs, `multipart` for one POST per recording.
+const UPLOAD_MODE: Option<&str> = option_env!("UPLOAD_MODE");
+/// Set to `1` to delete recordings from the card once uploaded.
+const UPLOAD_DELETE: Option<&str> = option_env!("UPLOAD_DELETE");
+/// Small enough to send between frames without holding the SD card's bus for long.
+const CHUNK_SIZE: usize = 32 * 1024;
+
+fn config() -> Option<UploadConfig> {
+    let url = UPLOAD_URL?;
+    let mode = match UPLOAD_MODE {
+        Some("multipart") => UploadMode::Multipart,
+        _ => UploadMode::ChunkedPut { chunk_size: CHUNK_SIZE },
+    };
+    Some(UploadConfig {
+        api_key: UPLOAD_API_KEY.map(str::to_string),
+        mode,
+        delete_after_upload: UPLOAD_DELETE == Some("1"),
+        ..UploadConfig::new(url)
+    })
+}
+
+/// Start the upload thread, or `None` if no upload server was configured.
+pub fn new_uploader(bus_lock: BusLock) -> Option<Uploader> {
+    let config = config()?;
+    let client = UploadClient::new(EspHttpClient::new(tls_config()), config).with_storage_lock(bus_lock);
+    match Uploader::start(client) {
+        Ok(uploader) => Some(uploader),
+        Err(e) => {
+            log::info!("Failed to start uploader: {:?}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Application state shared by the ESP32 and desktop builds.
+//!
+//! The binaries inject their platform backends and copy the state into their UI; the
+//! logic in between lives here so both run the same code.
+
+use std::io;
+
+use crate::clock::{is_valid_unix, unix_now};
+use crate::codec::RecordingFormat;
+use crate::history::{History, Sample};
+use crate::navigation::{Navigator, Page};
+use crate::recording::Recorder;
+use crate::sensors::{IndoorReading, SensorSource};
+use crate::settings::{SettingField, Settings, SettingsStore};
+use crate::weather::WeatherSource;
+use crate::{WeatherData, WifiNetwork, WifiNetworkProvider};
+
+pub struct Model<R = Box<dyn Recorder>> {
+    settings: Settings,
+    settings_store: Option<Box<dyn SettingsStore>>,
+    weather_source: Box<dyn WeatherSource>,
+    weather: Option<WeatherData>,
+    /// Past weather, recorded once the clock is set.
+    history: History,
+    /// `None` when no sensor was found.
+    sensor: Option<Box<dyn SensorSource>>,
+    indoor: Option<IndoorReading>,
+    wifi: Box<dyn WifiNetworkProvider>,
+    wifi_networks: Vec<WifiNetwork>,
+    /// `None` on hardware without a microphone.
+    recorder: Option<R>,
+    navigator: Navigator,
+}
+
+impl<R: Recorder> Model<R> {
+    /// A model with default settings that are not saved, and no recorder.
+    pub fn new(weather_source: Box<dyn WeatherSource>, wifi: Box<dyn WifiNetworkProvider>) -> Self {
+        Self {
+            settings: Settings::default(),
+            settings_store: None,
+            weather_source,
+            weather: None,
+            history: History::default(),
+            sensor: None,
+            indoor: None,
+            wifi,
+            wifi_networks: Vec::new(),
+            recorder: None,
+            navigator: Navigator::default(),
+        }
+    }
+
+    /// Use `settings`, saving changes to `store`.
+    pub fn with_settings(
+        mut self,
+        settings: Settings,
+        store: Option<Box<dyn SettingsStore>>,
+    ) -> Self {
+        self.settings = settings;
+        self.settings_store = store;
+        self.apply_settings();
+        self
+    }
+
+    pub fn with_recorder(mut self, recorder: R) -> Self {
+        self.recorder = Some(recorder);
+        self.apply_settings();
+        self
+    }
+
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_sensor(mut self, sensor: Box<dyn SensorSource>) -> Self {
+        self.sensor = Some(sensor);
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Step a setting, apply it and save the result.
+    pub fn adjust_setting(&mut self, field: SettingField, delta: i32) -> io::Result<()> {
+        self.settings.adjust(field, delta);
+        self.apply_settings();
+        match &self.settings_store {
+            Some(store) => self.settings.save(store.as_ref()),
+            None => Err(io::Error::other("no settings storage")),
+        }
+    }
+
+    fn apply_settings(&mut self) {
+        let length = self.settings.recording_length();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.session_mut().set_max_duration(length);
+        }
+    }
+
+    /// The last weather fetched, if any.
+    pub fn weather(&self) -> Option<&WeatherData> {
+        self.weather.as_ref()
+    }
+
+    /// Fetch the weather for the configured location and add it to the history. A
+    /// failure keeps the last reading.
+    pub fn refresh_weather(&mut self) -> io::Result<&WeatherData> {
+        let weather = self.weather_source.current(&self.settings.location)?;
+        let now = unix_now();
+        if is_valid_unix(now) {
+            if let Err(e) = self.history.push(Sample::new(now, &weather)) {
+                log::warn!("Failed to save the weather history: {}", e);
+            }
+        }
+        Ok(self.weather.insert(weather))
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    pub fn sensor_name(&self) -> Option<&'static str> {
+        self.sensor.as_ref().map(|sensor| sensor.name())
+    }
+
+    /// The last indoor reading, if any.
+    pub fn indoor(&self) -> Option<&IndoorReading> {
+        self.indoor.as_ref()
+    }
+
+    /// Read the indoor sensor. A failure keeps the last reading.
+    pub fn refresh_indoor(&mut self) -> io::Result<&IndoorReading> {
+        let sensor = self
+            .sensor
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no indoor sensor"))?;
+        let reading = sensor.read()?;
+        Ok(self.indoor.insert(reading))
+    }
+
+    pub fn wifi_networks(&self) -> &[WifiNetwork] {
+        &self.wifi_networks
+    }
+
+    pub fn scan_wifi(&mut self) -> &[WifiNetwork] {
+        self.wifi_networks = self.wifi.scan_wifi_networks();
+        &self.wifi_networks
+    }
+
+    pub fn current_page(&self) -> Page {
+        self.navigator.current_page()
+    }
+
+    /// Move `delta` pages along, as a swipe does. Returns whether the page changed.
+    pub fn navigate(&mut self, delta: i32) -> bool {
+        self.navigator.step(delta)
+    }
+
+    pub fn open_page(&mut self, page: Page) -> bool {
+        self.navigator.go_to(page)
+    }
+
+    pub fn navigate_back(&mut self) -> bool {
+        self.navigator.back()
+    }
+
+    pub fn recorder(&self) -> Option<&R> {
+        self.recorder.as_ref()
+    }
+
+    pub fn recorder_mut(&mut self) -> Option<&mut R> {
+        self.recorder.as_mut()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder
+            .as_ref()
+            .is_some_and(|recorder| recorder.session().is_recording())
+    }
+
+    pub fn start_recording(&mut self, format: RecordingFormat) -> io::Result<()> {
+        match &mut self.recorder {
+            Some(recorder) => recorder.start(format),
+            None => Err(io::Error::other("no recorder available")),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.stop();
+        }
+    }
+
+    /// Advance the recorder; call this regularly from a UI timer.
+    pub fn poll_recording(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.poll();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use crate::recording::RecordingSession;
+    use crate::settings::Location;
+
+    struct FakeWeather;
+
+    impl WeatherSource for FakeWeather {
+        fn current(&self, location: &Location) -> io::Result<WeatherData> {
+            if location.name == "Berlin" {
+                return Err(io::Error::other("offline"));
+            }
+            Ok(WeatherData {
+                temperature: location.latitude,
+                humidity: 50.0,
+                wind_speed: 1.0,
+            })
+        }
+    }
+
+    struct FakeWifi;
+
+    impl WifiNetworkProvider for FakeWifi {
+        fn scan_wifi_networks(&self) -> Vec<WifiNetwork> {
+            vec![WifiNetwork {
+                ssid: "Home".to_string(),
+            }]
+        }
+    }
+
+    /// Gets warmer with every read and fails on the third.
+    struct FakeSensor(u32);
+
+    impl SensorSource for FakeSensor {
+        fn name(&self) -> &'static str {
+            "Fake"
+        }
+
+        fn read(&mut self) -> io::Result<IndoorReading> {
+            self.0 += 1;
+            if self.0 == 3 {
+                return Err(io::Error::other("bus error"));
+            }
+            Ok(IndoorReading {
+                temperature: 20.0 + f64::from(self.0),
+                humidity: Some(40.0),
+                pressure: None,
+            })
+        }
+    }
+
+    struct FakeRecorder(RecordingSession);
+
+    impl Recorder for FakeRecorder {
+        fn start(&mut self, _format: RecordingFormat) -> io::Result<()> {
+            self.0.start(Instant::now());
+            Ok(())
+        }
+
+        fn stop(&mut self) {
+            self.0.stop(Instant::now());
+        }
+
+        fn poll(&mut self) {}
+
+        fn session(&self) -> &RecordingSession {
+            &self.0
+        }
+
+        fn session_mut(&mut self) -> &mut RecordingSession {
+            &mut self.0
+        }
+    }
+
+    /// Keeps what was saved so the test can look at it.
+    #[derive(Clone, Default)]
+    struct MemoryStore(Rc<RefCell<Option<Vec<u8>>>>);
+
+    impl SettingsStore for MemoryStore {
+        fn load(&self) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.borrow().clone())
+        }
+
+        fn save(&self, data: &[u8]) -> io::Result<()> {
+            *self.0.borrow_mut() = Some(data.to_vec());
+            Ok(())
+        }
+    }
+
+    fn fake_model() -> Model<FakeRecorder> {
+        Model::new(Box::new(FakeWeather), Box::new(FakeWifi))
+    }
+
+    #[test]
+    fn test_weather_and_wifi() {
+        let mut model = fake_model();
+        assert!(model.weather().is_none());
+        assert_eq!(model.refresh_weather().unwrap().temperature, 43.45);
+
+        // Berlin is "offline": the last reading is kept.
+        model.settings.location = Location::new("Berlin", 52.52, 13.40);
+        assert!(model.refresh_weather().is_err());
+        assert_eq!(model.weather().unwrap().temperature, 43.45);
+
+        // The failed fetch is not recorded.
+        assert_eq!(model.history().len(), 1);
+        assert_eq!(model.history().samples().next().unwrap().temperature, 43.45);
+
+        assert!(model.wifi_networks().is_empty());
+        assert_eq!(model.scan_wifi()[0].ssid, "Home");
+    }
+
+    #[test]
+    fn test_indoor_sensor() {
+        let mut model = fake_model();
+        assert_eq!(model.sensor_name(), None);
+        assert_eq!(
+            model.refresh_indoor().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let mut model = model.with_sensor(Box::new(FakeSensor(0)));
+        assert_eq!(model.sensor_name(), Some("Fake"));
+        assert!(model.indoor().is_none());
+        assert_eq!(model.refresh_indoor().unwrap().temperature, 21.0);
+        assert_eq!(model.refresh_indoor().unwrap().temperature, 22.0);
+        assert!(model.refresh_indoor().is_err());
+        assert_eq!(model.indoor().unwrap().temperature, 22.0);
+    }
+
+    #[test]
+    fn test_settings_are_applied_and_saved() {
+        let store = MemoryStore::default();
+        let mut model = fake_model()
+            .with_settings(Settings::default(), Some(Box::new(store.clone())))
+            .with_recorder(FakeRecorder(RecordingSession::new(
+                16000,
+                Duration::from_secs(1),
+            )));
+        assert_eq!(
+            model.recorder().unwrap().session().max_duration(),
+            Duration::from_secs(10)
+        );
+
+        model
+            .adjust_setting(SettingField::RecordingLength, 1)
+            .unwrap();
+        assert_eq!(
+            model.recorder().unwrap().session().max_duration(),
+            Duration::from_secs(15)
+        );
+        assert_eq!(Settings::load(&store).unwrap(), *model.settings());
+
+        assert!(fake_model()
+            .adjust_setting(SettingField::SpiClock, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_recording_and_navigation() {
+        let mut model = fake_model();
+        assert!(model.start_recording(RecordingFormat::Pcm).is_err());
+        let mut model = model.with_recorder(FakeRecorder(RecordingSession::new(
+            16000,
+            Duration::from_secs(1),
+        )));
+        model.start_recording(RecordingFormat::Pcm).unwrap();
+        assert!(model.is_recording());
+        model.stop_recording();
+        assert!(!model.is_recording());
+
+        assert_eq!(model.current_page(), Page::Weather);
+        assert!(model.navigate(1));
+        assert!(model.open_page(Page::Chat));
+        assert!(model.navigate_back());
+        assert_eq!(model.current_page(), Page::Forecast);
+    }
+}
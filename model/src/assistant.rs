@@ -0,0 +1,458 @@
+//! The voice assistant pipeline: transcribe a recording, stream the reply and speak it.
+//!
+//! Every service is optional; stages without a server are skipped. The binaries supply
+//! the HTTP client and the speaker.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use log::info;
+
+use crate::chat::{ChatClient, ChatConfig, ChatMessage, Role};
+use crate::clock::unix_now;
+use crate::codec::RecordingFormat;
+use crate::http::HttpClient;
+use crate::playback::{AudioSink, PlaybackQueue};
+use crate::stt::{SttClient, SttConfig};
+use crate::tts::{TtsClient, TtsConfig};
+
+/// Transcription, chat and speech requests each run a TLS handshake.
+const WORKER_STACK_SIZE: usize = 32 * 1024;
+
+/// Progress of a recording through transcription and the assistant's reply.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatEvent {
+    Transcribing,
+    Thinking,
+    Message(ChatMessage),
+    /// Next piece of the reply being streamed.
+    Token(String),
+    /// The complete reply, replacing the streamed pieces.
+    Reply(ChatMessage),
+    Failed(String),
+    /// The pipeline finished; speech may still be playing.
+    Done,
+}
+
+/// The servers the assistant talks to; `None` skips that stage.
+#[derive(Debug, Clone, Default)]
+pub struct AssistantConfig {
+    pub stt: Option<SttConfig>,
+    pub chat: Option<ChatConfig>,
+    pub tts: Option<TtsConfig>,
+}
+
+/// Shared between the UI and the [`AssistantWorker`] thread, which runs the pipeline.
+pub struct Assistant<C, S> {
+    stt: Option<SttClient<C>>,
+    /// Conversation the transcripts are sent to, kept across recordings.
+    chat: Option<Mutex<ChatClient<C>>>,
+    tts: Option<TtsClient<C>>,
+    playback: Option<PlaybackQueue<S>>,
+    /// Progress for the UI, drained by `take_events`.
+    events: Mutex<Vec<ChatEvent>>,
+    /// Set from the UI to stop answering the current recording; cleared for the next.
+    cancel: AtomicBool,
+}
+
+impl<C: HttpClient + Clone, S: AudioSink + Send + 'static> Assistant<C, S> {
+    /// Replies are only spoken when there is both a speech server and `playback`.
+    pub fn new(http: C, config: AssistantConfig, playback: Option<PlaybackQueue<S>>) -> Self {
+        Self {
+            stt: config
+                .stt
+                .map(|config| SttClient::new(http.clone(), config)),
+            chat: config
+                .chat
+                .map(|config| Mutex::new(ChatClient::new(http.clone(), config))),
+            tts: config.tts.map(|config| TtsClient::new(http, config)),
+            playback,
+            events: Mutex::new(Vec::new()),
+            cancel: AtomicBool::new(false),
+        }
+    }
+
+    pub fn take_events(&self) -> Vec<ChatEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn push_event(&self, event: ChatEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    /// Stop the reply that is streaming in and anything being spoken.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.stop_speaking();
+    }
+
+    /// Silence the speaker, e.g. so a new recording doesn't pick it up.
+    pub fn stop_speaking(&self) {
+        if let Some(playback) = &self.playback {
+            playback.stop();
+        }
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.playback
+            .as_ref()
+            .is_some_and(|playback| playback.is_busy())
+    }
+
+    /// Run a finished recording through transcription, the chat model and speech.
+    /// Blocks until the reply is complete; [`AssistantWorker`] runs it in the background.
+    pub fn respond(&self, file_name: &str, format: RecordingFormat, audio: &[u8]) {
+        // Only a stop pressed while this recording is answered counts.
+        self.cancel.store(false, Ordering::Relaxed);
+        self.run(file_name, format, audio);
+        self.push_event(ChatEvent::Done);
+    }
+
+    fn run(&self, file_name: &str, format: RecordingFormat, audio: &[u8]) {
+        let Some(stt) = &self.stt else {
+            return;
+        };
+        self.push_event(ChatEvent::Transcribing);
+        let text = match stt.transcribe(file_name, format, audio) {
+            Ok(text) => text,
+            Err(e) => {
+                info!("Transcription failed: {:?}", e);
+                self.push_event(ChatEvent::Failed(format!("Transcription failed: {}", e)));
+                return;
+            }
+        };
+        if self.cancel.load(Ordering::Relaxed) {
+            self.push_event(ChatEvent::Failed("Stopped".to_string()));
+            return;
+        }
+        info!("Transcript: {}", text);
+        self.push_event(ChatEvent::Message(ChatMessage::new(
+            Role::User,
+            text.as_str(),
+            unix_now(),
+        )));
+
+        let Some(chat) = &self.chat else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.push_event(ChatEvent::Thinking);
+        let reply = {
+            let mut chat = chat.lock().unwrap();
+            let result = chat.send_streaming(&text, unix_now(), &self.cancel, &mut |token| {
+                self.push_event(ChatEvent::Token(token.to_string()));
+            });
+            match result {
+                Ok(reply) => reply.clone(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    self.push_event(ChatEvent::Failed("Stopped".to_string()));
+                    return;
+                }
+                Err(e) => {
+                    info!("Chat request failed: {:?}", e);
+                    self.push_event(ChatEvent::Failed(format!("Assistant unavailable: {}", e)));
+                    return;
+                }
+            }
+        };
+        info!("Reply: {}", reply.content);
+        let content = reply.content.clone();
+        self.push_event(ChatEvent::Reply(reply));
+
+        if self.cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        if let (Some(tts), Some(playback)) = (&self.tts, &self.playback) {
+            match tts.synthesize(&content) {
+                Ok(clip) => {
+                    info!("Speaking {:?} of audio", clip.duration());
+                    playback.enqueue(clip);
+                }
+                Err(e) => info!("Speech synthesis failed: {:?}", e),
+            }
+        }
+    }
+}
+
+/// The messages shown on the chat page, built up from [`ChatEvent`]s.
+#[derive(Debug, Clone)]
+pub struct ChatLog {
+    messages: Vec<ChatMessage>,
+    /// The last message is a reply still being streamed.
+    streaming: bool,
+    max_messages: usize,
+}
+
+impl ChatLog {
+    /// Keep at most `max_messages`, dropping the oldest.
+    pub fn new(max_messages: usize) -> Self {
+        Self {
+            messages: Vec::new(),
+            streaming: false,
+            max_messages,
+        }
+    }
+
+    /// Oldest first.
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Apply an event to the messages. Returns whether they changed.
+    pub fn apply(&mut self, event: &ChatEvent) -> bool {
+        match event {
+            ChatEvent::Message(message) => self.push(message.clone()),
+            ChatEvent::Token(token) => {
+                if !std::mem::replace(&mut self.streaming, true) {
+                    self.push(ChatMessage::new(Role::Assistant, "", unix_now()));
+                }
+                if let Some(last) = self.messages.last_mut() {
+                    last.content.push_str(token);
+                }
+            }
+            ChatEvent::Reply(reply) => {
+                if std::mem::replace(&mut self.streaming, false) {
+                    self.messages.pop();
+                }
+                self.push(reply.clone());
+            }
+            ChatEvent::Failed(_) => {
+                self.streaming = false;
+                return false;
+            }
+            ChatEvent::Transcribing | ChatEvent::Thinking | ChatEvent::Done => return false,
+        }
+        true
+    }
+
+    fn push(&mut self, message: ChatMessage) {
+        self.messages.push(message);
+        if self.messages.len() > self.max_messages {
+            self.messages.remove(0);
+        }
+    }
+}
+
+/// A recording waiting for [`Assistant::respond`].
+struct Request {
+    file_name: String,
+    format: RecordingFormat,
+    audio: Vec<u8>,
+}
+
+/// Answers recordings one after another on a background thread, so the recorder is
+/// free for the next one while a reply is still coming in.
+pub struct AssistantWorker {
+    requests: mpsc::Sender<Request>,
+}
+
+impl AssistantWorker {
+    pub fn new<C, S>(assistant: Arc<Assistant<C, S>>) -> io::Result<Self>
+    where
+        C: HttpClient + Clone + Send + Sync + 'static,
+        S: AudioSink + Send + 'static,
+    {
+        let (requests, receiver) = mpsc::channel::<Request>();
+        std::thread::Builder::new()
+            .name("assistant".into())
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || {
+                for request in receiver {
+                    assistant.respond(&request.file_name, request.format, &request.audio);
+                }
+            })?;
+        Ok(Self { requests })
+    }
+
+    /// Queue a recording behind the ones still being answered.
+    pub fn submit(&self, file_name: &str, format: RecordingFormat, audio: Vec<u8>) {
+        let request = Request {
+            file_name: file_name.to_string(),
+            format,
+            audio,
+        };
+        if self.requests.send(request).is_err() {
+            log::warn!("The assistant stopped, {} is not answered", file_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    use crate::codec::wav;
+    use crate::http::mock::MockServer;
+    use crate::http::StdHttpClient;
+    use crate::playback::FileSink;
+    use crate::test_util::TempDir;
+
+    fn config(url: &str) -> AssistantConfig {
+        AssistantConfig {
+            stt: Some(SttConfig {
+                base_url: url.to_string(),
+                ..Default::default()
+            }),
+            chat: Some(ChatConfig {
+                base_url: url.to_string(),
+                ..Default::default()
+            }),
+            tts: Some(TtsConfig {
+                base_url: url.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_respond() {
+        let speech: Vec<i16> = (0..2400).map(|i| (i % 100) as i16).collect();
+        let mut wav_bytes = Vec::new();
+        wav::write_pcm(&mut wav_bytes, &speech, 24000).unwrap();
+        let mut speech_response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: {}\r\n\r\n",
+            wav_bytes.len()
+        )
+        .into_bytes();
+        speech_response.extend_from_slice(&wav_bytes);
+        let server = MockServer::start(vec![
+            MockServer::json(200, r#"{"text": "What's the weather?"}"#),
+            MockServer::event_stream(include_str!("../testdata/openai_stream.txt")),
+            speech_response,
+        ]);
+        let dir = TempDir::new("respond");
+        let playback = PlaybackQueue::new(FileSink::new(&dir.0)).unwrap();
+        let assistant = Assistant::new(MockServer::client(), config(server.url()), Some(playback));
+
+        assistant.respond("rec_1.wav", RecordingFormat::Pcm, b"RIFF....WAVE");
+        let events = assistant.take_events();
+        assert_eq!(events[0], ChatEvent::Transcribing);
+        assert!(matches!(&events[1], ChatEvent::Message(m) if m.content == "What's the weather?"));
+        assert_eq!(events[2], ChatEvent::Thinking);
+        assert_eq!(events[3], ChatEvent::Token("It's".to_string()));
+        assert!(
+            matches!(&events[events.len() - 2], ChatEvent::Reply(m) if m.content == "It's sunny and 21°C.")
+        );
+        assert_eq!(events.last(), Some(&ChatEvent::Done));
+
+        let mut log = ChatLog::new(10);
+        for event in &events[..events.len() - 2] {
+            log.apply(event);
+        }
+        assert_eq!(log.messages()[1].content, "It's sunny and 21°C.");
+        assert!(log.apply(&events[events.len() - 2]));
+        assert_eq!(log.messages().len(), 2, "the streamed reply is replaced");
+
+        let started = Instant::now();
+        while assistant.is_speaking() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let (_, played) =
+            wav::read(std::fs::File::open(dir.0.join("playback_000.wav")).unwrap()).unwrap();
+        assert_eq!(played, speech);
+        server.finish();
+    }
+
+    #[test]
+    fn test_transcription_failure() {
+        let server = MockServer::start(vec![MockServer::json(500, r#"{"error": "down"}"#)]);
+        let assistant: Assistant<_, FileSink> =
+            Assistant::new(MockServer::client(), config(server.url()), None);
+        assistant.respond("rec_1.wav", RecordingFormat::Pcm, b"RIFF");
+        let events = assistant.take_events();
+        assert!(
+            matches!(&events[1], ChatEvent::Failed(e) if e.starts_with("Transcription failed"))
+        );
+        assert_eq!(events.len(), 3);
+        server.finish();
+
+        // Without any servers a recording produces nothing but `Done`.
+        let assistant: Assistant<_, FileSink> =
+            Assistant::new(StdHttpClient::default(), AssistantConfig::default(), None);
+        assistant.respond("rec_1.wav", RecordingFormat::Pcm, b"RIFF");
+        assert_eq!(assistant.take_events(), [ChatEvent::Done]);
+    }
+
+    #[test]
+    fn test_worker() {
+        let server = MockServer::start(vec![MockServer::json(200, r#"{"text": ""}"#)]);
+        let mut config = config(server.url());
+        config.chat = None;
+        let assistant: Arc<Assistant<_, FileSink>> =
+            Arc::new(Assistant::new(MockServer::client(), config, None));
+        let worker = AssistantWorker::new(assistant.clone()).unwrap();
+        worker.submit("rec_1.wav", RecordingFormat::Pcm, b"RIFF".to_vec());
+
+        let started = Instant::now();
+        let mut events = Vec::new();
+        while !events.contains(&ChatEvent::Done) && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            events.extend(assistant.take_events());
+        }
+        assert_eq!(events[0], ChatEvent::Transcribing);
+        assert_eq!(events.last(), Some(&ChatEvent::Done));
+        server.finish();
+    }
+
+    #[test]
+    fn test_cancel_during_transcription() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let assistant: Arc<Assistant<_, FileSink>> =
+            Arc::new(Assistant::new(MockServer::client(), config(&url), None));
+        // A stop pressed before the recording doesn't affect it.
+        assistant.cancel();
+        let worker = AssistantWorker::new(assistant.clone()).unwrap();
+        worker.submit("rec_1.wav", RecordingFormat::Pcm, b"RIFF".to_vec());
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+        reader.read_exact(&mut vec![0; content_length]).unwrap();
+        assistant.cancel();
+        let response = MockServer::json(200, r#"{"text": "What's the weather?"}"#);
+        reader.get_mut().write_all(&response).unwrap();
+        drop(reader);
+
+        let started = Instant::now();
+        let mut events = Vec::new();
+        while !events.contains(&ChatEvent::Done) && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            events.extend(assistant.take_events());
+        }
+        assert_eq!(
+            events,
+            [
+                ChatEvent::Transcribing,
+                ChatEvent::Failed("Stopped".to_string()),
+                ChatEvent::Done
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chat_log_is_bounded() {
+        let mut log = ChatLog::new(2);
+        for text in ["one", "two", "three"] {
+            assert!(log.apply(&ChatEvent::Message(ChatMessage::new(Role::User, text, 0))));
+        }
+        assert_eq!(log.messages()[0].content, "two");
+        assert!(!log.apply(&ChatEvent::Failed("Stopped".to_string())));
+    }
+}
@@ -0,0 +1,529 @@
+//! Pin maps and peripherals of the supported boards.
+//!
+//! The firmware picks one profile at build time; [`BoardProfile::validate`] runs on the
+//! host so a pin clash shows up in `cargo test` instead of as a board that won't boot.
+
+use std::fmt;
+
+pub type Gpio = u8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+    Esp32,
+    Esp32S3,
+}
+
+impl Chip {
+    /// Whether the pin exists and is free for application use.
+    fn is_usable(self, gpio: Gpio) -> bool {
+        match self {
+            // 6..=11 are wired to the flash; 20, 24 and 28..=31 don't exist.
+            Chip::Esp32 => matches!(gpio, 0..=5 | 12..=19 | 21..=23 | 25..=27 | 32..=39),
+            // 26..=32 are wired to the flash and 33..=37 to octal PSRAM.
+            Chip::Esp32S3 => matches!(gpio, 0..=21 | 38..=48),
+        }
+    }
+
+    fn is_input_only(self, gpio: Gpio) -> bool {
+        self == Chip::Esp32 && (34..=39).contains(&gpio)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiBusPins {
+    pub sclk: Gpio,
+    pub mosi: Gpio,
+    pub miso: Option<Gpio>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayController {
+    St7789,
+    /// Uses the same commands as the ST7789 for everything the firmware does.
+    Ili9342,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayProfile {
+    pub controller: DisplayController,
+    pub bus: SpiBusPins,
+    pub cs: Gpio,
+    pub dc: Gpio,
+    pub reset: Option<Gpio>,
+    /// Active high.
+    pub backlight: Option<Gpio>,
+    pub width: u16,
+    pub height: u16,
+    /// Memory access control: rotation, mirroring and RGB/BGR order.
+    pub madctl: u8,
+    pub invert_colors: bool,
+}
+
+/// An SD card in SPI mode. On the display's bus the two share SCLK, MOSI and MISO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdProfile {
+    pub bus: SpiBusPins,
+    pub cs: Gpio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2sPins {
+    pub bclk: Gpio,
+    pub ws: Gpio,
+    pub mclk: Option<Gpio>,
+}
+
+/// An I2C bus; the pins run open drain, so both count as outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cPins {
+    pub sda: Gpio,
+    pub scl: Gpio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicKind {
+    /// A digital MEMS microphone like the INMP441 or MSM261, read directly over I2S.
+    I2sMems,
+    /// An ES7210 ADC, which needs configuring over I2C before it sends samples.
+    Es7210,
+}
+
+impl MicKind {
+    /// Whether the firmware has a driver for it; codecs need a setup it can't do yet.
+    pub fn is_supported(self) -> bool {
+        self == MicKind::I2sMems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicProfile {
+    pub kind: MicKind,
+    pub i2s: I2sPins,
+    pub data_in: Gpio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeakerKind {
+    /// A MAX98357-style class D amplifier taking I2S directly.
+    Max98357,
+    /// An ES8311 codec, which needs configuring over I2C.
+    Es8311,
+}
+
+impl SpeakerKind {
+    /// Whether the firmware has a driver for it; codecs need a setup it can't do yet.
+    pub fn is_supported(self) -> bool {
+        self == SpeakerKind::Max98357
+    }
+}
+
+/// On the microphone's I2S pins the two run full duplex on one port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeakerProfile {
+    pub kind: SpeakerKind,
+    pub i2s: I2sPins,
+    pub data_out: Gpio,
+    /// Amplifier enable, active high.
+    pub enable: Option<Gpio>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonAction {
+    /// Start a recording, or stop the one running.
+    Record,
+    NextPage,
+    PreviousPage,
+}
+
+/// A push button pulling its pin low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Button {
+    pub gpio: Gpio,
+    pub action: ButtonAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardProfile {
+    pub name: &'static str,
+    pub chip: Chip,
+    pub display: DisplayProfile,
+    pub sd: Option<SdProfile>,
+    pub mic: Option<MicProfile>,
+    pub speaker: Option<SpeakerProfile>,
+    /// Where a BME280, SHT3x or AHT20 for indoor readings may be connected.
+    pub sensor_i2c: Option<I2cPins>,
+    pub buttons: &'static [Button],
+}
+
+/// Why a profile can't be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinError {
+    /// Two functions want the same pin.
+    Conflict {
+        gpio: Gpio,
+        first: String,
+        second: String,
+    },
+    /// The pin doesn't exist on the chip or is taken by its flash or PSRAM.
+    Unusable { gpio: Gpio, function: String },
+    /// An output on one of the ESP32's input-only pins.
+    InputOnly { gpio: Gpio, function: String },
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinError::Conflict {
+                gpio,
+                first,
+                second,
+            } => write!(f, "GPIO{} is used for both {} and {}", gpio, first, second),
+            PinError::Unusable { gpio, function } => {
+                write!(f, "GPIO{} for {} is not available", gpio, function)
+            }
+            PinError::InputOnly { gpio, function } => {
+                write!(f, "GPIO{} for {} is input only", gpio, function)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PinError {}
+
+/// A pin and what it is used for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinClaim {
+    pub gpio: Gpio,
+    pub function: String,
+    pub output: bool,
+}
+
+#[derive(Default)]
+struct Claims(Vec<PinClaim>);
+
+impl Claims {
+    fn output(&mut self, gpio: Gpio, function: impl Into<String>) {
+        self.0.push(PinClaim {
+            gpio,
+            function: function.into(),
+            output: true,
+        });
+    }
+
+    fn input(&mut self, gpio: Gpio, function: impl Into<String>) {
+        self.0.push(PinClaim {
+            gpio,
+            function: function.into(),
+            output: false,
+        });
+    }
+
+    fn spi_bus(&mut self, bus: &SpiBusPins, user: &str) {
+        self.output(bus.sclk, format!("{} SCLK", user));
+        self.output(bus.mosi, format!("{} MOSI", user));
+        if let Some(miso) = bus.miso {
+            self.input(miso, format!("{} MISO", user));
+        }
+    }
+
+    fn i2s(&mut self, pins: &I2sPins, user: &str) {
+        self.output(pins.bclk, format!("{} BCLK", user));
+        self.output(pins.ws, format!("{} WS", user));
+        if let Some(mclk) = pins.mclk {
+            self.output(mclk, format!("{} MCLK", user));
+        }
+    }
+}
+
+impl BoardProfile {
+    /// Whether the SD card sits on the display's SPI bus.
+    pub fn sd_shares_display_bus(&self) -> bool {
+        self.sd.is_some_and(|sd| sd.bus == self.display.bus)
+    }
+
+    /// Whether the speaker runs on the microphone's I2S port.
+    pub fn speaker_shares_mic_i2s(&self) -> bool {
+        matches!((self.mic, self.speaker), (Some(mic), Some(speaker)) if mic.i2s == speaker.i2s)
+    }
+
+    /// Every pin the profile uses. Shared buses are listed once.
+    pub fn pin_claims(&self) -> Vec<PinClaim> {
+        let mut claims = Claims::default();
+        let display = &self.display;
+        if self.sd_shares_display_bus() {
+            claims.spi_bus(&display.bus, "display/SD SPI");
+        } else {
+            claims.spi_bus(&display.bus, "display SPI");
+        }
+        claims.output(display.cs, "display CS");
+        claims.output(display.dc, "display DC");
+        if let Some(reset) = display.reset {
+            claims.output(reset, "display reset");
+        }
+        if let Some(backlight) = display.backlight {
+            claims.output(backlight, "display backlight");
+        }
+
+        if let Some(sd) = &self.sd {
+            if !self.sd_shares_display_bus() {
+                claims.spi_bus(&sd.bus, "SD SPI");
+            }
+            claims.output(sd.cs, "SD CS");
+        }
+
+        if let Some(mic) = &self.mic {
+            if self.speaker_shares_mic_i2s() {
+                claims.i2s(&mic.i2s, "audio I2S");
+            } else {
+                claims.i2s(&mic.i2s, "mic I2S");
+            }
+            claims.input(mic.data_in, "mic data");
+        }
+        if let Some(speaker) = &self.speaker {
+            if !self.speaker_shares_mic_i2s() {
+                claims.i2s(&speaker.i2s, "speaker I2S");
+            }
+            claims.output(speaker.data_out, "speaker data");
+            if let Some(enable) = speaker.enable {
+                claims.output(enable, "speaker enable");
+            }
+        }
+
+        if let Some(i2c) = &self.sensor_i2c {
+            claims.output(i2c.sda, "sensor I2C SDA");
+            claims.output(i2c.scl, "sensor I2C SCL");
+        }
+
+        for button in self.buttons {
+            claims.input(button.gpio, format!("{:?} button", button.action));
+        }
+        claims.0
+    }
+
+    /// Check that every pin is usable on the chip and claimed only once.
+    pub fn validate(&self) -> Result<(), PinError> {
+        let claims = self.pin_claims();
+        for (i, claim) in claims.iter().enumerate() {
+            if !self.chip.is_usable(claim.gpio) {
+                return Err(PinError::Unusable {
+                    gpio: claim.gpio,
+                    function: claim.function.clone(),
+                });
+            }
+            if claim.output && self.chip.is_input_only(claim.gpio) {
+                return Err(PinError::InputOnly {
+                    gpio: claim.gpio,
+                    function: claim.function.clone(),
+                });
+            }
+            if let Some(first) = claims[..i].iter().find(|c| c.gpio == claim.gpio) {
+                return Err(PinError::Conflict {
+                    gpio: claim.gpio,
+                    first: first.function.clone(),
+                    second: claim.function.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ST7789 240x240 and the SD card share one SPI bus.
+const T_CAMERA_PLUS_BUS: SpiBusPins = SpiBusPins {
+    sclk: 21,
+    mosi: 19,
+    miso: Some(22),
+};
+
+/// LilyGo T-Camera Plus (ESP32-D0WDQ6) as shipped: display, SD card and MEMS microphone.
+pub const T_CAMERA_PLUS: BoardProfile = BoardProfile {
+    name: "LilyGo T-Camera Plus",
+    chip: Chip::Esp32,
+    display: DisplayProfile {
+        controller: DisplayController::St7789,
+        bus: T_CAMERA_PLUS_BUS,
+        cs: 12,
+        dc: 15,
+        reset: None,
+        backlight: Some(2),
+        width: 240,
+        height: 240,
+        madctl: 0x00,
+        invert_colors: false,
+    },
+    sd: Some(SdProfile {
+        bus: T_CAMERA_PLUS_BUS,
+        cs: 0,
+    }),
+    mic: Some(MicProfile {
+        kind: MicKind::I2sMems,
+        i2s: I2sPins {
+            bclk: 14,
+            ws: 32,
+            mclk: None,
+        },
+        data_in: 33,
+    }),
+    speaker: None,
+    sensor_i2c: None,
+    buttons: &[],
+};
+
+/// The T-Camera Plus with a MAX98357 on the camera header, which takes I2S0 and its
+/// pins from the camera.
+pub const T_CAMERA_PLUS_SPEAKER: BoardProfile = BoardProfile {
+    name: "LilyGo T-Camera Plus + MAX98357",
+    speaker: Some(SpeakerProfile {
+        kind: SpeakerKind::Max98357,
+        i2s: I2sPins {
+            bclk: 26,
+            ws: 25,
+            mclk: None,
+        },
+        data_out: 27,
+        enable: None,
+    }),
+    ..T_CAMERA_PLUS
+};
+
+/// Espressif ESP32-S3-BOX-3; the SD card is on the BOX-3-SENSOR dock.
+pub const ESP32_S3_BOX_3: BoardProfile = BoardProfile {
+    name: "ESP32-S3-BOX-3",
+    chip: Chip::Esp32S3,
+    display: DisplayProfile {
+        controller: DisplayController::Ili9342,
+        bus: SpiBusPins {
+            sclk: 7,
+            mosi: 6,
+            miso: None,
+        },
+        cs: 5,
+        dc: 4,
+        reset: Some(48),
+        backlight: Some(47),
+        width: 320,
+        height: 240,
+        // Mirrored on both axes, BGR.
+        madctl: 0xC8,
+        invert_colors: true,
+    },
+    sd: Some(SdProfile {
+        bus: SpiBusPins {
+            sclk: 11,
+            mosi: 14,
+            miso: Some(9),
+        },
+        cs: 12,
+    }),
+    // The ES7210 microphone and ES8311 speaker codecs are not supported yet. They share
+    // I2S on BCLK 17, WS 45 and MCLK 2, with data in on 16, data out on 15 and the
+    // amplifier enabled on 46.
+    mic: None,
+    speaker: None,
+    // The dock's AHT30.
+    sensor_i2c: Some(I2cPins { sda: 41, scl: 40 }),
+    buttons: &[Button {
+        gpio: 0,
+        action: ButtonAction::Record,
+    }],
+};
+
+pub const PROFILES: [&BoardProfile; 3] = [&T_CAMERA_PLUS, &T_CAMERA_PLUS_SPEAKER, &ESP32_S3_BOX_3];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_profiles_are_valid() {
+        for profile in PROFILES {
+            assert_eq!(profile.validate(), Ok(()), "{}", profile.name);
+        }
+        assert!(T_CAMERA_PLUS.sd_shares_display_bus());
+        assert!(!ESP32_S3_BOX_3.sd_shares_display_bus());
+        assert!(!T_CAMERA_PLUS_SPEAKER.speaker_shares_mic_i2s());
+    }
+
+    #[test]
+    fn test_builtin_profiles_are_supported() {
+        for profile in PROFILES {
+            if let Some(mic) = profile.mic {
+                assert!(mic.kind.is_supported(), "{}", profile.name);
+            }
+            if let Some(speaker) = profile.speaker {
+                assert!(speaker.kind.is_supported(), "{}", profile.name);
+            }
+        }
+        assert!(!MicKind::Es7210.is_supported());
+        assert!(!SpeakerKind::Es8311.is_supported());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        // What the firmware used to do: the SD card on its own "bus" reusing the
+        // display's SCLK and MOSI.
+        let mut profile = T_CAMERA_PLUS;
+        profile.sd = Some(SdProfile {
+            bus: SpiBusPins {
+                miso: Some(18),
+                ..T_CAMERA_PLUS_BUS
+            },
+            cs: 0,
+        });
+        assert_eq!(
+            profile.validate(),
+            Err(PinError::Conflict {
+                gpio: 21,
+                first: "display SPI SCLK".to_string(),
+                second: "SD SPI SCLK".to_string(),
+            })
+        );
+
+        let mut profile = T_CAMERA_PLUS;
+        profile.buttons = &[Button {
+            gpio: 0,
+            action: ButtonAction::Record,
+        }];
+        assert_eq!(
+            profile.validate().unwrap_err().to_string(),
+            "GPIO0 is used for both SD CS and Record button"
+        );
+
+        let mut profile = ESP32_S3_BOX_3;
+        profile.sensor_i2c = Some(I2cPins { sda: 41, scl: 47 });
+        assert_eq!(
+            profile.validate().unwrap_err().to_string(),
+            "GPIO47 is used for both display backlight and sensor I2C SCL"
+        );
+    }
+
+    #[test]
+    fn test_unusable_pins() {
+        let mut profile = T_CAMERA_PLUS;
+        profile.display.dc = 34;
+        assert!(matches!(
+            profile.validate(),
+            Err(PinError::InputOnly { gpio: 34, .. })
+        ));
+        // Input-only pins are fine for inputs.
+        profile.display.dc = 15;
+        profile.buttons = &[Button {
+            gpio: 34,
+            action: ButtonAction::NextPage,
+        }];
+        assert_eq!(profile.validate(), Ok(()));
+
+        let mut profile = ESP32_S3_BOX_3;
+        profile.display.cs = 35;
+        assert!(matches!(
+            profile.validate(),
+            Err(PinError::Unusable { gpio: 35, .. })
+        ));
+        let mut profile = T_CAMERA_PLUS;
+        profile.display.cs = 6;
+        assert!(matches!(
+            profile.validate(),
+            Err(PinError::Unusable { gpio: 6, .. })
+        ));
+    }
+}
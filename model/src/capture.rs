@@ -0,0 +1,506 @@
+//! Microphone capture and saving, shared by the ESP32 and desktop recorders.
+//!
+//! Platforms only provide an [`AudioSource`]: I2S on the device, a WAV file on desktop.
+//! [`CaptureRecorder`] runs the same DSP, encoding and indexing on top of either.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::clock::unix_now;
+use crate::codec::{wav, write_recording, RecordingFormat};
+use crate::dsp::{voice_chain, Processor};
+use crate::level::LevelMeter;
+use crate::recording::{Recorder, RecordingMetadata, RecordingSession};
+use crate::recordings::{write_atomic, RecordingIndex, RetentionPolicy};
+
+/// Encoding, e.g. to Opus, and the `on_saved` callback run on the capture thread.
+const CAPTURE_STACK_SIZE: usize = 32 * 1024;
+
+/// Live 16-bit mono input.
+pub trait AudioSource: Send {
+    fn sample_rate(&self) -> u32;
+
+    /// Block until input is available and fill the start of `buf`; `Ok(0)` means the
+    /// input ended. `TimedOut` errors are retried.
+    fn read(&mut self, buf: &mut [i16]) -> io::Result<usize>;
+}
+
+/// Plays a WAV file as if it were a microphone, for machines without one.
+pub struct WavFileSource {
+    sample_rate: u32,
+    samples: Vec<i16>,
+    position: usize,
+    paced: bool,
+    started: Option<Instant>,
+}
+
+impl WavFileSource {
+    /// Load a mono PCM or IMA ADPCM file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (info, samples) = wav::read(io::BufReader::new(std::fs::File::open(path)?))?;
+        Ok(Self::new(info.sample_rate, samples))
+    }
+
+    pub fn new(sample_rate: u32, samples: Vec<i16>) -> Self {
+        Self {
+            sample_rate,
+            samples,
+            position: 0,
+            paced: true,
+            started: None,
+        }
+    }
+
+    /// Return samples as fast as they are read instead of at the file's pace.
+    pub fn unpaced(mut self) -> Self {
+        self.paced = false;
+        self
+    }
+}
+
+impl AudioSource for WavFileSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, buf: &mut [i16]) -> io::Result<usize> {
+        let n = buf.len().min(self.samples.len() - self.position);
+        buf[..n].copy_from_slice(&self.samples[self.position..self.position + n]);
+        self.position += n;
+        if self.paced {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            let due = started
+                + Duration::from_secs_f64(self.position as f64 / f64::from(self.sample_rate));
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        Ok(n)
+    }
+}
+
+/// Read `source` until `running` is cleared, `max_duration` is reached or the input
+/// ends, and return the processed samples.
+///
+/// The raw input is appended to `pending` for the level meter and waveform, so they
+/// reflect microphone placement rather than the cleaned-up signal.
+pub fn capture(
+    source: &mut dyn AudioSource,
+    running: &AtomicBool,
+    pending: &Mutex<Vec<i16>>,
+    max_duration: Duration,
+) -> io::Result<Vec<i16>> {
+    let sample_rate = source.sample_rate();
+    let total_samples = (f64::from(sample_rate) * max_duration.as_secs_f64()) as usize;
+    let mut recorded = Vec::with_capacity(total_samples);
+    let mut buf = [0i16; 1024];
+    let mut dsp = voice_chain(sample_rate);
+
+    info!("Recording up to {} samples...", total_samples);
+
+    // Levels over the last second, logged so the microphone can be positioned.
+    let mut second_meter = LevelMeter::default();
+    let mut samples_this_second = 0;
+
+    let start_time = Instant::now();
+    while running.load(Ordering::Relaxed) && recorded.len() < total_samples {
+        let want = buf.len().min(total_samples - recorded.len());
+        let samples = match source.read(&mut buf[..want]) {
+            Ok(0) => break,
+            Ok(n) => &mut buf[..n],
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                info!("Audio read timed out: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        second_meter.process(samples);
+        pending.lock().unwrap().extend_from_slice(samples);
+        dsp.process(samples);
+        recorded.extend_from_slice(samples);
+
+        samples_this_second += samples.len();
+        if samples_this_second >= sample_rate as usize {
+            let levels = second_meter.total();
+            info!(
+                "Level: RMS {:.1} dBFS, peak {:.1} dBFS, {} clipped samples",
+                levels.rms_dbfs(),
+                levels.peak_dbfs(),
+                levels.clip_count
+            );
+            second_meter.reset();
+            samples_this_second = 0;
+        }
+    }
+
+    info!(
+        "Recorded {} samples in {:?}",
+        recorded.len(),
+        start_time.elapsed()
+    );
+    Ok(recorded)
+}
+
+/// A finished recording, encoded and saved if there was somewhere to save it.
+#[derive(Debug, Clone)]
+pub struct SavedRecording {
+    /// File name in the index, or a generic name when it was not saved.
+    pub file_name: String,
+    pub format: RecordingFormat,
+    pub encoded: Vec<u8>,
+    pub duration: Duration,
+}
+
+/// Encode `samples` and add them to `index`, pruning it by `retention`. Only the file
+/// writes and the index update hold `storage_lock`, not the encoding.
+pub fn save_recording(
+    samples: &[i16],
+    sample_rate: u32,
+    format: RecordingFormat,
+    index: Option<&Mutex<RecordingIndex>>,
+    retention: &RetentionPolicy,
+    storage_lock: Option<&Mutex<()>>,
+) -> io::Result<SavedRecording> {
+    let mut encoded = Vec::new();
+    write_recording(&mut encoded, format, samples, sample_rate)?;
+    let mut file_name = format!("recording.{}", format.extension());
+
+    if let Some(index) = index {
+        let _storage = storage_lock.map(|lock| lock.lock().unwrap());
+        let created_at = unix_now();
+        let path = index.lock().unwrap().next_path(created_at, format);
+        write_atomic(&path, &encoded)?;
+        info!("Audio saved to: {} ({:?})", path.display(), format);
+
+        let mut metadata = RecordingMetadata::from_samples(samples, sample_rate);
+        metadata.format = format;
+        metadata.save(&path)?;
+        info!(
+            "Recording levels: RMS {:.1} dBFS, peak {:.1} dBFS, {} clipped samples",
+            metadata.levels.rms_dbfs(),
+            metadata.levels.peak_dbfs(),
+            metadata.levels.clip_count
+        );
+
+        let mut index = index.lock().unwrap();
+        index.add(&path, created_at, &metadata)?;
+        index.prune(retention, created_at)?;
+        file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+    } else {
+        info!("Audio recording completed (not saved - no storage)");
+    }
+
+    Ok(SavedRecording {
+        file_name,
+        format,
+        encoded,
+        duration: Duration::from_millis(samples.len() as u64 * 1000 / sample_rate.max(1) as u64),
+    })
+}
+
+type OpenSource = Box<dyn FnMut() -> io::Result<Box<dyn AudioSource>>>;
+type OnSaved = Arc<dyn Fn(SavedRecording) + Send + Sync>;
+
+/// Background task reading the source while a recording is running.
+struct CaptureThread {
+    running: Arc<AtomicBool>,
+    /// Samples captured since the UI last polled, used for the level meter and waveform.
+    pending: Arc<Mutex<Vec<i16>>>,
+    handle: JoinHandle<()>,
+}
+
+/// A [`Recorder`] capturing from an [`AudioSource`] on a background thread.
+pub struct CaptureRecorder {
+    session: RecordingSession,
+    open_source: OpenSource,
+    capture: Option<CaptureThread>,
+    /// Shared with the capture thread, which adds each recording once it is saved.
+    index: Option<Arc<Mutex<RecordingIndex>>>,
+    retention: RetentionPolicy,
+    /// Called on the capture thread with every finished recording.
+    on_saved: Option<OnSaved>,
+    /// Held while writing a recording to storage, but not while encoding it.
+    storage_lock: Option<Arc<Mutex<()>>>,
+}
+
+impl CaptureRecorder {
+    /// `open_source` is called at the start of every recording.
+    pub fn new(
+        session: RecordingSession,
+        open_source: impl FnMut() -> io::Result<Box<dyn AudioSource>> + 'static,
+    ) -> Self {
+        Self {
+            session,
+            open_source: Box::new(open_source),
+            capture: None,
+            index: None,
+            retention: RetentionPolicy::default(),
+            on_saved: None,
+            storage_lock: None,
+        }
+    }
+
+    /// Save recordings into `index`, keeping it within `retention`.
+    pub fn with_index(mut self, index: RecordingIndex, retention: RetentionPolicy) -> Self {
+        self.index = Some(Arc::new(Mutex::new(index)));
+        self.retention = retention;
+        self
+    }
+
+    /// Keep recordings within `retention` once an index is set with [`Self::set_index`].
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Change the limits for the recordings saved from now on, e.g. to the free space
+    /// of a newly inserted card.
+    pub fn set_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+    }
+
+    /// Swap the index, e.g. when the SD card was pulled or another one inserted. A
+    /// recording already being saved still goes to the old index.
+    pub fn set_index(&mut self, index: Option<RecordingIndex>) {
+        self.index = index.map(|index| Arc::new(Mutex::new(index)));
+    }
+
+    pub fn on_saved(mut self, on_saved: impl Fn(SavedRecording) + Send + Sync + 'static) -> Self {
+        self.on_saved = Some(Arc::new(on_saved));
+        self
+    }
+
+    /// Hold `lock` while writing a recording, for storage that shares a bus with
+    /// something else, like the SD card and the display on the ESP32.
+    pub fn with_storage_lock(mut self, lock: Arc<Mutex<()>>) -> Self {
+        self.storage_lock = Some(lock);
+        self
+    }
+
+    pub fn index(&self) -> Option<&Arc<Mutex<RecordingIndex>>> {
+        self.index.as_ref()
+    }
+
+    /// Whether the last recording is still being captured or saved.
+    pub fn is_busy(&self) -> bool {
+        self.capture
+            .as_ref()
+            .is_some_and(|capture| !capture.handle.is_finished())
+    }
+}
+
+impl Recorder for CaptureRecorder {
+    /// Start capturing in a background thread. The recording stops on `stop` or after
+    /// the session's maximum duration, and is saved in `format`. Fails while the last
+    /// recording is still running or being saved.
+    fn start(&mut self, format: RecordingFormat) -> io::Result<()> {
+        if !format.is_available() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} recordings are not supported by this build", format),
+            ));
+        }
+        if self.session.is_recording() {
+            return Err(io::Error::other("a recording is already in progress"));
+        }
+        if self.is_busy() {
+            return Err(io::Error::other("the last recording is still being saved"));
+        }
+        if let Some(capture) = self.capture.take() {
+            let _ = capture.handle.join();
+        }
+
+        let mut source = (self.open_source)()?;
+        let sample_rate = source.sample_rate();
+        let running = Arc::new(AtomicBool::new(true));
+        let pending = Arc::new(Mutex::new(Vec::new()));
+        let max_duration = self.session.max_duration();
+        let index = self.index.clone();
+        let retention = self.retention;
+        let on_saved = self.on_saved.clone();
+        let storage_lock = self.storage_lock.clone();
+
+        let handle = std::thread::Builder::new()
+            .name("audio-capture".into())
+            .stack_size(CAPTURE_STACK_SIZE)
+            .spawn({
+                let running = running.clone();
+                let pending = pending.clone();
+                move || {
+                    let result = capture(source.as_mut(), &running, &pending, max_duration);
+                    running.store(false, Ordering::Relaxed);
+                    let saved = result.and_then(|samples| {
+                        save_recording(
+                            &samples,
+                            sample_rate,
+                            format,
+                            index.as_deref(),
+                            &retention,
+                            storage_lock.as_deref(),
+                        )
+                    });
+                    match saved {
+                        Ok(saved) => {
+                            if let Some(on_saved) = on_saved {
+                                on_saved(saved);
+                            }
+                        }
+                        Err(e) => info!("Audio capture failed: {:?}", e),
+                    }
+                }
+            })?;
+
+        self.session.start(Instant::now());
+        self.capture = Some(CaptureThread {
+            running,
+            pending,
+            handle,
+        });
+        Ok(())
+    }
+
+    /// Stop the running recording. The capture thread saves the file in the background.
+    fn stop(&mut self) {
+        if let Some(elapsed) = self.session.stop(Instant::now()) {
+            info!("Stopping recording after {:?}", elapsed);
+        }
+        if let Some(capture) = &self.capture {
+            capture.running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Move captured samples into the session and stop once the time limit is reached.
+    fn poll(&mut self) {
+        if let Some(capture) = &self.capture {
+            let samples = std::mem::take(&mut *capture.pending.lock().unwrap());
+            self.session.push_samples(&samples);
+
+            if !capture.running.load(Ordering::Relaxed) && self.session.is_recording() {
+                // The capture thread ended on its own (read error or end of input).
+                self.session.stop(Instant::now());
+            }
+        }
+        if self.session.should_stop(Instant::now()) {
+            self.stop();
+        }
+    }
+
+    fn session(&self) -> &RecordingSession {
+        &self.session
+    }
+
+    fn session_mut(&mut self) -> &mut RecordingSession {
+        &mut self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::sync::mpsc;
+
+    fn tone(len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| ((i as f32 * 0.2).sin() * 8000.0) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_capture_stops_at_limit() {
+        let mut source = WavFileSource::new(16000, tone(48000)).unpaced();
+        let pending = Mutex::new(Vec::new());
+        let samples = capture(
+            &mut source,
+            &AtomicBool::new(true),
+            &pending,
+            Duration::from_secs(2),
+        )
+        .unwrap();
+        assert_eq!(samples.len(), 32000);
+        assert_eq!(pending.lock().unwrap().len(), 32000);
+        // The raw input is metered; the saved samples went through the voice chain.
+        assert_eq!(pending.lock().unwrap()[..], tone(32000)[..]);
+        assert_ne!(samples, tone(32000));
+
+        let samples = capture(
+            &mut source,
+            &AtomicBool::new(true),
+            &pending,
+            Duration::from_secs(2),
+        )
+        .unwrap();
+        assert_eq!(samples.len(), 16000, "the rest of the file");
+    }
+
+    #[test]
+    fn test_wav_file_source() {
+        let dir = TempDir::new("source");
+        let path = dir.0.join("mic.wav");
+        let mut bytes = Vec::new();
+        wav::write_pcm(&mut bytes, &tone(100), 8000).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut source = WavFileSource::open(&path).unwrap();
+        assert_eq!(source.sample_rate(), 8000);
+        let mut buf = [0i16; 400];
+        let started = Instant::now();
+        assert_eq!(source.read(&mut buf).unwrap(), 100);
+        assert_eq!(buf[..100], tone(100)[..]);
+        // 100 samples at 8 kHz take 12.5 ms to "record".
+        assert!(started.elapsed() >= Duration::from_millis(12));
+        assert_eq!(source.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_capture_recorder_saves() {
+        let dir = TempDir::new("recorder");
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        let (saved_tx, saved_rx) = mpsc::channel();
+        let saved_tx = Mutex::new(saved_tx);
+        let storage_lock = Arc::new(Mutex::new(()));
+        let mut recorder = CaptureRecorder::new(
+            RecordingSession::new(16000, Duration::from_secs(10)),
+            || Ok(Box::new(WavFileSource::new(16000, tone(8000)).unpaced())),
+        )
+        .with_index(index, RetentionPolicy::default())
+        .with_storage_lock(storage_lock.clone())
+        .on_saved(move |saved| saved_tx.lock().unwrap().send(saved).unwrap());
+
+        let bus = storage_lock.lock().unwrap();
+        recorder.start(RecordingFormat::ImaAdpcm).unwrap();
+        assert!(recorder.session().is_recording());
+        assert!(recorder.start(RecordingFormat::ImaAdpcm).is_err());
+        assert!(
+            saved_rx.recv_timeout(Duration::from_millis(200)).is_err(),
+            "saving waits for the storage"
+        );
+        recorder.poll();
+        assert!(!recorder.session().is_recording(), "the input ended");
+        assert!(
+            recorder.start(RecordingFormat::ImaAdpcm).is_err(),
+            "the last recording is still being saved"
+        );
+        drop(bus);
+        let saved = saved_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(saved.format, RecordingFormat::ImaAdpcm);
+        assert_eq!(saved.duration, Duration::from_millis(500));
+        let index = recorder.index().unwrap().lock().unwrap();
+        let entry = index.get(&saved.file_name).unwrap();
+        assert_eq!(entry.format, RecordingFormat::ImaAdpcm);
+        assert_eq!(
+            std::fs::read(index.path(&saved.file_name)).unwrap(),
+            saved.encoded
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! Conversation with an OpenAI-compatible `/v1/chat/completions` endpoint.
+//!
+//! Works with hosted APIs as well as local llama.cpp or Ollama servers, which expose
+//! the same JSON shape. Replies can be streamed token by token over server-sent events.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::sse::SseParser;
+use crate::http::{join_url, HttpClient, Request};
+
+pub const COMPLETIONS_PATH: &str = "/v1/chat/completions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// One message of a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    /// Unix seconds when the message was added.
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>, timestamp: u64) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Server root, e.g. `https://192.168.1.10:11434` for Ollama.
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub system_prompt: String,
+    /// Messages kept besides the system prompt; older ones are forgotten.
+    pub max_history: usize,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.openai.com".to_string(),
+            api_key: None,
+            model: "gpt-4o-mini".to_string(),
+            system_prompt: "You are a voice assistant on a tiny 240x240 screen. \
+                            Answer in one or two short sentences."
+                .to_string(),
+            max_history: 12,
+            temperature: None,
+            max_tokens: Some(200),
+        }
+    }
+}
+
+/// Messages exchanged so far, bounded to the most recent ones.
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    messages: VecDeque<ChatMessage>,
+    max_messages: usize,
+}
+
+impl Conversation {
+    pub fn new(max_messages: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            max_messages,
+        }
+    }
+
+    pub fn messages(&self) -> impl ExactSizeIterator<Item = &ChatMessage> {
+        self.messages.iter()
+    }
+
+    pub fn last(&self) -> Option<&ChatMessage> {
+        self.messages.back()
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        self.messages.push_back(message);
+        while self.messages.len() > self.max_messages {
+            self.messages.pop_front();
+        }
+        // A history starting with a reply confuses some models; start at a question.
+        while self.messages.front().is_some_and(|m| m.role != Role::User) {
+            self.messages.pop_front();
+        }
+    }
+
+    fn pop(&mut self) -> Option<ChatMessage> {
+        self.messages.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[derive(Serialize)]
+struct WireMessage<'a> {
+    role: Role,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<WireMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+pub struct ChatClient<C> {
+    http: C,
+    config: ChatConfig,
+    conversation: Conversation,
+}
+
+impl<C: HttpClient> ChatClient<C> {
+    pub fn new(http: C, config: ChatConfig) -> Self {
+        let conversation = Conversation::new(config.max_history);
+        Self {
+            http,
+            config,
+            conversation,
+        }
+    }
+
+    pub fn config(&self) -> &ChatConfig {
+        &self.config
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    pub fn reset(&mut self) {
+        self.conversation.clear();
+    }
+
+    fn completion_request(&self, stream: bool) -> io::Result<Request> {
+        let system = WireMessage {
+            role: Role::System,
+            content: &self.config.system_prompt,
+        };
+        let history = self.conversation.messages().map(|m| WireMessage {
+            role: m.role,
+            content: &m.content,
+        });
+        let body = CompletionRequest {
+            model: &self.config.model,
+            messages: std::iter::once(system).chain(history).collect(),
+            temperature: self.config.temperature,
+            max_tokens: self.config.max_tokens,
+            stream,
+        };
+        Request::post(join_url(&self.config.base_url, COMPLETIONS_PATH))
+            .bearer_auth(self.config.api_key.as_deref())
+            .json(&body)
+    }
+
+    /// Send `text` as the user and return the assistant's reply.
+    ///
+    /// On failure the question is removed from the history so it can be asked again.
+    pub fn send(&mut self, text: &str, now: u64) -> io::Result<&ChatMessage> {
+        self.conversation
+            .push(ChatMessage::new(Role::User, text, now));
+        match self.complete() {
+            Ok(reply) => {
+                self.conversation
+                    .push(ChatMessage::new(Role::Assistant, reply, now));
+                Ok(self.conversation.last().unwrap())
+            }
+            Err(e) => {
+                self.conversation.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// Like [`send`](Self::send), passing each piece of the reply to `on_token` as the
+    /// server produces it.
+    ///
+    /// Setting `cancel` stops the stream; whatever arrived so far is kept as the reply.
+    /// If nothing arrived the question is forgotten and an `Interrupted` error returned.
+    pub fn send_streaming(
+        &mut self,
+        text: &str,
+        now: u64,
+        cancel: &AtomicBool,
+        on_token: &mut dyn FnMut(&str),
+    ) -> io::Result<&ChatMessage> {
+        self.conversation
+            .push(ChatMessage::new(Role::User, text, now));
+        let mut reply = String::new();
+        let result = self.stream(cancel, &mut |token| {
+            reply.push_str(token);
+            on_token(token);
+        });
+        match result {
+            Err(e) if e.kind() != io::ErrorKind::Interrupted || reply.trim().is_empty() => {
+                self.conversation.pop();
+                Err(e)
+            }
+            _ => {
+                self.conversation
+                    .push(ChatMessage::new(Role::Assistant, reply.trim(), now));
+                Ok(self.conversation.last().unwrap())
+            }
+        }
+    }
+
+    fn stream(&self, cancel: &AtomicBool, on_token: &mut dyn FnMut(&str)) -> io::Result<()> {
+        let mut parser = SseParser::new();
+        let mut done = false;
+        let response = self.http.execute_streaming(
+            self.completion_request(true)?
+                .header("Accept", "text/event-stream"),
+            &mut |data| {
+                let cancelled = || io::Error::new(io::ErrorKind::Interrupted, "cancelled");
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(cancelled());
+                }
+                for event in parser.feed(data) {
+                    if cancel.load(Ordering::Relaxed) {
+                        return Err(cancelled());
+                    }
+                    if done || event.data == "[DONE]" {
+                        done = true;
+                        continue;
+                    }
+                    if let Some(token) = parse_delta(&event.data)? {
+                        on_token(&token);
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        response.error_for_status()?;
+        if let Some(event) = parser.finish() {
+            if !done && event.data != "[DONE]" {
+                if let Some(token) = parse_delta(&event.data)? {
+                    on_token(&token);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn complete(&self) -> io::Result<String> {
+        let response = self
+            .http
+            .execute(self.completion_request(false)?)?
+            .error_for_status()?;
+        let completion: CompletionResponse = response.json()?;
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .map(|content| content.trim().to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "response has no choices"))
+    }
+}
+
+/// The content of one `chat.completion.chunk`, if it carries any.
+fn parse_delta(data: &str) -> io::Result<Option<String>> {
+    let chunk: serde_json::Value = serde_json::from_str(data)?;
+    // Errors after the stream started arrive as an event instead of a status code.
+    if let Some(error) = chunk.get("error") {
+        let message = error["message"]
+            .as_str()
+            .or(error.as_str())
+            .unwrap_or("unknown");
+        return Err(io::Error::other(format!("stream error: {}", message)));
+    }
+    Ok(chunk["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|content| !content.is_empty())
+        .map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock::MockServer;
+    use crate::http::StdHttpClient;
+
+    fn reply(content: &str) -> Vec<u8> {
+        MockServer::json(
+            200,
+            &serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": content},
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        )
+    }
+
+    fn client(server: &MockServer, max_history: usize) -> ChatClient<StdHttpClient> {
+        ChatClient::new(
+            MockServer::client(),
+            ChatConfig {
+                base_url: server.url().to_string(),
+                model: "llama3".to_string(),
+                system_prompt: "Be brief.".to_string(),
+                max_history,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_conversation_is_bounded() {
+        let mut conversation = Conversation::new(3);
+        for i in 0..3 {
+            conversation.push(ChatMessage::new(Role::User, format!("q{}", i), i));
+            conversation.push(ChatMessage::new(Role::Assistant, format!("a{}", i), i));
+        }
+        // Trimming to 3 would start at a reply, so that is dropped as well.
+        let contents: Vec<_> = conversation.messages().map(|m| &m.content).collect();
+        assert_eq!(contents, ["q2", "a2"]);
+    }
+
+    #[test]
+    fn test_send_keeps_history() {
+        let server = MockServer::start(vec![reply(" Sunny, 21°C. "), reply("Yes.")]);
+        let mut chat = client(&server, 10);
+
+        let answer = chat.send("What's the weather?", 100).unwrap();
+        assert_eq!(answer.role, Role::Assistant);
+        assert_eq!(answer.content, "Sunny, 21°C.");
+        chat.send("Should I bring a jacket?", 200).unwrap();
+        assert_eq!(chat.conversation().messages().len(), 4);
+
+        let requests = server.finish();
+        assert_eq!(requests[1].path, COMPLETIONS_PATH);
+        let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(body["model"], "llama3");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["max_tokens"], 200);
+        assert!(body.get("temperature").is_none());
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "Be brief.");
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[3]["content"], "Should I bring a jacket?");
+    }
+
+    fn stream_reply(
+        chat: &mut ChatClient<StdHttpClient>,
+        cancel: &AtomicBool,
+    ) -> (io::Result<String>, Vec<String>) {
+        let mut tokens = Vec::new();
+        let reply = chat
+            .send_streaming("Weather?", 5, cancel, &mut |token| {
+                tokens.push(token.to_string())
+            })
+            .map(|message| message.content.clone());
+        (reply, tokens)
+    }
+
+    #[test]
+    fn test_streaming_recorded_servers() {
+        let recordings = [
+            include_str!("../testdata/openai_stream.txt"),
+            include_str!("../testdata/llama_cpp_stream.txt"),
+            include_str!("../testdata/ollama_stream.txt"),
+        ];
+        let server = MockServer::start(
+            recordings
+                .iter()
+                .map(|r| MockServer::event_stream(r))
+                .collect(),
+        );
+        let mut chat = client(&server, 10);
+        for _ in recordings {
+            chat.reset();
+            let (reply, tokens) = stream_reply(&mut chat, &AtomicBool::new(false));
+            assert_eq!(reply.unwrap(), "It's sunny and 21°C.");
+            assert_eq!(tokens, ["It's", " sunny", " and", " 21°C."]);
+            assert_eq!(chat.conversation().messages().len(), 2);
+        }
+
+        let requests = server.finish();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(requests[0].header("Accept"), Some("text/event-stream"));
+    }
+
+    #[test]
+    fn test_streaming_errors() {
+        let server = MockServer::start(vec![
+            MockServer::json(429, r#"{"error": {"message": "Rate limit reached"}}"#),
+            MockServer::event_stream(
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n\
+                 data: {\"error\":{\"message\":\"overloaded\"}}\n\n",
+            ),
+        ]);
+        let mut chat = client(&server, 10);
+
+        let (reply, tokens) = stream_reply(&mut chat, &AtomicBool::new(false));
+        assert_eq!(
+            reply.unwrap_err().to_string(),
+            "HTTP 429: Rate limit reached"
+        );
+        assert!(tokens.is_empty());
+
+        let (reply, tokens) = stream_reply(&mut chat, &AtomicBool::new(false));
+        assert_eq!(reply.unwrap_err().to_string(), "stream error: overloaded");
+        assert_eq!(tokens, ["Hi"]);
+        assert_eq!(chat.conversation().messages().len(), 0);
+        server.finish();
+    }
+
+    #[test]
+    fn test_streaming_cancel() {
+        let stream = MockServer::event_stream(include_str!("../testdata/openai_stream.txt"));
+        let server = MockServer::start(vec![stream.clone(), stream]);
+        let mut chat = client(&server, 10);
+
+        let cancel = AtomicBool::new(true);
+        let (reply, _) = stream_reply(&mut chat, &cancel);
+        assert_eq!(reply.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert_eq!(chat.conversation().messages().len(), 0);
+
+        // Cancelling mid-stream keeps the partial reply.
+        cancel.store(false, Ordering::Relaxed);
+        let mut tokens = 0;
+        let reply = chat
+            .send_streaming("Weather?", 6, &cancel, &mut |_| {
+                tokens += 1;
+                if tokens == 2 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+            .unwrap();
+        assert_eq!(reply.content, "It's sunny");
+        assert_eq!(chat.conversation().messages().len(), 2);
+        server.finish();
+    }
+
+    #[test]
+    fn test_failed_send_is_forgotten() {
+        let server = MockServer::start(vec![
+            MockServer::json(500, r#"{"error": "model not loaded"}"#),
+            MockServer::json(200, r#"{"choices": []}"#),
+        ]);
+        let mut chat = client(&server, 10);
+
+        let error = chat.send("Hello?", 1).unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 500: model not loaded");
+        assert_eq!(chat.conversation().messages().len(), 0);
+
+        assert!(chat.send("Hello?", 2).is_err());
+        server.finish();
+    }
+}
@@ -0,0 +1,594 @@
+//! Wall clock time: POSIX TZ strings and the labels shown by the on-screen clock.
+//!
+//! The device has no timezone database, so the zone is configured as a POSIX TZ string
+//! such as `EST5EDT,M3.2.0,M11.1.0` and all conversions happen here.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Timestamps before 2020-01-01 come from a clock that was never synced.
+pub const MIN_VALID_UNIX: u64 = 1_577_836_800;
+
+pub fn is_valid_unix(secs: u64) -> bool {
+    secs >= MIN_VALID_UNIX
+}
+
+/// Seconds since the epoch, or 0 if the system clock is before it.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Error from [`PosixTz::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TzError {
+    /// Byte offset into the TZ string where parsing failed.
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for TzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid TZ string at {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for TzError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Zone {
+    name: String,
+    /// Seconds east of UTC.
+    offset: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleDate {
+    /// `Mm.w.d`: weekday `d` (0 = Sunday) of week `w` (5 = last) of month `m`.
+    Month { month: u8, week: u8, weekday: u8 },
+    /// `Jn`: day 1..=365, never counting February 29.
+    Julian(u16),
+    /// `n`: zero-based day of the year, counting February 29.
+    Ordinal(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    date: RuleDate,
+    /// Local time of the transition in seconds, may be negative or past midnight.
+    time: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dst {
+    zone: Zone,
+    start: Rule,
+    end: Rule,
+}
+
+/// A timezone described by a POSIX TZ string, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixTz {
+    std: Zone,
+    dst: Option<Dst>,
+}
+
+impl Default for PosixTz {
+    fn default() -> Self {
+        Self::utc()
+    }
+}
+
+impl PosixTz {
+    pub fn utc() -> Self {
+        Self::fixed("UTC", 0)
+    }
+
+    /// A zone without DST, `offset` seconds east of UTC.
+    pub fn fixed(name: &str, offset: i32) -> Self {
+        Self {
+            std: Zone {
+                name: name.to_string(),
+                offset,
+            },
+            dst: None,
+        }
+    }
+
+    pub fn parse(tz: &str) -> Result<Self, TzError> {
+        let mut parser = Parser { input: tz, pos: 0 };
+        let std_name = parser.name()?;
+        let std_offset = -parser.offset()?;
+        let std = Zone {
+            name: std_name,
+            offset: std_offset,
+        };
+
+        if parser.is_done() {
+            return Ok(Self { std, dst: None });
+        }
+
+        let dst_name = parser.name()?;
+        let dst_offset = match parser.peek() {
+            Some(',') | None => std_offset + 3600,
+            _ => -parser.offset()?,
+        };
+        let (start, end) = if parser.is_done() {
+            // Without explicit rules, fall back to the US rules like glibc does.
+            (
+                Rule {
+                    date: RuleDate::Month {
+                        month: 3,
+                        week: 2,
+                        weekday: 0,
+                    },
+                    time: 7200,
+                },
+                Rule {
+                    date: RuleDate::Month {
+                        month: 11,
+                        week: 1,
+                        weekday: 0,
+                    },
+                    time: 7200,
+                },
+            )
+        } else {
+            parser.expect(',')?;
+            let start = parser.rule()?;
+            parser.expect(',')?;
+            let end = parser.rule()?;
+            (start, end)
+        };
+        if !parser.is_done() {
+            return Err(parser.error("unexpected trailing characters"));
+        }
+
+        Ok(Self {
+            std,
+            dst: Some(Dst {
+                zone: Zone {
+                    name: dst_name,
+                    offset: dst_offset,
+                },
+                start,
+                end,
+            }),
+        })
+    }
+
+    /// The zone in effect at `unix`: its offset in seconds east of UTC and abbreviation.
+    pub fn offset_at(&self, unix: i64) -> (i32, &str) {
+        let Some(dst) = &self.dst else {
+            return (self.std.offset, &self.std.name);
+        };
+
+        let year = civil_from_days((unix + i64::from(self.std.offset)).div_euclid(86400)).0;
+        // Transition times are given in the local time in effect before the change.
+        let start = transition_local(year, dst.start) - i64::from(self.std.offset);
+        let end = transition_local(year, dst.end) - i64::from(dst.zone.offset);
+        let in_dst = if start < end {
+            unix >= start && unix < end
+        } else {
+            // Southern hemisphere: summer time spans the new year.
+            unix >= start || unix < end
+        };
+
+        if in_dst {
+            (dst.zone.offset, &dst.zone.name)
+        } else {
+            (self.std.offset, &self.std.name)
+        }
+    }
+
+    pub fn to_local(&self, unix: i64) -> LocalTime {
+        let (offset, abbreviation) = self.offset_at(unix);
+        LocalTime::from_unix(unix, offset, abbreviation)
+    }
+}
+
+impl FromStr for PosixTz {
+    type Err = TzError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> TzError {
+        TzError {
+            position: self.pos,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), TzError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    /// `EST` or the quoted form `<+0530>`.
+    fn name(&mut self) -> Result<String, TzError> {
+        let rest = &self.input[self.pos..];
+        let (name, len) = if let Some(quoted) = rest.strip_prefix('<') {
+            let end = quoted
+                .find('>')
+                .ok_or_else(|| self.error("unterminated zone name"))?;
+            (&quoted[..end], end + 2)
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+        if name.len() < 3 {
+            return Err(self.error("zone name must have at least 3 characters"));
+        }
+        self.pos += len;
+        Ok(name.to_string())
+    }
+
+    fn number(&mut self, max: u32) -> Result<u32, TzError> {
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let value = rest[..len]
+            .parse()
+            .ok()
+            .filter(|&value| value <= max)
+            .ok_or_else(|| self.error("number out of range"))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, with the POSIX sign (positive is west of UTC).
+    fn offset(&mut self) -> Result<i32, TzError> {
+        self.time(24)
+    }
+
+    fn time(&mut self, max_hours: u32) -> Result<i32, TzError> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let mut seconds = self.number(max_hours)? * 3600;
+        if self.eat(':') {
+            seconds += self.number(59)? * 60;
+            if self.eat(':') {
+                seconds += self.number(59)?;
+            }
+        }
+        Ok(sign * seconds as i32)
+    }
+
+    fn rule(&mut self) -> Result<Rule, TzError> {
+        let date = if self.eat('M') {
+            let month = self.number(12)? as u8;
+            self.expect('.')?;
+            let week = self.number(5)? as u8;
+            self.expect('.')?;
+            let weekday = self.number(6)? as u8;
+            if month == 0 || week == 0 {
+                return Err(self.error("month and week start at 1"));
+            }
+            RuleDate::Month {
+                month,
+                week,
+                weekday,
+            }
+        } else if self.eat('J') {
+            let day = self.number(365)? as u16;
+            if day == 0 {
+                return Err(self.error("Julian days start at 1"));
+            }
+            RuleDate::Julian(day)
+        } else {
+            RuleDate::Ordinal(self.number(365)? as u16)
+        };
+        let time = if self.eat('/') {
+            // RFC 8536 allows -167..=167 hours here.
+            self.time(167)?
+        } else {
+            7200
+        };
+        Ok(Rule { date, time })
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date, after Howard Hinnant's algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Days since 1970-01-01 to a (year, month, day) date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Transition of `rule` in `year` as local seconds since the epoch.
+fn transition_local(year: i64, rule: Rule) -> i64 {
+    let days = match rule.date {
+        RuleDate::Month {
+            month,
+            week,
+            weekday,
+        } => {
+            let month = u32::from(month);
+            let first = days_from_civil(year, month, 1);
+            // 1970-01-01 was a Thursday.
+            let first_weekday = (first + 4).rem_euclid(7) as u32;
+            let mut day =
+                1 + (u32::from(weekday) + 7 - first_weekday) % 7 + (u32::from(week) - 1) * 7;
+            while day > days_in_month(year, month) {
+                day -= 7;
+            }
+            first + i64::from(day) - 1
+        }
+        RuleDate::Julian(day) => {
+            let leap_skip = i64::from(is_leap_year(year) && day > 59);
+            days_from_civil(year, 1, 1) + i64::from(day) - 1 + leap_skip
+        }
+        RuleDate::Ordinal(day) => days_from_civil(year, 1, 1) + i64::from(day),
+    };
+    days * 86400 + i64::from(rule.time)
+}
+
+/// A broken-down local time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 = Sunday.
+    pub weekday: u32,
+    /// Seconds east of UTC.
+    pub offset: i32,
+    pub abbreviation: String,
+}
+
+impl LocalTime {
+    pub fn from_unix(unix: i64, offset: i32, abbreviation: &str) -> Self {
+        let local = unix + i64::from(offset);
+        let days = local.div_euclid(86400);
+        let secs = local.rem_euclid(86400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            minute: secs % 3600 / 60,
+            second: secs % 60,
+            weekday: (days + 4).rem_euclid(7) as u32,
+            offset,
+            abbreviation: abbreviation.to_string(),
+        }
+    }
+
+    /// `14:05`
+    pub fn time_label(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+
+    /// `Sat, Oct 18`
+    pub fn date_label(&self) -> String {
+        format!(
+            "{}, {} {}",
+            WEEKDAYS[self.weekday as usize],
+            MONTHS[self.month as usize - 1],
+            self.day
+        )
+    }
+
+    /// `2026-10-18 14:05 EDT`
+    pub fn datetime_label(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02} {} {}",
+            self.year,
+            self.month,
+            self.day,
+            self.time_label(),
+            self.abbreviation
+        )
+    }
+}
+
+/// What the on-screen clock shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockDisplay {
+    pub time: String,
+    pub date: String,
+    /// `false` until the clock has been set, e.g. by SNTP.
+    pub synced: bool,
+}
+
+impl ClockDisplay {
+    pub fn new(unix: u64, tz: &PosixTz) -> Self {
+        if !is_valid_unix(unix) {
+            return Self {
+                time: "--:--".to_string(),
+                date: "Clock not set".to_string(),
+                synced: false,
+            };
+        }
+        let local = tz.to_local(unix as i64);
+        Self {
+            time: local.time_label(),
+            date: local.date_label(),
+            synced: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unix(year: i64, month: u32, day: u32, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        for days in [-719_468, -1, 0, 59, 10_957, 20_744, 50_000] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(PosixTz::parse("").is_err());
+        assert!(PosixTz::parse("E5").is_err());
+        assert!(PosixTz::parse("EST").is_err());
+        assert!(PosixTz::parse("EST5EDT,M3.2.0").is_err());
+        assert!(PosixTz::parse("EST5EDT,M13.2.0,M11.1.0").is_err());
+        let error = PosixTz::parse("UTC0junk!").unwrap_err();
+        assert_eq!(error.position, 8);
+    }
+
+    #[test]
+    fn test_fixed_offsets() {
+        let utc: PosixTz = "UTC0".parse().unwrap();
+        assert_eq!(utc.offset_at(0), (0, "UTC"));
+        assert_eq!(utc, PosixTz::utc());
+
+        let india = PosixTz::parse("<+0530>-5:30").unwrap();
+        let local = india.to_local(unix(2026, 1, 1, 0, 0));
+        assert_eq!(local.offset, 5 * 3600 + 30 * 60);
+        assert_eq!(local.datetime_label(), "2026-01-01 05:30 +0530");
+    }
+
+    #[test]
+    fn test_north_american_dst() {
+        let tz = PosixTz::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // 2026: DST from March 8 at 2:00 EST to November 1 at 2:00 EDT.
+        assert_eq!(tz.offset_at(unix(2026, 3, 8, 6, 59)), (-5 * 3600, "EST"));
+        assert_eq!(tz.offset_at(unix(2026, 3, 8, 7, 0)), (-4 * 3600, "EDT"));
+        assert_eq!(tz.offset_at(unix(2026, 11, 1, 5, 59)), (-4 * 3600, "EDT"));
+        assert_eq!(tz.offset_at(unix(2026, 11, 1, 6, 0)), (-5 * 3600, "EST"));
+
+        // The US rules are the default when none are given.
+        assert_eq!(PosixTz::parse("EST5EDT").unwrap(), tz);
+
+        let local = tz.to_local(unix(2026, 10, 18, 18, 5));
+        assert_eq!(local.time_label(), "14:05");
+        assert_eq!(local.date_label(), "Sun, Oct 18");
+    }
+
+    #[test]
+    fn test_european_and_southern_dst() {
+        let cet = PosixTz::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // Last Sunday of March 2026 is the 29th, switching at 01:00 UTC.
+        assert_eq!(cet.offset_at(unix(2026, 3, 29, 0, 59)).1, "CET");
+        assert_eq!(cet.offset_at(unix(2026, 3, 29, 1, 0)).1, "CEST");
+        assert_eq!(cet.offset_at(unix(2026, 10, 25, 1, 0)).1, "CET");
+
+        let sydney = PosixTz::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(
+            sydney.offset_at(unix(2026, 1, 15, 0, 0)),
+            (11 * 3600, "AEDT")
+        );
+        assert_eq!(
+            sydney.offset_at(unix(2026, 7, 15, 0, 0)),
+            (10 * 3600, "AEST")
+        );
+    }
+
+    #[test]
+    fn test_julian_rules() {
+        // DST from March 1 (J60) to day 300 (zero-based) with one hour shifts.
+        let tz = PosixTz::parse("AAA0BBB,J60/0,300/0").unwrap();
+        assert_eq!(tz.offset_at(unix(2024, 2, 29, 12, 0)).1, "AAA");
+        assert_eq!(tz.offset_at(unix(2024, 3, 1, 0, 0)).1, "BBB");
+        // Day 300 of a leap year is October 27.
+        assert_eq!(tz.offset_at(unix(2024, 10, 26, 22, 59)).1, "BBB");
+        assert_eq!(tz.offset_at(unix(2024, 10, 26, 23, 0)).1, "AAA");
+    }
+
+    #[test]
+    fn test_clock_display() {
+        let tz = PosixTz::utc();
+        let display = ClockDisplay::new(12, &tz);
+        assert!(!display.synced);
+        assert_eq!(display.time, "--:--");
+
+        let display = ClockDisplay::new(MIN_VALID_UNIX + 3600 + 60, &tz);
+        assert!(display.synced);
+        assert_eq!(display.time, "01:01");
+        assert_eq!(display.date, "Wed, Jan 1");
+    }
+}
@@ -0,0 +1,194 @@
+//! IMA ADPCM in the block layout used by WAV files (format tag `0x0011`).
+//!
+//! Each mono block starts with a 4 byte header (first sample and step index) followed by
+//! 4-bit codes, low nibble first. A block of `block_align` bytes holds
+//! [`samples_per_block`] samples.
+
+const INDEX_TABLE: [i8; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const STEP_TABLE: [i16; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// Block size used for recordings; 256 bytes is the common choice up to 16 kHz mono.
+pub const BLOCK_ALIGN: usize = 256;
+
+const HEADER_LEN: usize = 4;
+
+/// Number of samples in a full mono block of `block_align` bytes.
+pub const fn samples_per_block(block_align: usize) -> usize {
+    (block_align - HEADER_LEN) * 2 + 1
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    predictor: i32,
+    index: usize,
+}
+
+impl State {
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step = i32::from(STEP_TABLE[self.index]);
+        let mut diff = i32::from(sample) - self.predictor;
+        let mut code = 0u8;
+        if diff < 0 {
+            code = 8;
+            diff = -diff;
+        }
+
+        // Successive approximation of diff / step, mirroring the decoder exactly.
+        let mut delta = step >> 3;
+        if diff >= step {
+            code |= 4;
+            diff -= step;
+            delta += step;
+        }
+        if diff >= step >> 1 {
+            code |= 2;
+            diff -= step >> 1;
+            delta += step >> 1;
+        }
+        if diff >= step >> 2 {
+            code |= 1;
+            delta += step >> 2;
+        }
+
+        self.apply(code, delta);
+        code
+    }
+
+    fn decode(&mut self, code: u8) -> i16 {
+        let step = i32::from(STEP_TABLE[self.index]);
+        let mut delta = step >> 3;
+        if code & 4 != 0 {
+            delta += step;
+        }
+        if code & 2 != 0 {
+            delta += step >> 1;
+        }
+        if code & 1 != 0 {
+            delta += step >> 2;
+        }
+        self.apply(code, delta);
+        self.predictor as i16
+    }
+
+    fn apply(&mut self, code: u8, delta: i32) {
+        if code & 8 != 0 {
+            self.predictor -= delta;
+        } else {
+            self.predictor += delta;
+        }
+        self.predictor = self
+            .predictor
+            .clamp(i32::from(i16::MIN), i32::from(i16::MAX));
+        self.index = (self.index as i32 + i32::from(INDEX_TABLE[usize::from(code)]))
+            .clamp(0, STEP_TABLE.len() as i32 - 1) as usize;
+    }
+}
+
+/// Encode mono samples into ADPCM blocks. The last block is shortened to fit.
+pub fn encode(samples: &[i16], block_align: usize) -> Vec<u8> {
+    let per_block = samples_per_block(block_align);
+    let mut output = Vec::with_capacity(samples.len().div_ceil(per_block) * block_align);
+    // The step index carries over between blocks so the encoder stays adapted.
+    let mut index = 0;
+
+    for block in samples.chunks(per_block) {
+        let mut state = State {
+            predictor: i32::from(block[0]),
+            index,
+        };
+        output.extend_from_slice(&block[0].to_le_bytes());
+        output.push(index as u8);
+        output.push(0);
+
+        for pair in block[1..].chunks(2) {
+            let low = state.encode(pair[0]);
+            let high = pair.get(1).map_or(0, |&s| state.encode(s));
+            output.push(low | (high << 4));
+        }
+        index = state.index;
+    }
+    output
+}
+
+/// Decode ADPCM blocks. `sample_count` trims the padding nibble of the last block.
+pub fn decode(data: &[u8], block_align: usize, sample_count: usize) -> Vec<i16> {
+    let mut samples = Vec::with_capacity(sample_count.min(data.len() * 2));
+
+    for block in data.chunks(block_align) {
+        if block.len() < HEADER_LEN || samples.len() >= sample_count {
+            break;
+        }
+        let first = i16::from_le_bytes([block[0], block[1]]);
+        let mut state = State {
+            predictor: i32::from(first),
+            index: usize::from(block[2]).min(STEP_TABLE.len() - 1),
+        };
+        samples.push(first);
+
+        for &byte in &block[HEADER_LEN..] {
+            samples.push(state.decode(byte & 0x0f));
+            samples.push(state.decode(byte >> 4));
+        }
+    }
+    samples.truncate(sample_count);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| (8000.0 * (i as f32 * 0.05).sin()) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_block_layout() {
+        assert_eq!(samples_per_block(BLOCK_ALIGN), 505);
+
+        let encoded = encode(&sine(505 * 2), BLOCK_ALIGN);
+        assert_eq!(encoded.len(), 2 * BLOCK_ALIGN);
+
+        // A partial block only holds the header and the codes it needs.
+        let encoded = encode(&sine(505 + 4), BLOCK_ALIGN);
+        assert_eq!(encoded.len(), BLOCK_ALIGN + HEADER_LEN + 2);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = sine(4000);
+        let output = decode(&encode(&input, BLOCK_ALIGN), BLOCK_ALIGN, input.len());
+        assert_eq!(output.len(), input.len());
+
+        // Block headers carry the exact sample.
+        assert_eq!(output[0], input[0]);
+        assert_eq!(output[505], input[505]);
+
+        // After adapting, ADPCM tracks a smooth signal closely.
+        let max_error = input[100..]
+            .iter()
+            .zip(&output[100..])
+            .map(|(a, b)| (i32::from(*a) - i32::from(*b)).abs())
+            .max()
+            .unwrap();
+        assert!(max_error < 400, "max error {max_error}");
+    }
+
+    #[test]
+    fn test_extremes_do_not_overflow() {
+        let input: Vec<i16> = (0..1000)
+            .map(|i| if i % 2 == 0 { i16::MAX } else { i16::MIN })
+            .collect();
+        let output = decode(&encode(&input, BLOCK_ALIGN), BLOCK_ALIGN, input.len());
+        assert_eq!(output.len(), input.len());
+    }
+}
@@ -0,0 +1,95 @@
+//! Encoders for recordings written to the SD card.
+
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+pub mod adpcm;
+pub mod ogg;
+pub mod opus;
+pub mod wav;
+
+/// File format a recording is stored in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingFormat {
+    /// Uncompressed 16-bit WAV, 32 KB/s at 16 kHz.
+    #[default]
+    Pcm,
+    /// IMA ADPCM WAV, a quarter of the PCM size and cheap to encode.
+    ImaAdpcm,
+    /// Opus in Ogg, smallest by far but only available with the `opus` feature.
+    Opus,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 3] = [Self::Pcm, Self::ImaAdpcm, Self::Opus];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Pcm | Self::ImaAdpcm => "wav",
+            Self::Opus => "opus",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Pcm | Self::ImaAdpcm => "audio/wav",
+            Self::Opus => "audio/ogg",
+        }
+    }
+
+    /// Whether this build can encode the format.
+    pub fn is_available(self) -> bool {
+        self != Self::Opus || cfg!(feature = "opus")
+    }
+}
+
+/// Encode mono samples in `format` and write them to `writer`.
+pub fn write_recording<W: Write>(
+    mut writer: W,
+    format: RecordingFormat,
+    samples: &[i16],
+    sample_rate: u32,
+) -> io::Result<()> {
+    match format {
+        RecordingFormat::Pcm => wav::write_pcm(writer, samples, sample_rate),
+        RecordingFormat::ImaAdpcm => wav::write_ima_adpcm(writer, samples, sample_rate),
+        RecordingFormat::Opus => {
+            writer.write_all(&opus::encode(samples, sample_rate)?)?;
+            writer.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_recording() {
+        let samples: Vec<i16> = (0..1600).map(|i| (i % 100) as i16 * 50).collect();
+
+        let mut pcm = Vec::new();
+        write_recording(&mut pcm, RecordingFormat::Pcm, &samples, 16000).unwrap();
+        let mut adpcm = Vec::new();
+        write_recording(&mut adpcm, RecordingFormat::ImaAdpcm, &samples, 16000).unwrap();
+        assert!(adpcm.len() * 3 < pcm.len());
+
+        for bytes in [pcm, adpcm] {
+            let (_, decoded) = wav::read(bytes.as_slice()).unwrap();
+            assert_eq!(decoded.len(), samples.len());
+        }
+
+        let mut opus = Vec::new();
+        let result = write_recording(&mut opus, RecordingFormat::Opus, &samples, 16000);
+        assert_eq!(result.is_ok(), RecordingFormat::Opus.is_available());
+    }
+
+    #[test]
+    fn test_format_serde() {
+        let json = serde_json::to_string(&RecordingFormat::ImaAdpcm).unwrap();
+        assert_eq!(json, "\"ima_adpcm\"");
+        assert_eq!(RecordingFormat::Opus.extension(), "opus");
+    }
+}
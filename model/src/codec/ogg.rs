@@ -0,0 +1,270 @@
+//! Minimal Ogg bitstream writer and reader (RFC 3533) for a single logical stream.
+
+use std::io::{self, Write};
+
+const HEADER_LEN: usize = 27;
+const MAX_SEGMENTS: usize = 255;
+/// Pages are flushed once they carry this much data, keeping seeks cheap.
+const TARGET_PAGE_SIZE: usize = 4096;
+
+const FLAG_CONTINUED: u8 = 0x01;
+const FLAG_BEGIN: u8 = 0x02;
+const FLAG_END: u8 = 0x04;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The Ogg CRC: polynomial 0x04c11db7, no reflection, zero initial value.
+fn crc32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+/// Packs packets into Ogg pages.
+pub struct OggWriter<W: Write> {
+    inner: W,
+    serial: u32,
+    sequence: u32,
+    granule: u64,
+    segments: Vec<u8>,
+    data: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> OggWriter<W> {
+    pub fn new(inner: W, serial: u32) -> Self {
+        Self {
+            inner,
+            serial,
+            sequence: 0,
+            granule: 0,
+            segments: Vec::new(),
+            data: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Queue a packet. `granule` is the stream position at the end of the packet.
+    ///
+    /// Packets must fit on one page (less than 64 KiB), which always holds for Opus.
+    pub fn write_packet(&mut self, packet: &[u8], granule: u64) -> io::Result<()> {
+        let segment_count = packet.len() / 255 + 1;
+        if segment_count > MAX_SEGMENTS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet too large for a single Ogg page",
+            ));
+        }
+        if self.segments.len() + segment_count > MAX_SEGMENTS {
+            self.flush_page(false)?;
+        }
+
+        let full_segments = packet.len() / 255;
+        self.segments
+            .resize(self.segments.len() + full_segments, 255);
+        self.segments.push((packet.len() % 255) as u8);
+        self.data.extend_from_slice(packet);
+        self.granule = granule;
+
+        if self.data.len() >= TARGET_PAGE_SIZE {
+            self.flush_page(false)?;
+        }
+        Ok(())
+    }
+
+    /// Write queued packets as a page, e.g. to keep header packets on their own page.
+    pub fn flush_page(&mut self, end_of_stream: bool) -> io::Result<()> {
+        if self.segments.is_empty() && !end_of_stream {
+            return Ok(());
+        }
+
+        let mut flags = 0;
+        if !self.started {
+            flags |= FLAG_BEGIN;
+            self.started = true;
+        }
+        if end_of_stream {
+            flags |= FLAG_END;
+        }
+
+        let mut page = Vec::with_capacity(HEADER_LEN + self.segments.len() + self.data.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0);
+        page.push(flags);
+        page.extend_from_slice(&self.granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(self.segments.len() as u8);
+        page.extend_from_slice(&self.segments);
+        page.extend_from_slice(&self.data);
+
+        let crc = crc32(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        self.inner.write_all(&page)?;
+        self.sequence += 1;
+        self.segments.clear();
+        self.data.clear();
+        Ok(())
+    }
+
+    /// Write the final page and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_page(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// A packet read back from an Ogg stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggPacket {
+    pub data: Vec<u8>,
+    /// Granule position of the page the packet ends on.
+    pub granule: u64,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Split an Ogg stream into packets, checking page CRCs.
+pub fn read_packets(bytes: &[u8]) -> io::Result<Vec<OggPacket>> {
+    let mut packets = Vec::new();
+    let mut partial = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let header = bytes
+            .get(offset..offset + HEADER_LEN)
+            .ok_or_else(|| invalid("truncated page header"))?;
+        if &header[0..4] != b"OggS" || header[4] != 0 {
+            return Err(invalid("missing Ogg capture pattern"));
+        }
+        let flags = header[5];
+        let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+        let expected_crc = u32::from_le_bytes(header[22..26].try_into().unwrap());
+        let segment_count = usize::from(header[26]);
+
+        let table_end = offset + HEADER_LEN + segment_count;
+        let table = bytes
+            .get(offset + HEADER_LEN..table_end)
+            .ok_or_else(|| invalid("truncated segment table"))?;
+        let data_len: usize = table.iter().map(|&s| usize::from(s)).sum();
+        let page_end = table_end + data_len;
+        let page = bytes
+            .get(offset..page_end)
+            .ok_or_else(|| invalid("truncated page data"))?;
+
+        let mut check = page.to_vec();
+        check[22..26].fill(0);
+        if crc32(&check) != expected_crc {
+            return Err(invalid("Ogg page CRC mismatch"));
+        }
+
+        if flags & FLAG_CONTINUED == 0 {
+            partial.clear();
+        }
+        let mut data_offset = table_end;
+        for &segment in table {
+            let segment = usize::from(segment);
+            partial.extend_from_slice(&bytes[data_offset..data_offset + segment]);
+            data_offset += segment;
+            if segment < 255 {
+                packets.push(OggPacket {
+                    data: std::mem::take(&mut partial),
+                    granule,
+                });
+            }
+        }
+        offset = page_end;
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_matches_reference() {
+        // Reference value for the Ogg CRC (CRC-32/MPEG-2 without final inversion).
+        assert_eq!(crc32(b"123456789"), 0x89a1_897f);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packets: Vec<Vec<u8>> = vec![
+            b"header".to_vec(),
+            vec![7; 255],
+            vec![1; 600],
+            Vec::new(),
+            vec![9; 3],
+        ];
+
+        let mut writer = OggWriter::new(Vec::new(), 0x1234);
+        writer.write_packet(&packets[0], 0).unwrap();
+        writer.flush_page(false).unwrap();
+        for (i, packet) in packets.iter().enumerate().skip(1) {
+            writer.write_packet(packet, i as u64 * 960).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let read = read_packets(&bytes).unwrap();
+        assert_eq!(read.len(), packets.len());
+        for (read, written) in read.iter().zip(&packets) {
+            assert_eq!(&read.data, written);
+        }
+        assert_eq!(read[0].granule, 0);
+        assert_eq!(read.last().unwrap().granule, 4 * 960);
+
+        // First page is flagged as beginning of stream, last as end of stream.
+        assert_eq!(bytes[5], FLAG_BEGIN);
+        let last_page = bytes.windows(4).rposition(|w| w == b"OggS").unwrap();
+        assert_eq!(bytes[last_page + 5], FLAG_END);
+    }
+
+    #[test]
+    fn test_many_packets_span_pages() {
+        let mut writer = OggWriter::new(Vec::new(), 1);
+        for i in 0..1000u32 {
+            writer.write_packet(&i.to_le_bytes(), u64::from(i)).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let read = read_packets(&bytes).unwrap();
+        assert_eq!(read.len(), 1000);
+        assert_eq!(read[999].data, 999u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let mut writer = OggWriter::new(Vec::new(), 1);
+        writer.write_packet(b"payload", 1).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(read_packets(&bytes).is_err());
+        assert!(read_packets(b"OggS").is_err());
+    }
+}
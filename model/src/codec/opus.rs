@@ -0,0 +1,244 @@
+//! Opus in an Ogg container (RFC 7845).
+//!
+//! The container handling is always available; encoding and decoding need the `opus`
+//! feature, which links libopus through `audiopus`.
+
+use std::io;
+
+use super::ogg::OggPacket;
+
+/// Opus granule positions always count 48 kHz samples.
+pub const GRANULE_RATE: u32 = 48000;
+
+/// Frame length used for recordings.
+pub const FRAME_MS: u32 = 20;
+
+/// Bitrate used for speech recordings.
+pub const BITRATE: i32 = 16000;
+
+/// Identification header, the first packet of every Ogg Opus stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusHead {
+    pub channels: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+}
+
+impl OpusHead {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(19);
+        bytes.extend_from_slice(b"OpusHead");
+        bytes.push(1);
+        bytes.push(self.channels);
+        bytes.extend_from_slice(&self.pre_skip.to_le_bytes());
+        bytes.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&0i16.to_le_bytes());
+        bytes.push(0);
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 19 || &bytes[0..8] != b"OpusHead" || bytes[8] & 0xf0 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing OpusHead packet",
+            ));
+        }
+        Ok(Self {
+            channels: bytes[9],
+            pre_skip: u16::from_le_bytes([bytes[10], bytes[11]]),
+            input_sample_rate: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+}
+
+/// Comment header with only a vendor string.
+pub fn opus_tags(vendor: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16 + vendor.len());
+    bytes.extend_from_slice(b"OpusTags");
+    bytes.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(vendor.as_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes
+}
+
+/// Split a parsed stream into its header and audio packets.
+pub fn split_stream(packets: &[OggPacket]) -> io::Result<(OpusHead, &[OggPacket])> {
+    let head = OpusHead::parse(packets.first().map_or(&[][..], |p| &p.data))?;
+    match packets.get(1) {
+        Some(tags) if tags.data.starts_with(b"OpusTags") => Ok((head, &packets[2..])),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing OpusTags packet",
+        )),
+    }
+}
+
+#[cfg(feature = "opus")]
+mod codec {
+    use std::io;
+
+    use audiopus::coder::{Decoder, Encoder};
+    use audiopus::packet::Packet;
+    use audiopus::{Application, Bitrate, Channels, MutSignals, SampleRate};
+
+    use super::super::ogg::{read_packets, OggWriter};
+    use super::{opus_tags, split_stream, OpusHead, BITRATE, FRAME_MS, GRANULE_RATE};
+
+    fn opus_error(error: audiopus::Error) -> io::Error {
+        io::Error::other(error)
+    }
+
+    fn sample_rate(rate: u32) -> io::Result<SampleRate> {
+        SampleRate::try_from(rate as i32).map_err(opus_error)
+    }
+
+    /// Encode mono samples as an Ogg Opus stream.
+    pub fn encode(samples: &[i16], rate: u32) -> io::Result<Vec<u8>> {
+        let mut encoder = Encoder::new(sample_rate(rate)?, Channels::Mono, Application::Voip)
+            .map_err(opus_error)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(BITRATE))
+            .map_err(opus_error)?;
+        let granule_per_sample = u64::from(GRANULE_RATE / rate);
+        let lookahead = encoder.lookahead().map_err(opus_error)?;
+
+        let mut writer = OggWriter::new(Vec::new(), 0x5265_6331);
+        let head = OpusHead {
+            channels: 1,
+            pre_skip: (u64::from(lookahead) * granule_per_sample) as u16,
+            input_sample_rate: rate,
+        };
+        writer.write_packet(&head.to_bytes(), 0)?;
+        writer.flush_page(false)?;
+        writer.write_packet(&opus_tags("slint-chat-esp32"), 0)?;
+        writer.flush_page(false)?;
+
+        let frame_len = (rate * FRAME_MS / 1000) as usize;
+        let mut frame = vec![0i16; frame_len];
+        let mut packet = [0u8; 1500];
+        let mut granule = 0;
+        // Flush the encoder's lookahead with silence so no audio is cut at the end.
+        let padded_len = samples.len() + lookahead as usize;
+        let total_granule = u64::from(head.pre_skip) + samples.len() as u64 * granule_per_sample;
+
+        for start in (0..padded_len).step_by(frame_len) {
+            frame.fill(0);
+            if start < samples.len() {
+                let end = (start + frame_len).min(samples.len());
+                frame[..end - start].copy_from_slice(&samples[start..end]);
+            }
+            let len = encoder.encode(&frame, &mut packet).map_err(opus_error)?;
+            granule += frame_len as u64 * granule_per_sample;
+            // The last packet's granule marks where decoded audio ends.
+            writer.write_packet(&packet[..len], granule.min(total_granule))?;
+        }
+        writer.finish()
+    }
+
+    /// Decode an Ogg Opus stream produced by [`encode`] back to samples at `rate`.
+    pub fn decode(bytes: &[u8], rate: u32) -> io::Result<Vec<i16>> {
+        let packets = read_packets(bytes)?;
+        let (head, audio) = split_stream(&packets)?;
+        let mut decoder = Decoder::new(sample_rate(rate)?, Channels::Mono).map_err(opus_error)?;
+        let granule_per_sample = u64::from(GRANULE_RATE / rate);
+
+        let mut samples = Vec::new();
+        let mut frame = vec![0i16; (rate * 120 / 1000) as usize];
+        for packet in audio {
+            let input = Packet::try_from(packet.data.as_slice()).map_err(opus_error)?;
+            let output = MutSignals::try_from(frame.as_mut_slice()).map_err(opus_error)?;
+            let len = decoder
+                .decode(Some(input), output, false)
+                .map_err(opus_error)?;
+            samples.extend_from_slice(&frame[..len]);
+        }
+
+        let pre_skip = (u64::from(head.pre_skip) / granule_per_sample) as usize;
+        let end = audio
+            .last()
+            .map_or(0, |p| (p.granule / granule_per_sample) as usize);
+        samples.truncate(end);
+        samples.drain(..pre_skip.min(samples.len()));
+        Ok(samples)
+    }
+}
+
+#[cfg(feature = "opus")]
+pub use codec::{decode, encode};
+
+#[cfg(not(feature = "opus"))]
+pub fn encode(_samples: &[i16], _rate: u32) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the `opus` feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ogg::{read_packets, OggWriter};
+    use super::*;
+
+    #[test]
+    fn test_head_round_trip() {
+        let head = OpusHead {
+            channels: 1,
+            pre_skip: 312,
+            input_sample_rate: 16000,
+        };
+        let bytes = head.to_bytes();
+        assert_eq!(bytes.len(), 19);
+        assert_eq!(OpusHead::parse(&bytes).unwrap(), head);
+        assert!(OpusHead::parse(b"OpusTags").is_err());
+    }
+
+    #[test]
+    fn test_stream_headers() {
+        let head = OpusHead {
+            channels: 1,
+            pre_skip: 0,
+            input_sample_rate: 16000,
+        };
+        let mut writer = OggWriter::new(Vec::new(), 7);
+        writer.write_packet(&head.to_bytes(), 0).unwrap();
+        writer.flush_page(false).unwrap();
+        writer.write_packet(&opus_tags("test"), 0).unwrap();
+        writer.flush_page(false).unwrap();
+        writer.write_packet(&[0xf8, 0xff, 0xfe], 960).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let packets = read_packets(&bytes).unwrap();
+        let (parsed, audio) = split_stream(&packets).unwrap();
+        assert_eq!(parsed, head);
+        assert_eq!(audio.len(), 1);
+        assert_eq!(audio[0].granule, 960);
+
+        assert!(split_stream(&packets[1..]).is_err());
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_opus_round_trip() {
+        let input: Vec<i16> = (0..16000)
+            .map(|i| {
+                (8000.0 * (i as f32 * 2.0 * std::f32::consts::PI * 440.0 / 16000.0).sin()) as i16
+            })
+            .collect();
+        let bytes = encode(&input, 16000).unwrap();
+        assert!(bytes.len() < input.len() * 2 / 8);
+
+        let output = decode(&bytes, 16000).unwrap();
+        assert_eq!(output.len(), input.len());
+        let input_levels = crate::level::AudioLevels::from_samples(&input);
+        let output_levels = crate::level::AudioLevels::from_samples(&output);
+        assert!((input_levels.rms - output_levels.rms).abs() < 0.05);
+    }
+
+    #[cfg(not(feature = "opus"))]
+    #[test]
+    fn test_encode_without_feature() {
+        let error = encode(&[0; 320], 16000).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,414 @@
+//! RIFF/WAVE reading and writing for 16-bit PCM and IMA ADPCM mono audio.
+
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::adpcm;
+
+pub const FORMAT_PCM: u16 = 0x0001;
+pub const FORMAT_IMA_ADPCM: u16 = 0x0011;
+
+/// Format of a parsed WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavInfo {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub block_align: u16,
+}
+
+/// Write 16-bit mono PCM with the canonical 44 byte header.
+pub fn write_pcm<W: Write>(mut writer: W, samples: &[i16], sample_rate: u32) -> io::Result<()> {
+    let data_size = (samples.len() * 2) as u32;
+
+    // RIFF header
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    // fmt chunk
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&FORMAT_PCM.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+
+    // data chunk
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Write mono IMA ADPCM (4 bits per sample) with a `fact` chunk holding the sample count.
+pub fn write_ima_adpcm<W: Write>(
+    mut writer: W,
+    samples: &[i16],
+    sample_rate: u32,
+) -> io::Result<()> {
+    let block_align = adpcm::BLOCK_ALIGN;
+    let samples_per_block = adpcm::samples_per_block(block_align);
+    let data = adpcm::encode(samples, block_align);
+    let data_size = data.len() as u32;
+    let padding = data.len() % 2;
+    let avg_bytes_per_sec = sample_rate * block_align as u32 / samples_per_block as u32;
+
+    // RIFF header: "WAVE" + fmt (8 + 20) + fact (8 + 4) + data (8 + size)
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(4 + 28 + 12 + 8 + data_size + padding as u32).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    // fmt chunk with the IMA ADPCM extension
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&20u32.to_le_bytes())?;
+    writer.write_all(&FORMAT_IMA_ADPCM.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&avg_bytes_per_sec.to_le_bytes())?;
+    writer.write_all(&(block_align as u16).to_le_bytes())?;
+    writer.write_all(&4u16.to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&(samples_per_block as u16).to_le_bytes())?;
+
+    // fact chunk
+    writer.write_all(b"fact")?;
+    writer.write_all(&4u32.to_le_bytes())?;
+    writer.write_all(&(samples.len() as u32).to_le_bytes())?;
+
+    // data chunk, padded to an even length as RIFF requires
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.write_all(&data)?;
+    if padding != 0 {
+        writer.write_all(&[0])?;
+    }
+    writer.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Bytes read from the start of a file looking for its data chunk; our headers take 60.
+const MAX_HEADER_LEN: u64 = 1024;
+
+/// Where the sizes a truncated file gets wrong are stored.
+struct Layout {
+    info: WavInfo,
+    /// Offset of the `fact` chunk's sample count.
+    fact_offset: Option<usize>,
+    /// Offset of the `data` chunk's header.
+    data_offset: usize,
+}
+
+/// Walk the chunks up to the data chunk's header, which may claim more data than follows.
+fn layout(header: &[u8]) -> io::Result<Layout> {
+    if header.len() < 12 || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+    let mut info = None;
+    let mut fact_offset = None;
+    let mut offset = 12;
+    while offset + 8 <= header.len() {
+        let id = &header[offset..offset + 4];
+        let size = read_u32(header, offset + 4) as usize;
+        let body_start = offset + 8;
+        if id == b"data" {
+            let info = info.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+            return Ok(Layout {
+                info,
+                fact_offset,
+                data_offset: offset,
+            });
+        }
+        let body_end = body_start
+            .checked_add(size)
+            .filter(|&end| end <= header.len())
+            .ok_or_else(|| invalid("header cut short"))?;
+        let body = &header[body_start..body_end];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                info = Some(WavInfo {
+                    format_tag: read_u16(body, 0),
+                    channels: read_u16(body, 2),
+                    sample_rate: read_u32(body, 4),
+                    block_align: read_u16(body, 12),
+                    bits_per_sample: read_u16(body, 14),
+                });
+            }
+            b"fmt " => return Err(invalid("fmt chunk too short")),
+            b"fact" if body.len() >= 4 => fact_offset = Some(body_start),
+            _ => {}
+        }
+        offset = body_end + size % 2;
+    }
+    Err(invalid("no data chunk"))
+}
+
+/// Fix the sizes in the header of a WAV file cut short, e.g. by a power cut while it
+/// was written, keeping the whole blocks that made it to disk.
+///
+/// Returns `false` if the file was intact. Fails with `InvalidData` if not even the
+/// header survived.
+pub fn repair_file(path: &Path) -> io::Result<bool> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut header = Vec::new();
+    (&mut file).take(MAX_HEADER_LEN).read_to_end(&mut header)?;
+    let layout = layout(&header)?;
+
+    let riff_size = u64::from(read_u32(&header, 4));
+    let data_start = (layout.data_offset + 8) as u64;
+    let claimed = u64::from(read_u32(&header, layout.data_offset + 4));
+    if data_start + claimed <= len && riff_size + 8 <= len {
+        return Ok(false);
+    }
+
+    // A partial sample or ADPCM block is of no use.
+    let block_align = u64::from(layout.info.block_align.max(1));
+    let available = len - data_start;
+    let cut = available < claimed;
+    let data_size = if cut {
+        available / block_align * block_align
+    } else {
+        claimed
+    };
+    let padding = data_size % 2;
+    let new_len = data_start + data_size + padding;
+    file.set_len(new_len)?;
+
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((new_len - 8) as u32).to_le_bytes())?;
+    file.seek(SeekFrom::Start(layout.data_offset as u64 + 4))?;
+    file.write_all(&(data_size as u32).to_le_bytes())?;
+    if let (true, Some(fact_offset), FORMAT_IMA_ADPCM) =
+        (cut, layout.fact_offset, layout.info.format_tag)
+    {
+        let blocks = data_size / block_align;
+        let samples = blocks * adpcm::samples_per_block(block_align as usize) as u64;
+        let fact_samples = u64::from(read_u32(&header, fact_offset)).min(samples);
+        file.seek(SeekFrom::Start(fact_offset as u64))?;
+        file.write_all(&(fact_samples as u32).to_le_bytes())?;
+    }
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// Read a mono PCM or IMA ADPCM WAV file and decode it to 16-bit samples.
+pub fn read<R: Read>(mut reader: R) -> io::Result<(WavInfo, Vec<i16>)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+
+    let mut info = None;
+    let mut fact_samples = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(&bytes, offset + 4) as usize;
+        let body_start = offset + 8;
+        let body_end = body_start
+            .checked_add(size)
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| invalid("chunk extends past end of file"))?;
+        let body = &bytes[body_start..body_end];
+
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid("fmt chunk too short"));
+                }
+                info = Some(WavInfo {
+                    format_tag: read_u16(body, 0),
+                    channels: read_u16(body, 2),
+                    sample_rate: read_u32(body, 4),
+                    block_align: read_u16(body, 12),
+                    bits_per_sample: read_u16(body, 14),
+                });
+            }
+            b"fact" if body.len() >= 4 => fact_samples = Some(read_u32(body, 0) as usize),
+            b"data" => {
+                let info = info.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                if info.channels != 1 {
+                    return Err(invalid("only mono files are supported"));
+                }
+                let samples = match (info.format_tag, info.bits_per_sample) {
+                    (FORMAT_PCM, 16) => body
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                        .collect(),
+                    (FORMAT_IMA_ADPCM, 4) => {
+                        let block_align = usize::from(info.block_align);
+                        if block_align <= 4 {
+                            return Err(invalid("invalid ADPCM block size"));
+                        }
+                        // Without a fact chunk every code in the data is a sample.
+                        let count = fact_samples.unwrap_or(usize::MAX);
+                        adpcm::decode(body, block_align, count)
+                    }
+                    _ => return Err(invalid("unsupported WAV encoding")),
+                };
+                return Ok((info, samples));
+            }
+            _ => {}
+        }
+        // Chunks are word aligned.
+        offset = body_end + size % 2;
+    }
+    Err(invalid("no data chunk"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn ramp(len: usize) -> Vec<i16> {
+        (0..len).map(|i| ((i * 37) % 2000) as i16 - 1000).collect()
+    }
+
+    #[test]
+    fn test_pcm_header() {
+        let mut bytes = Vec::new();
+        write_pcm(&mut bytes, &[1, -1], 16000).unwrap();
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(read_u32(&bytes, 4), 36 + 4);
+        assert_eq!(read_u16(&bytes, 20), FORMAT_PCM);
+        assert_eq!(read_u32(&bytes, 24), 16000);
+        assert_eq!(read_u32(&bytes, 40), 4);
+    }
+
+    #[test]
+    fn test_pcm_round_trip() {
+        let input = ramp(1001);
+        let mut bytes = Vec::new();
+        write_pcm(&mut bytes, &input, 16000).unwrap();
+
+        let (info, output) = read(bytes.as_slice()).unwrap();
+        assert_eq!(info.format_tag, FORMAT_PCM);
+        assert_eq!(info.sample_rate, 16000);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_adpcm_round_trip() {
+        let input: Vec<i16> = (0..3001)
+            .map(|i| (6000.0 * (i as f32 * 0.03).sin()) as i16)
+            .collect();
+        let mut bytes = Vec::new();
+        write_ima_adpcm(&mut bytes, &input, 16000).unwrap();
+        assert_eq!(bytes.len() % 2, 0);
+        assert_eq!(read_u32(&bytes, 4) as usize, bytes.len() - 8);
+        // Roughly a quarter of the PCM size.
+        assert!(bytes.len() < input.len() * 2 / 3);
+
+        let (info, output) = read(bytes.as_slice()).unwrap();
+        assert_eq!(info.format_tag, FORMAT_IMA_ADPCM);
+        assert_eq!(info.bits_per_sample, 4);
+        assert_eq!(info.block_align as usize, adpcm::BLOCK_ALIGN);
+        assert_eq!(output.len(), input.len());
+        assert!(input
+            .iter()
+            .zip(&output)
+            .skip(100)
+            .all(|(a, b)| (i32::from(*a) - i32::from(*b)).abs() < 400));
+    }
+
+    /// Offsets spread over the whole file, the same on every run.
+    fn offsets(len: usize, count: usize) -> Vec<usize> {
+        let mut state = 0x2545_f491_u32;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as usize % len
+            })
+            .collect()
+    }
+
+    fn check_repair(name: &str, bytes: &[u8], data_start: usize, samples: &[i16]) {
+        let dir = TempDir::new("wav-repair");
+        let path = dir.0.join(format!("{}.wav", name));
+        // Intact files are left alone.
+        std::fs::write(&path, bytes).unwrap();
+        assert!(!repair_file(&path).unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        for cut in offsets(bytes.len(), 200) {
+            std::fs::write(&path, &bytes[..cut]).unwrap();
+            let repaired = repair_file(&path);
+            if cut < data_start {
+                let error = repaired.unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData, "cut at {}", cut);
+                continue;
+            }
+            assert!(repaired.unwrap(), "cut at {}", cut);
+            let repaired = std::fs::read(&path).unwrap();
+            assert_eq!(read_u32(&repaired, 4) as usize, repaired.len() - 8);
+            let (_, decoded) = read(repaired.as_slice()).unwrap();
+            assert!(decoded.len() <= samples.len(), "cut at {}", cut);
+            assert_eq!(decoded[..], samples[..decoded.len()], "cut at {}", cut);
+            // Nothing but the partial last sample or block is lost.
+            let kept = repaired.len() - data_start;
+            assert!(
+                cut - data_start - kept < adpcm::BLOCK_ALIGN,
+                "cut at {}",
+                cut
+            );
+            assert!(!repair_file(&path).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_repair_truncated_pcm() {
+        let input = ramp(5001);
+        let mut bytes = Vec::new();
+        write_pcm(&mut bytes, &input, 16000).unwrap();
+        check_repair("pcm", &bytes, 44, &input);
+    }
+
+    #[test]
+    fn test_repair_truncated_adpcm() {
+        let input = ramp(5001);
+        let mut bytes = Vec::new();
+        write_ima_adpcm(&mut bytes, &input, 16000).unwrap();
+        // Decoding the intact file gives the reference for every cut.
+        let (_, decoded) = read(bytes.as_slice()).unwrap();
+        check_repair("adpcm", &bytes, 60, &decoded);
+    }
+
+    #[test]
+    fn test_rejects_garbage() {
+        assert!(read(&b"RIFF\0\0\0\0WAVE"[..]).is_err());
+        assert!(read(&b"not a wav file"[..]).is_err());
+
+        let mut bytes = Vec::new();
+        write_pcm(&mut bytes, &ramp(100), 16000).unwrap();
+        bytes.truncate(60);
+        assert!(read(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,442 @@
+//! Allocation-free preprocessing for microphone audio.
+//!
+//! Every stage works in place on 16-bit samples and keeps its state between blocks,
+//! so a chain can be fed straight from the I2S read loop:
+//!
+//! ```
+//! use slint_workshop_model::dsp::{voice_chain, Processor};
+//!
+//! let mut chain = voice_chain(16000);
+//! let mut block = [0i16; 512];
+//! chain.process(&mut block);
+//! ```
+
+use std::f32::consts::PI;
+
+/// A stage in the preprocessing chain.
+pub trait Processor {
+    /// Process `samples` in place.
+    fn process(&mut self, samples: &mut [i16]);
+
+    /// Forget all state, e.g. before a new recording.
+    fn reset(&mut self);
+
+    /// Run `next` on the output of this stage.
+    fn then<P: Processor>(self, next: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+/// Two stages run one after the other. Built with [`Processor::then`].
+#[derive(Debug, Clone)]
+pub struct Chain<A, B> {
+    pub first: A,
+    pub second: B,
+}
+
+impl<A: Processor, B: Processor> Processor for Chain<A, B> {
+    fn process(&mut self, samples: &mut [i16]) {
+        self.first.process(samples);
+        self.second.process(samples);
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+/// A disabled stage (`None`) passes samples through unchanged.
+impl<P: Processor> Processor for Option<P> {
+    fn process(&mut self, samples: &mut [i16]) {
+        if let Some(stage) = self {
+            stage.process(samples);
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(stage) = self {
+            stage.reset();
+        }
+    }
+}
+
+/// The chain used for speech: DC blocker, 80 Hz high-pass, AGC and noise gate.
+pub type VoiceChain = Chain<Chain<Chain<DcBlocker, Biquad>, Gain>, NoiseGate>;
+
+pub fn voice_chain(sample_rate: u32) -> VoiceChain {
+    DcBlocker::new()
+        .then(Biquad::high_pass(
+            sample_rate,
+            80.0,
+            std::f32::consts::FRAC_1_SQRT_2,
+        ))
+        .then(Gain::Agc(Agc::new(sample_rate, 0.1, 20.0)))
+        .then(NoiseGate::new(sample_rate, 0.01))
+}
+
+fn to_sample(value: f32) -> i16 {
+    value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Smoothing coefficient for a one-pole filter with the given time constant.
+fn time_coefficient(sample_rate: u32, seconds: f32) -> f32 {
+    1.0 - (-1.0 / (seconds * sample_rate as f32)).exp()
+}
+
+/// Removes a constant offset: `y[n] = x[n] - x[n-1] + r * y[n-1]`.
+#[derive(Debug, Clone)]
+pub struct DcBlocker {
+    r: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl DcBlocker {
+    pub fn new() -> Self {
+        Self::with_pole(0.995)
+    }
+
+    /// Pole close to 1.0 gives a lower cutoff and a slower settling time.
+    pub fn with_pole(r: f32) -> Self {
+        Self {
+            r,
+            x1: 0.0,
+            y1: 0.0,
+        }
+    }
+}
+
+impl Default for DcBlocker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor for DcBlocker {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let x = f32::from(*sample);
+            let y = x - self.x1 + self.r * self.y1;
+            self.x1 = x;
+            self.y1 = y;
+            *sample = to_sample(y);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.y1 = 0.0;
+    }
+}
+
+/// Second-order IIR filter (transposed direct form II).
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// High-pass filter from the RBJ audio EQ cookbook.
+    pub fn high_pass(sample_rate: u32, cutoff_hz: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate as f32;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos_w0) / 2.0 / a0,
+            b1: -(1.0 + cos_w0) / a0,
+            b2: (1.0 + cos_w0) / 2.0 / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+}
+
+impl Processor for Biquad {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let x = f32::from(*sample);
+            let y = self.b0 * x + self.z1;
+            self.z1 = self.b1 * x - self.a1 * y + self.z2;
+            self.z2 = self.b2 * x - self.a2 * y;
+            *sample = to_sample(y);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+/// Gain stage: either a fixed factor or automatic gain control.
+#[derive(Debug, Clone)]
+pub enum Gain {
+    Fixed(f32),
+    Agc(Agc),
+}
+
+impl Gain {
+    pub fn from_db(db: f32) -> Self {
+        Gain::Fixed(10f32.powf(db / 20.0))
+    }
+}
+
+impl Processor for Gain {
+    fn process(&mut self, samples: &mut [i16]) {
+        match self {
+            Gain::Fixed(gain) => {
+                for sample in samples {
+                    *sample = to_sample(f32::from(*sample) * *gain);
+                }
+            }
+            Gain::Agc(agc) => agc.process(samples),
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Gain::Agc(agc) = self {
+            agc.reset();
+        }
+    }
+}
+
+/// Automatic gain control pulling the RMS level towards a target.
+///
+/// Gain drops quickly on loud input and recovers slowly, so speech onsets do not clip.
+#[derive(Debug, Clone)]
+pub struct Agc {
+    target_rms: f32,
+    max_gain: f32,
+    gain: f32,
+    power: f32,
+    detector_coeff: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+}
+
+impl Agc {
+    /// `target_rms` is relative to full scale, `max_gain` is a linear factor.
+    pub fn new(sample_rate: u32, target_rms: f32, max_gain: f32) -> Self {
+        Self {
+            target_rms: target_rms * i16::MAX as f32,
+            max_gain,
+            gain: 1.0,
+            power: 0.0,
+            detector_coeff: time_coefficient(sample_rate, 0.1),
+            attack_coeff: time_coefficient(sample_rate, 0.01),
+            release_coeff: time_coefficient(sample_rate, 1.0),
+        }
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+}
+
+impl Processor for Agc {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let x = f32::from(*sample);
+            self.power += self.detector_coeff * (x * x - self.power);
+
+            let rms = self.power.sqrt().max(1.0);
+            let desired = (self.target_rms / rms).min(self.max_gain);
+            let coeff = if desired < self.gain {
+                self.attack_coeff
+            } else {
+                self.release_coeff
+            };
+            self.gain += coeff * (desired - self.gain);
+
+            *sample = to_sample(x * self.gain);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.gain = 1.0;
+        self.power = 0.0;
+    }
+}
+
+/// Mutes the signal while its envelope stays below a threshold.
+#[derive(Debug, Clone)]
+pub struct NoiseGate {
+    threshold: f32,
+    envelope: f32,
+    gain: f32,
+    hold_samples: u32,
+    hold_remaining: u32,
+    envelope_release: f32,
+    open_coeff: f32,
+    close_coeff: f32,
+}
+
+impl NoiseGate {
+    /// `threshold` is relative to full scale.
+    pub fn new(sample_rate: u32, threshold: f32) -> Self {
+        Self {
+            threshold: threshold * i16::MAX as f32,
+            envelope: 0.0,
+            gain: 0.0,
+            hold_samples: sample_rate / 10,
+            hold_remaining: 0,
+            envelope_release: time_coefficient(sample_rate, 0.05),
+            open_coeff: time_coefficient(sample_rate, 0.002),
+            close_coeff: time_coefficient(sample_rate, 0.05),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.hold_remaining > 0
+    }
+}
+
+impl Processor for NoiseGate {
+    fn process(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let x = f32::from(*sample);
+            let magnitude = x.abs();
+            if magnitude > self.envelope {
+                self.envelope = magnitude;
+            } else {
+                self.envelope += self.envelope_release * (magnitude - self.envelope);
+            }
+
+            if self.envelope > self.threshold {
+                self.hold_remaining = self.hold_samples;
+            } else {
+                self.hold_remaining = self.hold_remaining.saturating_sub(1);
+            }
+
+            let (target, coeff) = if self.hold_remaining > 0 {
+                (1.0, self.open_coeff)
+            } else {
+                (0.0, self.close_coeff)
+            };
+            self.gain += coeff * (target - self.gain);
+
+            *sample = to_sample(x * self.gain);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+        self.gain = 0.0;
+        self.hold_remaining = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::AudioLevels;
+
+    const RATE: u32 = 16000;
+
+    fn sine(freq: f32, amplitude: f32, offset: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let phase = 2.0 * PI * freq * i as f32 / RATE as f32;
+                to_sample(offset + amplitude * i16::MAX as f32 * phase.sin())
+            })
+            .collect()
+    }
+
+    fn mean(samples: &[i16]) -> f32 {
+        samples.iter().map(|&s| f32::from(s)).sum::<f32>() / samples.len() as f32
+    }
+
+    /// Process in small blocks, as the capture loop does, and return the steady-state tail.
+    fn run<P: Processor>(processor: &mut P, mut samples: Vec<i16>) -> Vec<i16> {
+        for block in samples.chunks_mut(256) {
+            processor.process(block);
+        }
+        samples.split_off(samples.len() / 2)
+    }
+
+    #[test]
+    fn test_dc_blocker_removes_offset() {
+        let input = sine(440.0, 0.2, 3000.0, RATE as usize);
+        assert!(mean(&input) > 2900.0);
+
+        let output = run(&mut DcBlocker::new(), input);
+        assert!(mean(&output).abs() < 50.0);
+        let levels = AudioLevels::from_samples(&output);
+        assert!((levels.peak - 0.2).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_high_pass_attenuates_rumble() {
+        let rumble = run(
+            &mut Biquad::high_pass(RATE, 80.0, 0.707),
+            sine(20.0, 0.5, 0.0, RATE as usize),
+        );
+        let voice = run(
+            &mut Biquad::high_pass(RATE, 80.0, 0.707),
+            sine(1000.0, 0.5, 0.0, RATE as usize),
+        );
+        // 20 Hz is two octaves below the cutoff: about -24 dB for a 2nd order filter.
+        assert!(AudioLevels::from_samples(&rumble).rms < 0.5 * 0.1);
+        assert!((AudioLevels::from_samples(&voice).peak - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_fixed_gain_saturates() {
+        let mut samples = vec![1000, -1000, 20000, -20000];
+        Gain::from_db(6.0206).process(&mut samples);
+        assert_eq!(samples, vec![2000, -2000, i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn test_agc_converges_to_target() {
+        let mut agc = Agc::new(RATE, 0.1, 20.0);
+        let quiet = run(&mut agc, sine(440.0, 0.02, 0.0, 4 * RATE as usize));
+        assert!((AudioLevels::from_samples(&quiet).rms - 0.1).abs() < 0.02);
+
+        agc.reset();
+        let loud = run(&mut agc, sine(440.0, 0.8, 0.0, 4 * RATE as usize));
+        assert!((AudioLevels::from_samples(&loud).rms - 0.1).abs() < 0.02);
+        assert!(agc.gain() < 1.0);
+    }
+
+    #[test]
+    fn test_noise_gate() {
+        let mut gate = NoiseGate::new(RATE, 0.05);
+        let noise = run(&mut gate, sine(3000.0, 0.01, 0.0, RATE as usize));
+        assert!(noise.iter().all(|&s| s.abs() < 10));
+        assert!(!gate.is_open());
+
+        let speech = run(&mut gate, sine(440.0, 0.3, 0.0, RATE as usize));
+        assert!(gate.is_open());
+        assert!((AudioLevels::from_samples(&speech).peak - 0.3).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_optional_stage_and_chain() {
+        let mut chain = None::<Gain>.then(Some(Gain::Fixed(2.0)));
+        let mut samples = vec![100, -100];
+        chain.process(&mut samples);
+        assert_eq!(samples, vec![200, -200]);
+
+        let mut voice = voice_chain(RATE);
+        let output = run(&mut voice, sine(440.0, 0.05, 2000.0, 2 * RATE as usize));
+        assert!(mean(&output).abs() < 100.0);
+        voice.reset();
+    }
+}
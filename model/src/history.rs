@@ -0,0 +1,578 @@
+//! Past weather readings, kept for the trend page.
+//!
+//! Samples live in a ring buffer in RAM. Once a file is attached they are also appended
+//! to it as 8-byte records: the Unix time as `u32`, the temperature in 0.01 °C as `i16`
+//! and the humidity in 0.01 % as `u16`, all little endian. The file is compacted once it
+//! holds twice the capacity, and a record cut short by a power cut is dropped when the
+//! file is attached again.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::recordings::{recover_atomic, write_atomic};
+use crate::WeatherData;
+
+pub const HISTORY_FILE: &str = "history.bin";
+pub const HOUR: u64 = 60 * 60;
+pub const DAY: u64 = 24 * HOUR;
+/// Weather fetched more often than this is not recorded again.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// A week of samples: 16 KB on the card and twice that in RAM.
+pub const DEFAULT_CAPACITY: usize = 7 * 24 * 12;
+
+const RECORD_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Unix time in seconds.
+    pub time: u64,
+    /// °C.
+    pub temperature: f32,
+    /// Relative humidity in percent.
+    pub humidity: f32,
+}
+
+impl Sample {
+    pub fn new(time: u64, weather: &WeatherData) -> Self {
+        Self {
+            time,
+            temperature: weather.temperature as f32,
+            humidity: weather.humidity as f32,
+        }
+    }
+
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let time = self.time.min(u64::from(u32::MAX)) as u32;
+        // `as` saturates, which is all the clamping these need.
+        let temperature = (self.temperature * 100.0).round() as i16;
+        let humidity = (self.humidity * 100.0).round() as u16;
+        let mut record = [0; RECORD_LEN];
+        record[..4].copy_from_slice(&time.to_le_bytes());
+        record[4..6].copy_from_slice(&temperature.to_le_bytes());
+        record[6..].copy_from_slice(&humidity.to_le_bytes());
+        record
+    }
+
+    fn decode(record: &[u8]) -> Self {
+        Self {
+            time: u64::from(u32::from_le_bytes(record[..4].try_into().unwrap())),
+            temperature: f32::from(i16::from_le_bytes([record[4], record[5]])) / 100.0,
+            humidity: f32::from(u16::from_le_bytes([record[6], record[7]])) / 100.0,
+        }
+    }
+}
+
+/// Minimum, maximum and mean of one quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+}
+
+impl Stats {
+    /// E.g. `12.5–18.0°C, avg 15.2`.
+    pub fn label(&self, unit: &str, decimals: usize) -> String {
+        format!(
+            "{:.*}–{:.*}{unit}, avg {:.*}",
+            decimals, self.min, decimals, self.max, decimals, self.avg
+        )
+    }
+}
+
+/// The samples of one hour, day or other period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    /// Unix time the period starts at.
+    pub start: u64,
+    pub count: usize,
+    pub temperature: Stats,
+    pub humidity: Stats,
+}
+
+/// One quantity over the last hours, ready for a chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trend {
+    /// Hourly averages, oldest first, scaled by [`normalize`].
+    pub values: Vec<f32>,
+    /// Over the same hours; `None` without samples.
+    pub stats: Option<Stats>,
+}
+
+/// Sums up samples as they come, then turns into an [`Aggregate`].
+struct Accumulator {
+    start: u64,
+    count: usize,
+    temperature: (f32, f32, f64),
+    humidity: (f32, f32, f64),
+}
+
+impl Accumulator {
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            count: 0,
+            temperature: (f32::INFINITY, f32::NEG_INFINITY, 0.0),
+            humidity: (f32::INFINITY, f32::NEG_INFINITY, 0.0),
+        }
+    }
+
+    fn add(&mut self, sample: &Sample) {
+        self.count += 1;
+        for (stats, value) in [
+            (&mut self.temperature, sample.temperature),
+            (&mut self.humidity, sample.humidity),
+        ] {
+            stats.0 = stats.0.min(value);
+            stats.1 = stats.1.max(value);
+            stats.2 += f64::from(value);
+        }
+    }
+
+    fn finish(self) -> Aggregate {
+        let stats = |(min, max, sum): (f32, f32, f64)| Stats {
+            min,
+            max,
+            avg: (sum / self.count as f64) as f32,
+        };
+        Aggregate {
+            start: self.start,
+            count: self.count,
+            temperature: stats(self.temperature),
+            humidity: stats(self.humidity),
+        }
+    }
+}
+
+/// Ring buffer of [`Sample`]s, oldest first, optionally mirrored to a file.
+pub struct History {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+    interval: u64,
+    path: Option<PathBuf>,
+    /// Records in the file; it is compacted at twice the capacity.
+    file_records: usize,
+    /// Held while reading or writing the file.
+    storage_lock: Option<Arc<Mutex<()>>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, SAMPLE_INTERVAL)
+    }
+}
+
+impl History {
+    /// Keep up to `capacity` samples in RAM, at least `interval` apart.
+    pub fn new(capacity: usize, interval: Duration) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.as_secs(),
+            path: None,
+            file_records: 0,
+            storage_lock: None,
+        }
+    }
+
+    /// Hold `lock` while using the file, for storage that shares a bus with something
+    /// else, like the SD card and the display on the ESP32.
+    pub fn with_storage_lock(mut self, lock: Arc<Mutex<()>>) -> Self {
+        self.storage_lock = Some(lock);
+        self
+    }
+
+    /// Load the samples saved in `path` before the ones in RAM, and save new ones there.
+    /// The file is rewritten with the result, which also drops a truncated record, and
+    /// restored first if a power cut interrupted the last rewrite.
+    pub fn attach(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+        let storage_lock = self.storage_lock.clone();
+        let _storage = storage_lock.as_ref().map(|lock| lock.lock().unwrap());
+        recover_atomic(&path)?;
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if bytes.len() % RECORD_LEN != 0 {
+            log::warn!("Dropping a truncated record from {}", path.display());
+        }
+        let first_in_ram = self.samples.front().map(|sample| sample.time);
+        let saved: Vec<Sample> = bytes
+            .chunks_exact(RECORD_LEN)
+            .map(Sample::decode)
+            .filter(|sample| match first_in_ram {
+                Some(first) => sample.time < first,
+                None => true,
+            })
+            .collect();
+        for sample in saved.into_iter().rev() {
+            if self.samples.len() == self.capacity {
+                break;
+            }
+            self.samples.push_front(sample);
+        }
+        self.path = Some(path);
+        self.rewrite()
+    }
+
+    /// Stop saving, e.g. when the SD card was removed; the samples stay in RAM.
+    pub fn detach(&mut self) {
+        self.path = None;
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Add a sample, unless it comes within the interval of the last one. Returns
+    /// whether it was added; a failure to save it still keeps it in RAM.
+    pub fn push(&mut self, sample: Sample) -> io::Result<bool> {
+        if let Some(last) = self.samples.back() {
+            if sample.time < last.time + self.interval {
+                return Ok(false);
+            }
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        let Some(path) = &self.path else {
+            return Ok(true);
+        };
+        let storage_lock = self.storage_lock.clone();
+        let _storage = storage_lock.as_ref().map(|lock| lock.lock().unwrap());
+        if self.file_records >= 2 * self.capacity {
+            self.rewrite()?;
+        } else {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            file.write_all(&sample.encode())?;
+            self.file_records += 1;
+        }
+        Ok(true)
+    }
+
+    /// The caller holds the storage lock.
+    fn rewrite(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let bytes: Vec<u8> = self.samples.iter().flat_map(Sample::encode).collect();
+        write_atomic(path, &bytes)?;
+        self.file_records = self.samples.len();
+        Ok(())
+    }
+
+    /// Oldest first.
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &Sample> {
+        self.samples.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Aggregate the samples from `since` on into periods of `period` seconds, skipping
+    /// periods without samples. Periods start at multiples of `period` in the time zone
+    /// `utc_offset` seconds east of UTC.
+    pub fn aggregate(&self, since: u64, period: u64, utc_offset: i32) -> Vec<Aggregate> {
+        let offset = i64::from(utc_offset);
+        let period_start = |time: u64| {
+            let local = time as i64 + offset;
+            (local - local.rem_euclid(period as i64) - offset) as u64
+        };
+        let mut aggregates = Vec::new();
+        let mut current: Option<Accumulator> = None;
+        for sample in self.samples.iter().filter(|sample| sample.time >= since) {
+            let start = period_start(sample.time);
+            match &mut current {
+                Some(acc) if acc.start == start => {}
+                _ => {
+                    aggregates.extend(current.take().map(Accumulator::finish));
+                    current = Some(Accumulator::new(start));
+                }
+            }
+            current.as_mut().unwrap().add(sample);
+        }
+        aggregates.extend(current.map(Accumulator::finish));
+        aggregates
+    }
+
+    /// One slot per hour for the `hours` hours up to and including the one `now` is in,
+    /// `None` for hours without samples.
+    pub fn hourly(&self, now: u64, hours: usize) -> Vec<Option<Aggregate>> {
+        let start = first_hour(now, hours);
+        let mut slots = vec![None; hours];
+        for aggregate in self.aggregate(start, HOUR, 0) {
+            if let Some(slot) = slots.get_mut(((aggregate.start - start) / HOUR) as usize) {
+                *slot = Some(aggregate);
+            }
+        }
+        slots
+    }
+
+    /// Local days from `since` on.
+    pub fn daily(&self, since: u64, utc_offset: i32) -> Vec<Aggregate> {
+        self.aggregate(since, DAY, utc_offset)
+    }
+
+    /// `quantity` of the hourly aggregates over the `hours` hours up to `now`, as the
+    /// trend page shows it.
+    pub fn trend(&self, now: u64, hours: usize, quantity: fn(&Aggregate) -> Stats) -> Trend {
+        let slots = self.hourly(now, hours);
+        let averages: Vec<Option<f32>> = slots
+            .iter()
+            .map(|slot| slot.as_ref().map(|aggregate| quantity(aggregate).avg))
+            .collect();
+        let since = first_hour(now, hours);
+        Trend {
+            values: normalize(&averages),
+            stats: self.summary(since).map(|aggregate| quantity(&aggregate)),
+        }
+    }
+
+    /// Everything from `since` on as a single aggregate, `None` without samples.
+    pub fn summary(&self, since: u64) -> Option<Aggregate> {
+        let mut acc = Accumulator::new(since);
+        for sample in self.samples.iter().filter(|sample| sample.time >= since) {
+            acc.add(sample);
+        }
+        (acc.count > 0).then(|| acc.finish())
+    }
+}
+
+/// Start of the first of `hours` hours that end with the one `now` is in.
+fn first_hour(now: u64, hours: usize) -> u64 {
+    (now / HOUR + 1).saturating_sub(hours as u64) * HOUR
+}
+
+/// Scale `values` to 0..=1 between their minimum and maximum for a bar chart. Gaps
+/// become -1 so the chart can leave them out; a flat line sits at 0.5.
+pub fn normalize(values: &[Option<f32>]) -> Vec<f32> {
+    let (min, max) = values
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+            (min.min(value), max.max(value))
+        });
+    values
+        .iter()
+        .map(|value| match value {
+            Some(_) if max <= min => 0.5,
+            Some(value) => (value - min) / (max - min),
+            None => -1.0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MIN_VALID_UNIX;
+    use crate::test_util::TempDir;
+
+    fn sample(time: u64, temperature: f32) -> Sample {
+        Sample {
+            time,
+            temperature,
+            humidity: 50.0 + temperature,
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let original = Sample {
+            time: 1_700_000_000,
+            temperature: -12.34,
+            humidity: 87.65,
+        };
+        let decoded = Sample::decode(&original.encode());
+        assert_eq!(decoded.time, original.time);
+        assert!((decoded.temperature - original.temperature).abs() < 0.005);
+        assert!((decoded.humidity - original.humidity).abs() < 0.005);
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut history = History::new(3, Duration::from_secs(60));
+        for i in 0..5 {
+            assert!(history
+                .push(sample(MIN_VALID_UNIX + i * 60, i as f32))
+                .unwrap());
+        }
+        // Too soon after the last one, or the clock went back.
+        assert!(!history
+            .push(sample(MIN_VALID_UNIX + 4 * 60 + 59, 9.0))
+            .unwrap());
+        assert!(!history.push(sample(MIN_VALID_UNIX, 9.0)).unwrap());
+        let temperatures: Vec<f32> = history.samples().map(|s| s.temperature).collect();
+        assert_eq!(temperatures, [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_file() {
+        let dir = TempDir::new("file");
+        let path = dir.0.join(HISTORY_FILE);
+        let start = MIN_VALID_UNIX;
+
+        let mut history = History::new(4, Duration::ZERO);
+        history.push(sample(start, 1.0)).unwrap();
+        history.attach(&path).unwrap();
+        for i in 1..10 {
+            history.push(sample(start + i, i as f32)).unwrap();
+        }
+        // Compacted once it reached twice the capacity.
+        assert!(std::fs::metadata(&path).unwrap().len() <= 8 * RECORD_LEN as u64);
+
+        // A power cut in the middle of a record.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        drop(file);
+
+        // Samples taken while the card was out come after the saved ones.
+        let mut history = History::new(4, Duration::ZERO);
+        history.push(sample(start + 20, 20.0)).unwrap();
+        history.attach(&path).unwrap();
+        let temperatures: Vec<f32> = history.samples().map(|s| s.temperature).collect();
+        assert_eq!(temperatures, [7.0, 8.0, 9.0, 20.0]);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            4 * RECORD_LEN as u64
+        );
+
+        history.detach();
+        history.push(sample(start + 21, 21.0)).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            4 * RECORD_LEN as u64
+        );
+
+        // A power cut between deleting the old file and renaming the new one.
+        std::fs::rename(&path, dir.0.join("history.bin.tmp")).unwrap();
+        let mut history = History::new(4, Duration::ZERO);
+        history.attach(&path).unwrap();
+        assert_eq!(history.len(), 4);
+    }
+
+    #[test]
+    fn test_storage_lock() {
+        let dir = TempDir::new("history-lock");
+        let path = dir.0.join(HISTORY_FILE);
+        let storage_lock = Arc::new(Mutex::new(()));
+        let mut history = History::new(4, Duration::ZERO).with_storage_lock(storage_lock.clone());
+        history.attach(&path).unwrap();
+
+        let bus = storage_lock.lock().unwrap();
+        let (pushed_tx, pushed_rx) = std::sync::mpsc::channel();
+        let pushing = std::thread::spawn(move || {
+            history.push(sample(MIN_VALID_UNIX, 1.0)).unwrap();
+            pushed_tx.send(()).unwrap();
+        });
+        assert!(
+            pushed_rx.recv_timeout(Duration::from_millis(200)).is_err(),
+            "saving waits for the storage"
+        );
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        drop(bus);
+        pushed_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        pushing.join().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), RECORD_LEN as u64);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        // Midnight UTC.
+        let midnight = 1_700_006_400;
+        let mut history = History::new(100, Duration::ZERO);
+        for (offset, temperature) in [(0, 10.0), (1800, 14.0), (3600, 20.0), (DAY + 60, -5.0)] {
+            history
+                .push(sample(midnight + offset, temperature))
+                .unwrap();
+        }
+
+        let hours = history.aggregate(midnight, HOUR, 0);
+        assert_eq!(hours.len(), 3);
+        assert_eq!(hours[0].start, midnight);
+        assert_eq!(hours[0].count, 2);
+        assert_eq!(
+            hours[0].temperature,
+            Stats {
+                min: 10.0,
+                max: 14.0,
+                avg: 12.0
+            }
+        );
+        assert_eq!(hours[0].humidity.avg, 62.0);
+        assert_eq!(hours[1].start, midnight + HOUR);
+        assert_eq!(hours[2].start, midnight + DAY);
+
+        let days = history.daily(0, 0);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].count, 3);
+        assert_eq!(days[0].temperature.max, 20.0);
+        assert_eq!(days[1].temperature.min, -5.0);
+
+        // Five hours behind UTC the first three samples fall on the previous day.
+        let days = history.daily(0, -5 * 3600);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].start, midnight - DAY + 5 * HOUR);
+        assert_eq!(days[0].count, 3);
+
+        assert_eq!(hours[0].temperature.label("°C", 1), "10.0–14.0°C, avg 12.0");
+        assert_eq!(hours[0].humidity.label("%", 0), "60–64%, avg 62");
+
+        let summary = history.summary(midnight + 1).unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.temperature.min, -5.0);
+        assert!(history.summary(midnight + 2 * DAY).is_none());
+    }
+
+    #[test]
+    fn test_hourly() {
+        let midnight = 1_700_006_400;
+        let mut history = History::new(100, Duration::ZERO);
+        history.push(sample(midnight - HOUR, 1.0)).unwrap();
+        history.push(sample(midnight + 2 * HOUR + 5, 3.0)).unwrap();
+
+        let slots = history.hourly(midnight + 2 * HOUR + 10, 3);
+        assert_eq!(slots.len(), 3);
+        assert!(slots[0].is_none());
+        assert!(slots[1].is_none());
+        assert_eq!(slots[2].unwrap().temperature.avg, 3.0);
+
+        let slots = history.hourly(midnight + 2 * HOUR + 10, 4);
+        assert_eq!(slots[0].unwrap().temperature.avg, 1.0);
+
+        let trend = history.trend(midnight + 2 * HOUR + 10, 4, |a| a.temperature);
+        assert_eq!(trend.values, [0.0, -1.0, -1.0, 1.0]);
+        assert_eq!(trend.stats.unwrap().max, 3.0);
+        let trend = history.trend(midnight + 2 * HOUR + 10, 3, |a| a.humidity);
+        assert_eq!(trend.values, [-1.0, -1.0, 0.5]);
+        assert_eq!(trend.stats.unwrap().avg, 53.0);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(&[Some(10.0), None, Some(20.0), Some(15.0)]),
+            [0.0, -1.0, 1.0, 0.5]
+        );
+        assert_eq!(normalize(&[Some(3.0), Some(3.0)]), [0.5, 0.5]);
+        assert!(normalize(&[None]).iter().all(|&value| value < 0.0));
+    }
+}
@@ -0,0 +1,144 @@
+//! A local HTTP server replaying canned responses, for testing the clients.
+
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::JoinHandle;
+
+use super::std_client::{find_header, read_body, read_head};
+use super::{StdHttpClient, TlsConfig};
+
+/// A request as received by [`MockServer`].
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+/// Answers one connection per canned response, in order, then stops.
+pub(crate) struct MockServer {
+    url: String,
+    handle: JoinHandle<Vec<RecordedRequest>>,
+}
+
+impl MockServer {
+    /// Start serving `responses`, each a complete raw HTTP response.
+    pub fn start(responses: Vec<Vec<u8>>) -> Self {
+        Self::serve("http", responses, |stream| Box::new(stream))
+    }
+
+    /// Like [`Self::start`], over TLS with a fresh self-signed certificate for
+    /// `127.0.0.1`. Returns the certificate as PEM.
+    #[cfg(feature = "tls")]
+    pub fn start_https(responses: Vec<Vec<u8>>) -> (Self, String) {
+        use std::sync::Arc;
+
+        use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+        let certified = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = Arc::new(
+            rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(vec![certified.cert.der().clone()], key)
+                .unwrap(),
+        );
+        let server = Self::serve("https", responses, move |stream| {
+            let connection = rustls::ServerConnection::new(config.clone()).unwrap();
+            Box::new(rustls::StreamOwned::new(connection, stream))
+        });
+        (server, certified.cert.pem())
+    }
+
+    /// A client that may talk to [`Self::start`]'s plain-HTTP servers.
+    pub fn client() -> StdHttpClient {
+        StdHttpClient::new(TlsConfig {
+            allow_http: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn serve(
+        scheme: &str,
+        responses: Vec<Vec<u8>>,
+        wrap: impl Fn(TcpStream) -> Box<dyn Connection> + Send + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("{}://{}", scheme, listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(wrap(stream));
+                // A client rejecting the certificate hangs up during the handshake.
+                let Ok((start, headers)) = read_head(&mut reader) else {
+                    continue;
+                };
+                let body = read_body(&mut reader, &headers).unwrap();
+                let mut parts = start.split_whitespace();
+                requests.push(RecordedRequest {
+                    method: parts.next().unwrap_or_default().to_string(),
+                    path: parts.next().unwrap_or_default().to_string(),
+                    headers,
+                    body,
+                });
+                // The client may hang up early, e.g. when cancelling a stream.
+                let stream = reader.get_mut();
+                let _ = stream.write_all(&response).and_then(|_| stream.flush());
+            }
+            requests
+        });
+
+        Self { url, handle }
+    }
+
+    /// A response with a JSON body.
+    pub fn json(status: u16, body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    /// A `text/event-stream` response delimited by the connection closing.
+    pub fn event_stream(body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 200 Mock\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n{}",
+            body
+        )
+        .into_bytes()
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Wait until every response was served and return the requests received.
+    pub fn finish(self) -> Vec<RecordedRequest> {
+        self.handle.join().unwrap()
+    }
+}
@@ -0,0 +1,435 @@
+//! A small HTTP abstraction shared by the network features.
+//!
+//! Clients in this crate are written against [`HttpClient`]; the ESP32 build implements
+//! it on top of `EspHttpConnection` and the host uses [`StdHttpClient`].
+//!
+//! Both only speak HTTPS unless their [`TlsConfig`] allows plain HTTP.
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+pub mod sse;
+mod std_client;
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+pub use std_client::StdHttpClient;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Overrides the client's timeout for this request.
+    pub timeout: Option<Duration>,
+    /// Fail once the response body grows past this many bytes, streamed or not.
+    pub max_response_size: Option<usize>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            timeout: None,
+            max_response_size: None,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::Post, url)
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds `Authorization: Bearer <token>` if a token is given.
+    pub fn bearer_auth(self, token: Option<&str>) -> Self {
+        match token {
+            Some(token) if !token.is_empty() => {
+                self.header("Authorization", format!("Bearer {}", token))
+            }
+            _ => self,
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn json(self, value: &impl serde::Serialize) -> io::Result<Self> {
+        Ok(self
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(value)?))
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_response_size(mut self, max: usize) -> Self {
+        self.max_response_size = Some(max);
+        self
+    }
+
+    /// For clients: fail if `received` body bytes exceed [`Self::max_response_size`].
+    pub fn check_response_size(&self, received: usize) -> io::Result<()> {
+        match self.max_response_size {
+            Some(max) if received > max => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("response larger than {} bytes", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The first header called `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        std_client::find_header(&self.headers, name)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> io::Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// Turn a non-2xx response into an error carrying the server's message.
+    pub fn error_for_status(self) -> io::Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        // OpenAI-compatible servers wrap errors as {"error": {"message": ...}}.
+        let message = serde_json::from_slice::<serde_json::Value>(&self.body)
+            .ok()
+            .and_then(|v| {
+                v["error"]["message"]
+                    .as_str()
+                    .or(v["error"].as_str())
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| self.text());
+        Err(io::Error::other(format!(
+            "HTTP {}: {}",
+            self.status,
+            message.trim()
+        )))
+    }
+}
+
+/// Sends HTTP requests. Implementations follow no redirects.
+pub trait HttpClient {
+    fn execute(&self, request: Request) -> io::Result<Response>;
+
+    /// Like [`execute`](Self::execute), but a successful response's body is passed to
+    /// `on_data` as it arrives instead of being collected. Error responses are still
+    /// collected so their message can be reported.
+    ///
+    /// Returning an error from `on_data` aborts the transfer with that error.
+    fn execute_streaming(
+        &self,
+        request: Request,
+        on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<Response> {
+        let mut response = self.execute(request)?;
+        if response.is_success() {
+            on_data(&std::mem::take(&mut response.body))?;
+        }
+        Ok(response)
+    }
+}
+
+impl<C: HttpClient + ?Sized> HttpClient for &C {
+    fn execute(&self, request: Request) -> io::Result<Response> {
+        (**self).execute(request)
+    }
+
+    fn execute_streaming(
+        &self,
+        request: Request,
+        on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<Response> {
+        (**self).execute_streaming(request, on_data)
+    }
+}
+
+/// How a client secures its connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificates to trust, e.g. the CA of on-prem servers. The host adds them to
+    /// the built-in roots; ESP-IDF trusts only them instead of its certificate bundle.
+    pub ca_certs: Option<String>,
+    /// Allow `http://` URLs, for servers on a trusted network and for tests.
+    pub allow_http: bool,
+}
+
+impl TlsConfig {
+    /// Reject URLs this config doesn't allow. Returns the parsed URL.
+    pub fn check_url(&self, url: &str) -> io::Result<Url> {
+        let parsed = Url::parse(url)?;
+        if parsed.scheme == "http" && !self.allow_http {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("refusing plain HTTP to {}; use https://", parsed.host),
+            ));
+        }
+        Ok(parsed)
+    }
+}
+
+/// The parts of a URL the clients need.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, always starting with `/`.
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> io::Result<Self> {
+        let invalid =
+            || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid URL: {}", url));
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let scheme = scheme.to_ascii_lowercase();
+        let default_port = match scheme.as_str() {
+            "http" => 80,
+            "https" => 443,
+            _ => return Err(invalid()),
+        };
+
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.to_string()
+        };
+        // IPv6 hosts are bracketed: `[::1]:8080`.
+        let port_start = match authority.rfind(']') {
+            Some(end) => authority[end..].find(':').map(|i| end + i),
+            None => authority.rfind(':'),
+        };
+        let (host, port) = match port_start {
+            Some(i) => (
+                &authority[..i],
+                authority[i + 1..].parse().map_err(|_| invalid())?,
+            ),
+            None => (authority, default_port),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            scheme,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+/// Join a base URL such as `https://host:8080/` with an API path.
+pub fn join_url(base: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Builds a `multipart/form-data` body.
+pub struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        Self::with_boundary(format!("----slint-chat-{:08x}", nanos))
+    }
+
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.part_header(name, None, None);
+        self.body.extend_from_slice(value.as_bytes());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    pub fn file(mut self, name: &str, file_name: &str, content_type: &str, data: &[u8]) -> Self {
+        self.part_header(name, Some(file_name), Some(content_type));
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    fn part_header(&mut self, name: &str, file_name: Option<&str>, content_type: Option<&str>) {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary, name
+        );
+        if let Some(file_name) = file_name {
+            header.push_str(&format!("; filename=\"{}\"", file_name));
+        }
+        header.push_str("\r\n");
+        if let Some(content_type) = content_type {
+            header.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        header.push_str("\r\n");
+        self.body.extend_from_slice(header.as_bytes());
+    }
+
+    /// The `Content-Type` header value and the finished body.
+    pub fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = Url::parse("http://localhost:8080/v1/audio?x=1").unwrap();
+        assert_eq!(url.scheme, "http");
+        assert_eq!(url.host, "localhost");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/v1/audio?x=1");
+
+        let url = Url::parse("HTTPS://api.example.com").unwrap();
+        assert_eq!((url.port, url.path.as_str()), (443, "/"));
+        assert_eq!(Url::parse("http://[::1]:99/").unwrap().host, "[::1]");
+        assert_eq!(Url::parse("http://[::1]/").unwrap().port, 80);
+
+        assert!(Url::parse("ftp://example.com").is_err());
+        assert!(Url::parse("example.com").is_err());
+        assert!(Url::parse("http://host:port/").is_err());
+    }
+
+    #[test]
+    fn test_check_url() {
+        let tls = TlsConfig::default();
+        assert!(tls.check_url("https://example.com/").is_ok());
+        let error = tls.check_url("http://example.com/").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+        let tls = TlsConfig {
+            allow_http: true,
+            ..Default::default()
+        };
+        assert_eq!(tls.check_url("http://a:1/x").unwrap().path, "/x");
+    }
+
+    #[test]
+    fn test_join_url() {
+        assert_eq!(join_url("http://a:1/", "/v1/x"), "http://a:1/v1/x");
+        assert_eq!(join_url("http://a:1/api", "v1/x"), "http://a:1/api/v1/x");
+    }
+
+    #[test]
+    fn test_multipart() {
+        let (content_type, body) = Multipart::with_boundary("XYZ")
+            .text("model", "whisper-1")
+            .file("file", "a.wav", "audio/wav", b"RIFF")
+            .finish();
+        assert_eq!(content_type, "multipart/form-data; boundary=XYZ");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--XYZ\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n\
+             --XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\
+             Content-Type: audio/wav\r\n\r\nRIFF\r\n--XYZ--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_error_for_status() {
+        let response = Response {
+            status: 401,
+            headers: vec![("content-type".into(), "application/json".into())],
+            body: br#"{"error": {"message": "bad key"}}"#.to_vec(),
+        };
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+        let error = response.error_for_status().unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 401: bad key");
+    }
+}
@@ -0,0 +1,165 @@
+//! Incremental parser for `text/event-stream` (server-sent events) bodies.
+
+/// One dispatched event. Fields the clients don't use, such as `retry`, are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The `event:` field, empty for the default `message` type.
+    pub event: String,
+    /// `data:` lines joined with `\n`.
+    pub data: String,
+    pub id: Option<String>,
+}
+
+/// Splits a byte stream into events; input may be cut at any byte.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    line: Vec<u8>,
+    /// A `\r` ended the last line, so a `\n` starting the next chunk belongs to it.
+    after_cr: bool,
+    event: String,
+    data: Vec<String>,
+    id: Option<String>,
+    /// The first line may start with a byte order mark.
+    started: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next piece of the body and return the events it completes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            if self.after_cr {
+                self.after_cr = false;
+                if byte == b'\n' {
+                    continue;
+                }
+            }
+            match byte {
+                b'\r' | b'\n' => {
+                    self.after_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(event) = self.process_line(&line) {
+                        events.push(event);
+                    }
+                }
+                _ => self.line.push(byte),
+            }
+        }
+        events
+    }
+
+    /// Flush an event left open by a stream that ended without a blank line.
+    pub fn finish(mut self) -> Option<SseEvent> {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.process_line(&line);
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Option<SseEvent> {
+        let mut line = String::from_utf8_lossy(line).into_owned();
+        if !self.started {
+            self.started = true;
+            if let Some(rest) = line.strip_prefix('\u{feff}') {
+                line = rest.to_string();
+            }
+        }
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => self.data.push(value.to_string()),
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.event);
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+            id: self.id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_in_pieces(stream: &[u8], piece: usize) -> Vec<SseEvent> {
+        let mut parser = SseParser::new();
+        let mut events = Vec::new();
+        for chunk in stream.chunks(piece) {
+            events.extend(parser.feed(chunk));
+        }
+        events.extend(parser.finish());
+        events
+    }
+
+    #[test]
+    fn test_fields_and_comments() {
+        let stream = b"\xEF\xBB\xBFdata: first\r\n\r\n\
+                       : keep-alive\n\
+                       event: update\nid: 7\ndata:a\ndata:  b\nretry: 100\n\n\
+                       data\n\n\
+                       event: empty\n\n\
+                       data: unterminated";
+        let events = parse_in_pieces(stream, stream.len());
+        assert_eq!(
+            events,
+            [
+                SseEvent {
+                    data: "first".into(),
+                    ..Default::default()
+                },
+                SseEvent {
+                    event: "update".into(),
+                    data: "a\n b".into(),
+                    id: Some("7".into()),
+                },
+                SseEvent {
+                    data: "".into(),
+                    id: Some("7".into()),
+                    ..Default::default()
+                },
+                SseEvent {
+                    data: "unterminated".into(),
+                    id: Some("7".into()),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_anywhere() {
+        // CRLF split between chunks must not produce an extra blank line.
+        let stream = "data: héllo\r\n\r\ndata: wörld\r\n\r\n".as_bytes();
+        for piece in 1..stream.len() {
+            let data: Vec<_> = parse_in_pieces(stream, piece)
+                .into_iter()
+                .map(|e| e.data)
+                .collect();
+            assert_eq!(data, ["héllo", "wörld"], "piece size {}", piece);
+        }
+    }
+}
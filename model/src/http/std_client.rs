@@ -0,0 +1,558 @@
+//! HTTP/1.1 client over `std::net::TcpStream` for the desktop build and host tests.
+//!
+//! HTTPS needs the `tls` feature, which brings in rustls and the Mozilla root
+//! certificates.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{HttpClient, Request, Response, TlsConfig, Url};
+
+/// Client using one connection per request.
+#[derive(Debug, Clone)]
+pub struct StdHttpClient {
+    pub timeout: Duration,
+    tls: TlsConfig,
+    #[cfg(feature = "tls")]
+    rustls: Arc<rustls::ClientConfig>,
+}
+
+impl Default for StdHttpClient {
+    /// HTTPS only, trusting the built-in roots.
+    fn default() -> Self {
+        Self::new(TlsConfig::default()).expect("no custom certificates to parse")
+    }
+}
+
+impl StdHttpClient {
+    /// Fails if `tls.ca_certs` holds no valid certificate.
+    pub fn new(tls: TlsConfig) -> io::Result<Self> {
+        Ok(Self {
+            timeout: Duration::from_secs(30),
+            #[cfg(feature = "tls")]
+            rustls: Arc::new(rustls_config(&tls)?),
+            tls,
+        })
+    }
+
+    pub fn tls(&self) -> &TlsConfig {
+        &self.tls
+    }
+
+    fn send(&self, request: &Request) -> io::Result<BufReader<Stream>> {
+        let url = self.tls.check_url(&request.url)?;
+        if cfg!(not(feature = "tls")) && url.scheme == "https" {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "https needs the tls feature of StdHttpClient",
+            ));
+        }
+        let host = url.host.trim_matches(['[', ']']);
+        let timeout = request.timeout.unwrap_or(self.timeout);
+        let tcp = connect(host, url.port, timeout)?;
+        tcp.set_read_timeout(Some(timeout))?;
+        tcp.set_write_timeout(Some(timeout))?;
+
+        let mut stream = match url.scheme.as_str() {
+            #[cfg(feature = "tls")]
+            "https" => {
+                let name = rustls::pki_types::ServerName::try_from(host.to_string())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let connection = rustls::ClientConnection::new(self.rustls.clone(), name)
+                    .map_err(io::Error::other)?;
+                Stream::Tls(Box::new(rustls::StreamOwned::new(connection, tcp)))
+            }
+            _ => Stream::Plain(tcp),
+        };
+
+        let mut writer = io::BufWriter::new(&mut stream);
+        write_request(&mut writer, &url, request)?;
+        writer.flush()?;
+        drop(writer);
+
+        Ok(BufReader::new(stream))
+    }
+}
+
+/// Try every address `host` resolves to, giving each `timeout` to accept.
+fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no address found for {}", host),
+        )
+    }))
+}
+
+/// The built-in roots plus the configured ones.
+#[cfg(feature = "tls")]
+fn rustls_config(tls: &TlsConfig) -> io::Result<rustls::ClientConfig> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(pem) = &tls.ca_certs {
+        let mut added = 0;
+        for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+            let cert =
+                cert.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            roots
+                .add(cert)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            added += 1;
+        }
+        if added == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no certificates in ca_certs",
+            ));
+        }
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    Ok(rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+enum Stream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            // Servers that close without close_notify are treated as ending the body.
+            Self::Tls(stream) => match stream.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+                result => result,
+            },
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl HttpClient for StdHttpClient {
+    fn execute(&self, request: Request) -> io::Result<Response> {
+        let mut body = Vec::new();
+        let mut response = self.execute_streaming(request, &mut |data| {
+            body.extend_from_slice(data);
+            Ok(())
+        })?;
+        if response.is_success() {
+            response.body = body;
+        }
+        Ok(response)
+    }
+
+    fn execute_streaming(
+        &self,
+        request: Request,
+        on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<Response> {
+        let mut reader = self.send(&request)?;
+        let (status, headers) = read_status(&mut reader)?;
+        let success = (200..300).contains(&status);
+        let mut body = Vec::new();
+        let mut received = 0;
+        read_body_with(&mut reader, &headers, &mut |data| {
+            received += data.len();
+            request.check_response_size(received)?;
+            if success {
+                on_data(data)
+            } else {
+                body.extend_from_slice(data);
+                Ok(())
+            }
+        })?;
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+fn write_request<W: Write>(writer: &mut W, url: &Url, request: &Request) -> io::Result<()> {
+    write!(writer, "{} {} HTTP/1.1\r\n", request.method, url.path)?;
+    if url.port == 80 {
+        write!(writer, "Host: {}\r\n", url.host)?;
+    } else {
+        write!(writer, "Host: {}:{}\r\n", url.host, url.port)?;
+    }
+    write!(writer, "Connection: close\r\n")?;
+    write!(writer, "Content-Length: {}\r\n", request.body.len())?;
+    for (name, value) in &request.headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    write!(writer, "\r\n")?;
+    writer.write_all(&request.body)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Read a start line and headers up to the blank line.
+pub(crate) fn read_head<R: BufRead>(reader: &mut R) -> io::Result<(String, Vec<(String, String)>)> {
+    let start = read_line(reader)?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    Ok((start, headers))
+}
+
+pub(crate) fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Read a body framed by `Transfer-Encoding: chunked`, `Content-Length` or EOF.
+#[cfg(test)]
+pub(crate) fn read_body<R: BufRead>(
+    reader: &mut R,
+    headers: &[(String, String)],
+) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    read_body_with(reader, headers, &mut |data| {
+        body.extend_from_slice(data);
+        Ok(())
+    })?;
+    Ok(body)
+}
+
+/// Like [`read_body`], passing the body to `on_data` piece by piece.
+fn read_body_with<R: BufRead>(
+    reader: &mut R,
+    headers: &[(String, String)],
+    on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let chunked = find_header(headers, "Transfer-Encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+
+    if chunked {
+        loop {
+            let line = read_line(reader)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size =
+                usize::from_str_radix(size, 16).map_err(|_| invalid("invalid chunk size"))?;
+            if size == 0 {
+                // Skip trailers.
+                while !read_line(reader)?.is_empty() {}
+                return Ok(());
+            }
+            read_exactly(reader, size, on_data)?;
+            read_line(reader)?;
+        }
+    } else if let Some(length) = find_header(headers, "Content-Length") {
+        let length: usize = length
+            .parse()
+            .map_err(|_| invalid("invalid Content-Length"))?;
+        read_exactly(reader, length, on_data)
+    } else {
+        loop {
+            let available = reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok(());
+            }
+            let len = available.len();
+            on_data(available)?;
+            reader.consume(len);
+        }
+    }
+}
+
+fn read_exactly<R: BufRead>(
+    reader: &mut R,
+    mut remaining: usize,
+    on_data: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    while remaining > 0 {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "body ended early",
+            ));
+        }
+        let len = available.len().min(remaining);
+        on_data(&available[..len])?;
+        reader.consume(len);
+        remaining -= len;
+    }
+    Ok(())
+}
+
+fn read_status<R: BufRead>(reader: &mut R) -> io::Result<(u16, Vec<(String, String)>)> {
+    let (status_line, headers) = read_head(reader)?;
+    let mut parts = status_line.split_whitespace();
+    let status = match (parts.next(), parts.next()) {
+        (Some(version), Some(status)) if version.starts_with("HTTP/") => {
+            status.parse().map_err(|_| invalid("invalid status code"))?
+        }
+        _ => return Err(invalid("invalid status line")),
+    };
+    Ok((status, headers))
+}
+
+#[cfg(test)]
+fn read_response<R: BufRead>(mut reader: R) -> io::Result<Response> {
+    let (status, headers) = read_status(&mut reader)?;
+    let body = read_body(&mut reader, &headers)?;
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock::MockServer;
+
+    #[test]
+    fn test_read_chunked_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n";
+        let response = read_response(&raw[..]).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.text(), "hello, world");
+    }
+
+    #[test]
+    fn test_read_response_without_length() {
+        let raw = b"HTTP/1.0 404 Not Found\r\nX-A: b\r\n\r\nmissing";
+        let response = read_response(&raw[..]).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.header("x-a"), Some("b"));
+        assert_eq!(response.text(), "missing");
+
+        assert!(read_response(&b"garbage\r\n\r\n"[..]).is_err());
+        assert!(read_response(&b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort"[..]).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_with_server() {
+        let server = MockServer::start(vec![MockServer::json(201, r#"{"ok":true}"#)]);
+        let response = MockServer::client()
+            .execute(
+                Request::post(format!("{}/items?id=3", server.url()))
+                    .header("X-Test", "1")
+                    .body("payload"),
+            )
+            .unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.text(), r#"{"ok":true}"#);
+
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/items?id=3");
+        assert_eq!(requests[0].header("x-test"), Some("1"));
+        assert_eq!(requests[0].body, b"payload");
+    }
+
+    #[test]
+    fn test_streaming_body() {
+        let server = MockServer::start(vec![
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n"
+                .to_vec(),
+            MockServer::json(503, r#"{"error": "busy"}"#),
+            b"HTTP/1.1 200 OK\r\n\r\nuntil the end".to_vec(),
+        ]);
+        let client = MockServer::client();
+
+        let mut chunks = Vec::new();
+        let response = client
+            .execute_streaming(Request::get(server.url()), &mut |data| {
+                chunks.push(data.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        assert!(response.body.is_empty());
+        assert_eq!(chunks, [b"abc".to_vec(), b"de".to_vec()]);
+
+        let response = client
+            .execute_streaming(Request::get(server.url()), &mut |_| panic!("error body"))
+            .unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(response.text(), r#"{"error": "busy"}"#);
+
+        let error = client
+            .execute_streaming(Request::get(server.url()), &mut |_| {
+                Err(io::Error::new(io::ErrorKind::Interrupted, "stop"))
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+        server.finish();
+    }
+
+    #[test]
+    fn test_response_limits() {
+        let server = MockServer::start(vec![
+            MockServer::json(200, r#"{"a":"0123456789"}"#),
+            MockServer::json(500, r#"{"a":"0123456789"}"#),
+            MockServer::json(200, "{}"),
+        ]);
+        let client = MockServer::client();
+        for _ in 0..2 {
+            let error = client
+                .execute(Request::get(server.url()).max_response_size(10))
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), "response larger than 10 bytes");
+        }
+        let response = client
+            .execute(Request::get(server.url()).max_response_size(2))
+            .unwrap();
+        assert_eq!(response.text(), "{}");
+        server.finish();
+
+        // Accepted by the OS but never answered.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let start = std::time::Instant::now();
+        let error = client
+            .execute(Request::get(url).timeout(Duration::from_millis(100)))
+            .unwrap_err();
+        assert!(
+            matches!(
+                error.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            "{:?}",
+            error
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        // Documentation range: nothing answers, so only the timeout ends the attempt.
+        let start = std::time::Instant::now();
+        MockServer::client()
+            .execute(Request::get("http://192.0.2.1/").timeout(Duration::from_millis(200)))
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_refuses_plain_http() {
+        let server = MockServer::start(vec![MockServer::json(200, "{}")]);
+        let error = StdHttpClient::default()
+            .execute(Request::get(server.url()))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        // Nothing was sent; let the server finish.
+        MockServer::client()
+            .execute(Request::get(server.url()))
+            .unwrap();
+        server.finish();
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn test_https_needs_tls_feature() {
+        let error = StdHttpClient::default()
+            .execute(Request::get("https://example.com/"))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_https_with_custom_ca() {
+        let (server, ca) = MockServer::start_https(vec![
+            MockServer::json(200, "unused"),
+            MockServer::json(201, r#"{"ok":true}"#),
+        ]);
+
+        // Not signed by any of the built-in roots.
+        let error = StdHttpClient::default()
+            .execute(Request::get(server.url()))
+            .unwrap_err();
+        assert!(error.to_string().contains("certificate"), "{}", error);
+
+        let client = StdHttpClient::new(TlsConfig {
+            ca_certs: Some(ca),
+            ..Default::default()
+        })
+        .unwrap();
+        let response = client
+            .execute(Request::post(format!("{}/items", server.url())).body("payload"))
+            .unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.text(), r#"{"ok":true}"#);
+
+        let requests = server.finish();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/items");
+        assert_eq!(requests[0].body, b"payload");
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_invalid_ca_certs() {
+        let error = StdHttpClient::new(TlsConfig {
+            ca_certs: Some("not a certificate".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,168 @@
+//! Input level metering: RMS, peak and clipping per block and per recording.
+
+use serde::{Deserialize, Serialize};
+
+/// Samples at or beyond this magnitude count as clipped.
+pub const CLIP_LEVEL: u16 = i16::MAX as u16;
+
+/// Levels below this are reported as silence in dBFS.
+pub const SILENCE_DBFS: f32 = -96.0;
+
+/// Levels of a block of 16-bit samples, normalized to full scale.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct AudioLevels {
+    /// Root mean square level in `0.0..=1.0`.
+    pub rms: f32,
+    /// Absolute peak level in `0.0..=1.0`.
+    pub peak: f32,
+    /// Number of samples at full scale.
+    pub clip_count: u32,
+}
+
+impl AudioLevels {
+    /// Measure a single block of samples.
+    pub fn from_samples(samples: &[i16]) -> Self {
+        let mut meter = LevelMeter::default();
+        meter.process(samples)
+    }
+
+    pub fn rms_dbfs(&self) -> f32 {
+        to_dbfs(self.rms)
+    }
+
+    pub fn peak_dbfs(&self) -> f32 {
+        to_dbfs(self.peak)
+    }
+
+    pub fn is_clipping(&self) -> bool {
+        self.clip_count > 0
+    }
+}
+
+/// Accumulates levels over a whole recording while reporting each block.
+#[derive(Debug, Clone, Default)]
+pub struct LevelMeter {
+    sum_squares: f64,
+    sample_count: u64,
+    peak: u16,
+    clip_count: u32,
+}
+
+impl LevelMeter {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Measure `samples`, add them to the running totals and return the block levels.
+    pub fn process(&mut self, samples: &[i16]) -> AudioLevels {
+        let mut sum_squares = 0.0f64;
+        let mut peak = 0u16;
+        let mut clip_count = 0u32;
+
+        for &sample in samples {
+            let magnitude = sample.unsigned_abs();
+            sum_squares += f64::from(sample) * f64::from(sample);
+            peak = peak.max(magnitude);
+            if magnitude >= CLIP_LEVEL {
+                clip_count += 1;
+            }
+        }
+
+        self.sum_squares += sum_squares;
+        self.sample_count += samples.len() as u64;
+        self.peak = self.peak.max(peak);
+        self.clip_count += clip_count;
+
+        AudioLevels {
+            rms: normalized_rms(sum_squares, samples.len() as u64),
+            peak: normalized(peak),
+            clip_count,
+        }
+    }
+
+    /// Levels over everything processed since the last reset.
+    pub fn total(&self) -> AudioLevels {
+        AudioLevels {
+            rms: normalized_rms(self.sum_squares, self.sample_count),
+            peak: normalized(self.peak),
+            clip_count: self.clip_count,
+        }
+    }
+}
+
+fn normalized(magnitude: u16) -> f32 {
+    (magnitude as f32 / i16::MAX as f32).min(1.0)
+}
+
+fn normalized_rms(sum_squares: f64, count: u64) -> f32 {
+    if count == 0 {
+        return 0.0;
+    }
+    let rms = (sum_squares / count as f64).sqrt() / i16::MAX as f64;
+    (rms as f32).min(1.0)
+}
+
+/// Convert a normalized level to dBFS, clamped at [`SILENCE_DBFS`].
+pub fn to_dbfs(level: f32) -> f32 {
+    if level <= 0.0 {
+        return SILENCE_DBFS;
+    }
+    (20.0 * level.log10()).max(SILENCE_DBFS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let phase = i as f32 * 2.0 * std::f32::consts::PI * 440.0 / 16000.0;
+                (amplitude * i16::MAX as f32 * phase.sin()) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_silence() {
+        let levels = AudioLevels::from_samples(&[0; 256]);
+        assert_eq!(levels, AudioLevels::default());
+        assert_eq!(levels.rms_dbfs(), SILENCE_DBFS);
+        assert_eq!(AudioLevels::from_samples(&[]), AudioLevels::default());
+    }
+
+    #[test]
+    fn test_sine_levels() {
+        let levels = AudioLevels::from_samples(&sine(0.5, 16000));
+        assert!((levels.peak - 0.5).abs() < 0.01);
+        // RMS of a sine is amplitude / sqrt(2).
+        assert!((levels.rms - 0.5 / 2f32.sqrt()).abs() < 0.01);
+        assert!((levels.peak_dbfs() + 6.02).abs() < 0.1);
+        assert!(!levels.is_clipping());
+    }
+
+    #[test]
+    fn test_clipping() {
+        let levels = AudioLevels::from_samples(&[i16::MAX, 0, i16::MIN, 1000, -32767]);
+        assert_eq!(levels.clip_count, 3);
+        assert_eq!(levels.peak, 1.0);
+        assert_eq!(levels.peak_dbfs(), 0.0);
+    }
+
+    #[test]
+    fn test_meter_accumulates() {
+        let mut meter = LevelMeter::default();
+        let quiet = meter.process(&sine(0.1, 1600));
+        let loud = meter.process(&[i16::MAX; 10]);
+        assert!(quiet.peak < 0.11);
+        assert_eq!(loud.clip_count, 10);
+
+        let total = meter.total();
+        assert_eq!(total.peak, 1.0);
+        assert_eq!(total.clip_count, 10);
+        assert!(total.rms > quiet.rms && total.rms < loud.rms);
+
+        meter.reset();
+        assert_eq!(meter.total(), AudioLevels::default());
+    }
+}
@@ -1,5 +1,35 @@
 use serde::{Deserialize, Serialize};
 
+pub mod app;
+pub mod assistant;
+pub mod board;
+pub mod capture;
+pub mod chat;
+pub mod clock;
+pub mod codec;
+pub mod dsp;
+pub mod history;
+pub mod http;
+pub mod level;
+pub mod mqtt;
+pub mod navigation;
+pub mod playback;
+pub mod recording;
+pub mod recordings;
+pub mod sensors;
+pub mod settings;
+pub mod storage;
+pub mod stt;
+pub mod telemetry;
+pub mod tts;
+pub mod upload;
+pub mod weather;
+
+pub use app::Model;
+
+#[cfg(test)]
+mod test_util;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WifiNetwork {
     pub ssid: String,
@@ -39,4 +69,4 @@ mod tests {
         assert_eq!(weather.humidity, 65.0);
         assert_eq!(weather.wind_speed, 5.2);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,241 @@
+//! A local broker for testing the clients, answering one client at a time.
+
+use std::io::{self, Read};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::{
+    packet, publish_packet, read_body, take_bytes, write_packet, CONNACK, CONNECT, DISCONNECT,
+    PINGREQ, PUBLISH, RETAIN, SUBSCRIBE,
+};
+
+// Only the broker sends these.
+const SUBACK: u8 = 0x90;
+const PINGRESP: u8 = 0xD0;
+
+/// A CONNECT as received by [`MockBroker`].
+#[derive(Debug, Clone)]
+pub(crate) struct Connect {
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// A message published by the client, or its will.
+#[derive(Debug, Clone)]
+pub(crate) struct Published {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+impl Published {
+    pub fn payload_text(&self) -> String {
+        String::from_utf8_lossy(&self.payload).into_owned()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct BrokerState {
+    pub connects: Vec<Connect>,
+    pub published: Vec<Published>,
+    /// Filters of the current connection.
+    pub subscriptions: Vec<String>,
+    pub pings: usize,
+    /// Clean disconnects.
+    pub disconnects: usize,
+    client: Option<TcpStream>,
+    closed: bool,
+}
+
+impl BrokerState {
+    /// Messages published to `topic`, oldest first.
+    pub fn messages(&self, topic: &str) -> Vec<&Published> {
+        self.published.iter().filter(|p| p.topic == topic).collect()
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<BrokerState>,
+    changed: Condvar,
+}
+
+pub(crate) struct MockBroker {
+    url: String,
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockBroker {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("mqtt://{}", listener.local_addr().unwrap());
+        let shared = Arc::new(Shared::default());
+        let handle = std::thread::spawn({
+            let shared = shared.clone();
+            move || {
+                while !shared.state.lock().unwrap().closed {
+                    match listener.accept() {
+                        Ok((stream, _)) => serve(stream, &shared),
+                        Err(_) => std::thread::sleep(Duration::from_millis(5)),
+                    }
+                }
+            }
+        });
+        Self {
+            url,
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Route a message to the client if it subscribed to `topic`.
+    pub fn send(&self, topic: &str, payload: &[u8]) {
+        let state = self.shared.state.lock().unwrap();
+        if let Some(client) = &state.client {
+            if state.subscriptions.iter().any(|f| topic_matches(f, topic)) {
+                write_packet(client, &publish_packet(topic, payload, false)).unwrap();
+            }
+        }
+    }
+
+    /// Drop the client's connection as if the network went away.
+    pub fn kick(&self) {
+        if let Some(client) = &self.shared.state.lock().unwrap().client {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, BrokerState> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Wait until `done` holds, failing the test after five seconds.
+    pub fn wait(&self, done: impl Fn(&BrokerState) -> bool) -> MutexGuard<'_, BrokerState> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut state = self.shared.state.lock().unwrap();
+        while !done(&state) {
+            let now = Instant::now();
+            assert!(now < deadline, "timed out waiting for the broker");
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        state
+    }
+}
+
+impl Drop for MockBroker {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.kick();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// `+` matches one level, a trailing `#` any number.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(name)) if level == name => {}
+            _ => return false,
+        }
+    }
+    topic.next().is_none()
+}
+
+fn string(data: &mut &[u8]) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(take_bytes(data)?).into_owned())
+}
+
+fn serve(stream: TcpStream, shared: &Shared) {
+    stream.set_nonblocking(false).unwrap();
+    let mut will = None;
+    let result = (|| -> io::Result<bool> {
+        let mut reader = &stream;
+        loop {
+            let mut header = [0];
+            if reader.read(&mut header)? == 0 {
+                return Ok(false);
+            }
+            let body = read_body(&mut reader)?;
+            let mut state = shared.state.lock().unwrap();
+            match header[0] & 0xF0 {
+                CONNECT => {
+                    let mut data = &body[..];
+                    string(&mut data)?; // "MQTT"
+                    let flags = data[1];
+                    data = &data[4..];
+                    let client_id = string(&mut data)?;
+                    if flags & 0x04 != 0 {
+                        will = Some(Published {
+                            topic: string(&mut data)?,
+                            payload: take_bytes(&mut data)?.to_vec(),
+                            retain: flags & 0x20 != 0,
+                        });
+                    }
+                    let username = (flags & 0x80 != 0).then(|| string(&mut data)).transpose()?;
+                    let password = (flags & 0x40 != 0).then(|| string(&mut data)).transpose()?;
+                    state.connects.push(Connect {
+                        client_id,
+                        username,
+                        password,
+                    });
+                    state.subscriptions.clear();
+                    state.client = Some(stream.try_clone()?);
+                    write_packet(&stream, &packet(CONNACK, &[0, 0]))?;
+                }
+                PUBLISH => {
+                    let (topic, payload) = super::parse_publish(&body)?;
+                    state.published.push(Published {
+                        topic,
+                        payload,
+                        retain: header[0] & RETAIN != 0,
+                    });
+                }
+                _ if header[0] == SUBSCRIBE => {
+                    let mut data = &body[2..];
+                    while !data.is_empty() {
+                        let filter = string(&mut data)?;
+                        state.subscriptions.push(filter);
+                        data = &data[1..];
+                    }
+                    write_packet(&stream, &packet(SUBACK, &[body[0], body[1], 0]))?;
+                }
+                PINGREQ => {
+                    state.pings += 1;
+                    write_packet(&stream, &[PINGRESP, 0])?;
+                }
+                DISCONNECT => {
+                    state.disconnects += 1;
+                    return Ok(true);
+                }
+                _ => {}
+            }
+            shared.changed.notify_all();
+        }
+    })();
+
+    let mut state = shared.state.lock().unwrap();
+    state.client = None;
+    if !matches!(result, Ok(true)) {
+        state.published.extend(will);
+    }
+    shared.changed.notify_all();
+}
@@ -0,0 +1,261 @@
+//! Just enough MQTT 3.1.1 for telemetry: QoS 0 publishing and subscriptions.
+//!
+//! [`Telemetry`](crate::telemetry::Telemetry) is written against [`MqttClient`]; the
+//! ESP32 build implements it on top of `EspMqttClient` and the host uses
+//! [`StdMqttClient`].
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+mod std_client;
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+pub use std_client::StdMqttClient;
+
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// Published by the broker when the client drops off without disconnecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Will {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    /// `mqtt://host:port`.
+    pub url: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive: Duration,
+    pub will: Option<Will>,
+    /// Wait before the first reconnect; doubled on every failure up to a minute.
+    pub reconnect_delay: Duration,
+}
+
+impl MqttConfig {
+    pub fn new(url: impl Into<String>, client_id: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client_id: client_id.into(),
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(60),
+            will: None,
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttEvent {
+    /// Connected or reconnected; subscriptions have to be made again.
+    Connected,
+    Disconnected,
+    Message {
+        topic: String,
+        payload: Vec<u8>,
+    },
+}
+
+/// A connection to a broker that reconnects by itself.
+pub trait MqttClient {
+    /// Fails while disconnected.
+    fn publish(&self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()>;
+
+    fn subscribe(&self, topic: &str) -> io::Result<()>;
+
+    /// Events since the last call, oldest first.
+    fn take_events(&self) -> Vec<MqttEvent>;
+}
+
+impl<C: MqttClient + ?Sized> MqttClient for &C {
+    fn publish(&self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        (**self).publish(topic, payload, retain)
+    }
+
+    fn subscribe(&self, topic: &str) -> io::Result<()> {
+        (**self).subscribe(topic)
+    }
+
+    fn take_events(&self) -> Vec<MqttEvent> {
+        (**self).take_events()
+    }
+}
+
+/// Host and port of an `mqtt://` URL.
+pub fn parse_url(url: &str) -> io::Result<(String, u16)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid URL: {}", url));
+    let authority = url
+        .strip_prefix("mqtt://")
+        .ok_or_else(invalid)?
+        .trim_end_matches('/');
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+        None => (authority, DEFAULT_PORT),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+pub(crate) const CONNECT: u8 = 0x10;
+pub(crate) const CONNACK: u8 = 0x20;
+pub(crate) const PUBLISH: u8 = 0x30;
+pub(crate) const SUBSCRIBE: u8 = 0x82;
+pub(crate) const PINGREQ: u8 = 0xC0;
+pub(crate) const DISCONNECT: u8 = 0xE0;
+/// Set in a PUBLISH header to keep the message for future subscribers.
+pub(crate) const RETAIN: u8 = 0x01;
+
+/// A packet with its fixed header.
+pub(crate) fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        packet.push(if len > 0 { byte | 0x80 } else { byte });
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+pub(crate) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+pub(crate) fn connect_packet(config: &MqttConfig) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if let Some(will) = &config.will {
+        flags |= 0x04;
+        if will.retain {
+            flags |= 0x20;
+        }
+    }
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = Vec::new();
+    put_bytes(&mut body, b"MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    let keep_alive = config.keep_alive.as_secs().min(u16::MAX as u64) as u16;
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    put_bytes(&mut body, config.client_id.as_bytes());
+    if let Some(will) = &config.will {
+        put_bytes(&mut body, will.topic.as_bytes());
+        put_bytes(&mut body, &will.payload);
+    }
+    if let Some(username) = &config.username {
+        put_bytes(&mut body, username.as_bytes());
+    }
+    if let Some(password) = &config.password {
+        put_bytes(&mut body, password.as_bytes());
+    }
+    packet(CONNECT, &body)
+}
+
+pub(crate) fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_bytes(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(if retain { PUBLISH | RETAIN } else { PUBLISH }, &body)
+}
+
+pub(crate) fn subscribe_packet(packet_id: u16, topic: &str) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    put_bytes(&mut body, topic.as_bytes());
+    body.push(0); // QoS 0
+    packet(SUBSCRIBE, &body)
+}
+
+/// Read the remaining length and body of a packet whose first byte was read.
+pub(crate) fn read_body<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = 0usize;
+    for shift in 0..4 {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7F) as usize) << (7 * shift);
+        if byte[0] & 0x80 == 0 {
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body)?;
+            return Ok(body);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "invalid remaining length",
+    ))
+}
+
+/// Split a length-prefixed field off the front of `data`.
+pub(crate) fn take_bytes<'a>(data: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "truncated packet");
+    let len = data.get(..2).ok_or_else(invalid)?;
+    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+    let bytes = data.get(2..2 + len).ok_or_else(invalid)?;
+    *data = &data[2 + len..];
+    Ok(bytes)
+}
+
+/// Topic and payload of a QoS 0 PUBLISH body.
+pub(crate) fn parse_publish(mut body: &[u8]) -> io::Result<(String, Vec<u8>)> {
+    let topic = take_bytes(&mut body)?;
+    let topic = String::from_utf8(topic.to_vec())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((topic, body.to_vec()))
+}
+
+pub(crate) fn write_packet<W: Write>(mut writer: W, packet: &[u8]) -> io::Result<()> {
+    writer.write_all(packet)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("mqtt://broker.lan").unwrap(),
+            ("broker.lan".to_string(), 1883)
+        );
+        assert_eq!(
+            parse_url("mqtt://10.0.0.2:1884/").unwrap(),
+            ("10.0.0.2".to_string(), 1884)
+        );
+        assert!(parse_url("http://broker.lan").is_err());
+        assert!(parse_url("mqtt://:1883").is_err());
+    }
+
+    #[test]
+    fn test_packet_framing() {
+        let payload = vec![7; 200];
+        let packet = publish_packet("a/b", &payload, true);
+        assert_eq!(packet[0], PUBLISH | RETAIN);
+        // 2 + 3 + 200 = 205 needs two length bytes.
+        assert_eq!(&packet[1..3], [0xCD, 0x01]);
+
+        let body = read_body(&mut &packet[1..]).unwrap();
+        let (topic, received) = parse_publish(&body).unwrap();
+        assert_eq!(topic, "a/b");
+        assert_eq!(received, payload);
+
+        assert!(read_body(&mut &[0xFF, 0xFF, 0xFF, 0xFF][..]).is_err());
+        assert!(parse_publish(&[0, 5, b'a']).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! MQTT over `std::net::TcpStream` for the desktop build and host tests.
+
+use std::io::{self, BufReader, Read};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::{
+    connect_packet, parse_url, publish_packet, read_body, subscribe_packet, write_packet,
+    MqttClient, MqttConfig, MqttEvent, CONNACK, DISCONNECT, PINGREQ, PUBLISH,
+};
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct State {
+    /// The connection's write half while connected.
+    stream: Option<TcpStream>,
+    events: Vec<MqttEvent>,
+    packet_id: u16,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+/// A plain-TCP client; a background thread reads, pings and reconnects.
+pub struct StdMqttClient {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StdMqttClient {
+    pub fn start(config: MqttConfig) -> io::Result<Self> {
+        parse_url(&config.url)?;
+        let shared = Arc::new(Shared::default());
+        let handle = std::thread::Builder::new().name("mqtt".into()).spawn({
+            let shared = shared.clone();
+            move || connection_loop(&config, &shared)
+        })?;
+        Ok(Self {
+            shared,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.state.lock().unwrap().stream.is_some()
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let state = self.shared.state.lock().unwrap();
+        match &state.stream {
+            Some(stream) => write_packet(stream, packet),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "not connected to the MQTT broker",
+            )),
+        }
+    }
+}
+
+impl MqttClient for StdMqttClient {
+    fn publish(&self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        self.send(&publish_packet(topic, payload, retain))
+    }
+
+    fn subscribe(&self, topic: &str) -> io::Result<()> {
+        let packet_id = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.packet_id = state.packet_id.wrapping_add(1).max(1);
+            state.packet_id
+        };
+        self.send(&subscribe_packet(packet_id, topic))
+    }
+
+    fn take_events(&self) -> Vec<MqttEvent> {
+        std::mem::take(&mut self.shared.state.lock().unwrap().events)
+    }
+}
+
+impl Drop for StdMqttClient {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.closed = true;
+            if let Some(stream) = state.stream.take() {
+                let _ = write_packet(&stream, &[DISCONNECT, 0]);
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        self.shared.wake.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn connection_loop(config: &MqttConfig, shared: &Shared) {
+    let mut delay = config.reconnect_delay;
+    loop {
+        match connect(config) {
+            Ok(stream) => {
+                delay = config.reconnect_delay;
+                let result = stream.try_clone().and_then(|writer| {
+                    let mut state = shared.state.lock().unwrap();
+                    if state.closed {
+                        return Ok(());
+                    }
+                    state.stream = Some(writer);
+                    state.events.push(MqttEvent::Connected);
+                    drop(state);
+                    read_loop(stream, config.keep_alive, shared)
+                });
+                let mut state = shared.state.lock().unwrap();
+                if state.closed {
+                    return;
+                }
+                state.stream = None;
+                state.events.push(MqttEvent::Disconnected);
+                if let Err(e) = result {
+                    log::info!("MQTT connection lost: {}", e);
+                }
+            }
+            Err(e) => log::info!("MQTT connection to {} failed: {}", config.url, e),
+        }
+
+        let state = shared.state.lock().unwrap();
+        let (state, _) = shared
+            .wake
+            .wait_timeout_while(state, delay, |state| !state.closed)
+            .unwrap();
+        if state.closed {
+            return;
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+fn connect(config: &MqttConfig) -> io::Result<TcpStream> {
+    let (host, port) = parse_url(&config.url)?;
+    let stream = TcpStream::connect((host.as_str(), port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    write_packet(&stream, &connect_packet(config))?;
+
+    let mut header = [0];
+    (&stream).read_exact(&mut header)?;
+    let body = read_body(&mut &stream)?;
+    if header[0] != CONNACK || body.len() != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected CONNACK",
+        ));
+    }
+    if body[1] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("broker refused the connection with code {}", body[1]),
+        ));
+    }
+    Ok(stream)
+}
+
+/// Read packets until the connection fails, pinging when it has been quiet for half
+/// the keep-alive.
+fn read_loop(stream: TcpStream, keep_alive: Duration, shared: &Shared) -> io::Result<()> {
+    let ping_interval = (keep_alive / 2).max(Duration::from_millis(100));
+    stream.set_read_timeout(Some(ping_interval))?;
+    let mut reader = BufReader::new(&stream);
+    let mut last_received = Instant::now();
+    loop {
+        let mut header = [0];
+        match reader.read(&mut header) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "broker closed the connection",
+                ))
+            }
+            Ok(_) => last_received = Instant::now(),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if last_received.elapsed() > keep_alive + ping_interval {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "broker stopped answering",
+                    ));
+                }
+                write_packet(&stream, &[PINGREQ, 0])?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        let body = read_body(&mut reader)?;
+        if header[0] & 0xF0 == PUBLISH {
+            let (topic, payload) = super::parse_publish(&body)?;
+            shared
+                .state
+                .lock()
+                .unwrap()
+                .events
+                .push(MqttEvent::Message { topic, payload });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::mock::MockBroker;
+    use crate::mqtt::Will;
+
+    fn wait_for_event(client: &StdMqttClient, expected: &MqttEvent) -> Vec<MqttEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while !events.contains(expected) {
+            assert!(
+                Instant::now() < deadline,
+                "no {:?} in {:?}",
+                expected,
+                events
+            );
+            std::thread::sleep(Duration::from_millis(10));
+            events.extend(client.take_events());
+        }
+        events
+    }
+
+    #[test]
+    fn test_publish_and_subscribe() {
+        let broker = MockBroker::start();
+        let mut config = MqttConfig::new(broker.url(), "device-1");
+        config.username = Some("user".to_string());
+        config.password = Some("secret".to_string());
+        config.keep_alive = Duration::from_millis(200);
+        let client = StdMqttClient::start(config).unwrap();
+        wait_for_event(&client, &MqttEvent::Connected);
+
+        client.publish("a/b", b"1", true).unwrap();
+        client.subscribe("cmd/+").unwrap();
+        drop(broker.wait(|state| state.subscriptions.contains(&"cmd/+".to_string())));
+        broker.send("cmd/x", b"go");
+        let events = wait_for_event(
+            &client,
+            &MqttEvent::Message {
+                topic: "cmd/x".to_string(),
+                payload: b"go".to_vec(),
+            },
+        );
+        assert_eq!(events.len(), 1);
+
+        // Pings keep the connection up past the keep-alive.
+        let state = broker.wait(|state| state.pings >= 2);
+        assert_eq!(state.connects[0].client_id, "device-1");
+        assert_eq!(state.connects[0].username.as_deref(), Some("user"));
+        assert_eq!(state.connects[0].password.as_deref(), Some("secret"));
+        assert_eq!(state.published[0].topic, "a/b");
+        assert!(state.published[0].retain);
+        drop(state);
+        assert!(client.is_connected());
+    }
+
+    #[test]
+    fn test_reconnects_with_will() {
+        let broker = MockBroker::start();
+        let mut config = MqttConfig::new(broker.url(), "device-2");
+        config.will = Some(Will {
+            topic: "status".to_string(),
+            payload: b"offline".to_vec(),
+            retain: true,
+        });
+        config.reconnect_delay = Duration::from_millis(20);
+        let client = StdMqttClient::start(config).unwrap();
+        wait_for_event(&client, &MqttEvent::Connected);
+
+        broker.kick();
+        let events = wait_for_event(&client, &MqttEvent::Connected);
+        assert_eq!(events, [MqttEvent::Disconnected, MqttEvent::Connected]);
+        let state = broker.wait(|state| state.connects.len() == 2);
+        assert_eq!(state.published[0].topic, "status");
+        assert_eq!(state.published[0].payload, b"offline");
+        drop(state);
+
+        drop(client);
+        let state = broker.wait(|state| state.disconnects == 1);
+        // A clean disconnect doesn't trigger the will.
+        assert_eq!(state.published.len(), 1);
+    }
+
+    #[test]
+    fn test_not_connected() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("mqtt://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = StdMqttClient::start(MqttConfig::new(url, "x")).unwrap();
+        let error = client.publish("a", b"", false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        assert!(StdMqttClient::start(MqttConfig::new("tcp://x", "x")).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Which top-level page the UI shows, and how swipes and buttons move between them.
+
+use serde::{Deserialize, Serialize};
+
+/// Top-level pages in swipe order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Page {
+    #[default]
+    Weather,
+    Forecast,
+    History,
+    Chat,
+    Recordings,
+    Wifi,
+    Settings,
+    Diagnostics,
+}
+
+impl Page {
+    pub const ALL: [Page; 8] = [
+        Page::Weather,
+        Page::Forecast,
+        Page::History,
+        Page::Chat,
+        Page::Recordings,
+        Page::Wifi,
+        Page::Settings,
+        Page::Diagnostics,
+    ];
+
+    /// Position in [`Page::ALL`], used by the page indicator.
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&page| page == self).unwrap()
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Weather => "Weather",
+            Self::Forecast => "Forecast",
+            Self::History => "History",
+            Self::Chat => "Chat",
+            Self::Recordings => "Recordings",
+            Self::Wifi => "Wi-Fi",
+            Self::Settings => "Settings",
+            Self::Diagnostics => "Diagnostics",
+        }
+    }
+}
+
+/// Most pages a user can go back through.
+const MAX_HISTORY: usize = 16;
+
+/// Tracks the current page and where the user came from.
+#[derive(Debug, Clone, Default)]
+pub struct Navigator {
+    current: Page,
+    history: Vec<Page>,
+}
+
+impl Navigator {
+    pub fn new(start: Page) -> Self {
+        Self {
+            current: start,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn current_page(&self) -> Page {
+        self.current
+    }
+
+    /// Show `page`, remembering the current one for [`back`](Self::back).
+    /// Returns whether the page changed.
+    pub fn go_to(&mut self, page: Page) -> bool {
+        if page == self.current {
+            return false;
+        }
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(self.current);
+        self.current = page;
+        true
+    }
+
+    /// Move `delta` pages along [`Page::ALL`], stopping at either end, as a swipe does.
+    pub fn step(&mut self, delta: i32) -> bool {
+        let last = Page::ALL.len() as i64 - 1;
+        let index = (self.current.index() as i64 + i64::from(delta)).clamp(0, last);
+        self.go_to(Page::ALL[index as usize])
+    }
+
+    /// Return to the previous page, or the first page when there is no history.
+    pub fn back(&mut self) -> bool {
+        let page = self.history.pop().unwrap_or_default();
+        let changed = page != self.current;
+        self.current = page;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_index() {
+        for (i, page) in Page::ALL.iter().enumerate() {
+            assert_eq!(page.index(), i);
+            assert_eq!(Page::from_index(i), Some(*page));
+        }
+        assert_eq!(Page::from_index(Page::ALL.len()), None);
+        assert_eq!(
+            serde_json::to_string(&Page::Diagnostics).unwrap(),
+            r#""diagnostics""#
+        );
+    }
+
+    #[test]
+    fn test_step_clamps() {
+        let mut nav = Navigator::default();
+        assert!(!nav.step(-1));
+        assert!(nav.step(1));
+        assert_eq!(nav.current_page(), Page::Forecast);
+        assert!(nav.step(100));
+        assert_eq!(nav.current_page(), Page::Diagnostics);
+        assert!(!nav.step(1));
+    }
+
+    #[test]
+    fn test_back() {
+        let mut nav = Navigator::new(Page::Weather);
+        nav.go_to(Page::Recordings);
+        assert!(!nav.go_to(Page::Recordings));
+        nav.go_to(Page::Chat);
+        assert!(nav.back());
+        assert_eq!(nav.current_page(), Page::Recordings);
+        assert!(nav.back());
+        assert_eq!(nav.current_page(), Page::Weather);
+        assert!(!nav.back());
+
+        for _ in 0..MAX_HISTORY {
+            nav.step(1);
+            nav.step(-1);
+        }
+        assert_eq!(nav.history.len(), MAX_HISTORY);
+        for _ in 0..MAX_HISTORY {
+            nav.back();
+        }
+        // The oldest entries were dropped; back then falls to the first page.
+        nav.go_to(Page::Settings);
+        nav.back();
+        nav.back();
+        assert_eq!(nav.current_page(), Page::Weather);
+    }
+}
@@ -0,0 +1,309 @@
+//! Audio output: the [`AudioSink`] trait and a queue playing clips on a background thread.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+use crate::codec::wav;
+
+/// Mono 16-bit audio ready to play.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clip {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl Clip {
+    pub fn new(sample_rate: u32, samples: Vec<i16>) -> Self {
+        Self {
+            sample_rate,
+            samples,
+        }
+    }
+
+    /// Decode a mono PCM or IMA ADPCM WAV file.
+    pub fn from_wav(bytes: &[u8]) -> io::Result<Self> {
+        let (info, samples) = wav::read(bytes)?;
+        Ok(Self::new(info.sample_rate, samples))
+    }
+
+    /// Interpret raw little-endian 16-bit mono PCM.
+    pub fn from_pcm(bytes: &[u8], sample_rate: u32) -> Self {
+        Self::new(sample_rate, crate::recording::pcm_bytes_to_samples(bytes))
+    }
+
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(
+            self.samples.len() as f64 / f64::from(self.sample_rate.max(1)),
+        )
+    }
+}
+
+/// Somewhere to play audio: a speaker amplifier on the device, a file on the host.
+pub trait AudioSink {
+    /// Prepare for a clip at `sample_rate`.
+    fn begin(&mut self, sample_rate: u32) -> io::Result<()>;
+
+    /// Play the next samples, blocking until the output accepted them.
+    fn write(&mut self, samples: &[i16]) -> io::Result<()>;
+
+    /// The clip ended or was stopped; silence the output.
+    fn end(&mut self) -> io::Result<()>;
+}
+
+/// Writes each clip to its own WAV file, for host tests and the desktop build.
+#[derive(Debug)]
+pub struct FileSink {
+    dir: PathBuf,
+    sample_rate: u32,
+    samples: Vec<i16>,
+    written: Vec<PathBuf>,
+}
+
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            sample_rate: 0,
+            samples: Vec::new(),
+            written: Vec::new(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files written so far, in playback order.
+    pub fn written(&self) -> &[PathBuf] {
+        &self.written
+    }
+}
+
+impl AudioSink for FileSink {
+    fn begin(&mut self, sample_rate: u32) -> io::Result<()> {
+        self.sample_rate = sample_rate;
+        self.samples.clear();
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        self.samples.extend_from_slice(samples);
+        Ok(())
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        let path = self
+            .dir
+            .join(format!("playback_{:03}.wav", self.written.len()));
+        let file = io::BufWriter::new(std::fs::File::create(&path)?);
+        wav::write_pcm(file, &self.samples, self.sample_rate)?;
+        self.samples.clear();
+        self.written.push(path);
+        Ok(())
+    }
+}
+
+/// Samples handed to the sink at once, which bounds how late [`PlaybackQueue::stop`] takes effect.
+const CHUNK_MS: usize = 20;
+
+struct QueuedClip {
+    clip: Clip,
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<VecDeque<QueuedClip>>,
+    wake: Condvar,
+    /// Bumped by `stop`; clips queued under an older generation are dropped.
+    generation: AtomicU64,
+    playing: AtomicBool,
+    closed: AtomicBool,
+}
+
+/// Plays clips one after another on a background thread.
+pub struct PlaybackQueue<S> {
+    shared: Arc<Shared>,
+    handle: JoinHandle<S>,
+}
+
+impl<S: AudioSink + Send + 'static> PlaybackQueue<S> {
+    pub fn new(sink: S) -> io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let handle = std::thread::Builder::new()
+            .name("audio-playback".into())
+            .stack_size(16 * 1024)
+            .spawn({
+                let shared = shared.clone();
+                move || play_loop(sink, &shared)
+            })?;
+        Ok(Self { shared, handle })
+    }
+
+    /// Play `clip` after the ones already queued.
+    pub fn enqueue(&self, clip: Clip) {
+        let generation = self.shared.generation.load(Ordering::SeqCst);
+        self.shared
+            .queue
+            .lock()
+            .unwrap()
+            .push_back(QueuedClip { clip, generation });
+        self.shared.wake.notify_one();
+    }
+
+    /// Cut the current clip short and drop everything queued.
+    pub fn stop(&self) {
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        self.shared.queue.lock().unwrap().clear();
+    }
+
+    /// Whether a clip is playing or waiting to play.
+    pub fn is_busy(&self) -> bool {
+        self.shared.playing.load(Ordering::SeqCst) || !self.shared.queue.lock().unwrap().is_empty()
+    }
+
+    /// Finish playing the queued clips and give the sink back.
+    pub fn close(self) -> S {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.shared.wake.notify_one();
+        self.handle.join().expect("playback thread panicked")
+    }
+}
+
+fn play_loop<S: AudioSink>(mut sink: S, shared: &Shared) -> S {
+    loop {
+        let next = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if let Some(next) = queue.pop_front() {
+                    // Set while holding the lock so `is_busy` never sees a gap.
+                    shared.playing.store(true, Ordering::SeqCst);
+                    break Some(next);
+                }
+                if shared.closed.load(Ordering::SeqCst) {
+                    break None;
+                }
+                queue = shared.wake.wait(queue).unwrap();
+            }
+        };
+        let Some(QueuedClip { clip, generation }) = next else {
+            return sink;
+        };
+        if let Err(e) = play_clip(&mut sink, &clip, generation, shared) {
+            log::warn!("Playback failed: {}", e);
+        }
+        shared.playing.store(false, Ordering::SeqCst);
+    }
+}
+
+fn play_clip<S: AudioSink>(
+    sink: &mut S,
+    clip: &Clip,
+    generation: u64,
+    shared: &Shared,
+) -> io::Result<()> {
+    let current = || shared.generation.load(Ordering::SeqCst) == generation;
+    if !current() {
+        return Ok(());
+    }
+    sink.begin(clip.sample_rate)?;
+    let chunk = (clip.sample_rate as usize * CHUNK_MS / 1000).max(1);
+    for samples in clip.samples.chunks(chunk) {
+        if !current() {
+            break;
+        }
+        sink.write(samples)?;
+    }
+    sink.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn tone(sample_rate: u32, len: usize) -> Clip {
+        Clip::new(
+            sample_rate,
+            (0..len).map(|i| (i % 100) as i16 * 100).collect(),
+        )
+    }
+
+    #[test]
+    fn test_queue_plays_in_order() {
+        let dir = TempDir::new("order");
+        let queue = PlaybackQueue::new(FileSink::new(&dir.0)).unwrap();
+        let first = tone(24000, 5000);
+        let second = tone(16000, 123);
+        queue.enqueue(first.clone());
+        queue.enqueue(second.clone());
+        let sink = queue.close();
+
+        assert_eq!(sink.written().len(), 2);
+        let played: Vec<Clip> = sink
+            .written()
+            .iter()
+            .map(|path| Clip::from_wav(&std::fs::read(path).unwrap()).unwrap())
+            .collect();
+        assert_eq!(played, [first, second]);
+    }
+
+    /// Blocks on `write` until the test lets it continue, so `stop` lands mid-clip.
+    struct GatedSink {
+        gate: Arc<(Mutex<usize>, Condvar)>,
+        written: usize,
+        clips: usize,
+    }
+
+    impl AudioSink for GatedSink {
+        fn begin(&mut self, _sample_rate: u32) -> io::Result<()> {
+            self.clips += 1;
+            Ok(())
+        }
+
+        fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+            let (permits, wake) = &*self.gate;
+            let mut permits = permits.lock().unwrap();
+            while *permits == 0 {
+                permits = wake.wait(permits).unwrap();
+            }
+            *permits -= 1;
+            self.written += samples.len();
+            Ok(())
+        }
+
+        fn end(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stop_drops_queue() {
+        let gate = Arc::new((Mutex::new(1), Condvar::new()));
+        let queue = PlaybackQueue::new(GatedSink {
+            gate: gate.clone(),
+            written: 0,
+            clips: 0,
+        })
+        .unwrap();
+        queue.enqueue(tone(1000, 1000));
+        queue.enqueue(tone(1000, 1000));
+        // Wait until the first chunk was taken.
+        while *gate.0.lock().unwrap() != 0 {
+            std::thread::yield_now();
+        }
+        assert!(queue.is_busy());
+
+        queue.stop();
+        *gate.0.lock().unwrap() = usize::MAX;
+        gate.1.notify_all();
+        let sink = queue.close();
+        // At most the chunk in flight and one more were written before the stop was seen.
+        assert!(sink.written <= 40, "{} samples written", sink.written);
+        assert_eq!(sink.clips, 1);
+    }
+}
@@ -0,0 +1,372 @@
+//! Push-to-talk recording state shared by the ESP32 and desktop builds.
+//!
+//! The platform code owns the actual capture (I2S on the device); this module only
+//! tracks whether a recording is running, for how long, and what the UI should show.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::RecordingFormat;
+use crate::level::{AudioLevels, LevelMeter};
+
+/// Number of bars shown in the waveform preview.
+pub const WAVEFORM_BARS: usize = 48;
+
+/// Whether the microphone is currently live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordingState {
+    #[default]
+    Idle,
+    Recording,
+}
+
+/// Start/stop state machine for a single recording at a time.
+#[derive(Debug)]
+pub struct RecordingSession {
+    state: RecordingState,
+    started: Option<Instant>,
+    max_duration: Duration,
+    waveform: Waveform,
+    meter: LevelMeter,
+    levels: AudioLevels,
+}
+
+impl RecordingSession {
+    /// Create an idle session that stops itself after `max_duration`.
+    pub fn new(sample_rate: u32, max_duration: Duration) -> Self {
+        // Ten bars per second keeps the preview readable on a 240px display.
+        let samples_per_bar = (sample_rate / 10).max(1) as usize;
+        Self {
+            state: RecordingState::Idle,
+            started: None,
+            max_duration,
+            waveform: Waveform::new(WAVEFORM_BARS, samples_per_bar),
+            meter: LevelMeter::default(),
+            levels: AudioLevels::default(),
+        }
+    }
+
+    pub fn state(&self) -> RecordingState {
+        self.state
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state == RecordingState::Recording
+    }
+
+    pub fn max_duration(&self) -> Duration {
+        self.max_duration
+    }
+
+    /// Change the limit; a running recording keeps the one it started with.
+    pub fn set_max_duration(&mut self, max_duration: Duration) {
+        if !self.is_recording() {
+            self.max_duration = max_duration;
+        }
+    }
+
+    /// Start recording. Returns `false` if a recording is already running.
+    pub fn start(&mut self, now: Instant) -> bool {
+        if self.is_recording() {
+            return false;
+        }
+        self.state = RecordingState::Recording;
+        self.started = Some(now);
+        self.waveform.clear();
+        self.meter.reset();
+        self.levels = AudioLevels::default();
+        true
+    }
+
+    /// Stop recording and return how long it ran, or `None` if nothing was recording.
+    pub fn stop(&mut self, now: Instant) -> Option<Duration> {
+        if !self.is_recording() {
+            return None;
+        }
+        let elapsed = self.elapsed(now);
+        self.state = RecordingState::Idle;
+        self.started = None;
+        self.levels = AudioLevels::default();
+        Some(elapsed)
+    }
+
+    /// Time since the recording was started, capped at the maximum duration.
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        self.started
+            .map(|started| {
+                now.saturating_duration_since(started)
+                    .min(self.max_duration)
+            })
+            .unwrap_or_default()
+    }
+
+    /// `true` once a running recording has reached its maximum duration.
+    pub fn should_stop(&self, now: Instant) -> bool {
+        self.started
+            .is_some_and(|started| now.saturating_duration_since(started) >= self.max_duration)
+    }
+
+    /// Feed captured samples into the level meter and waveform preview.
+    pub fn push_samples(&mut self, samples: &[i16]) {
+        if !self.is_recording() || samples.is_empty() {
+            return;
+        }
+        self.levels = self.meter.process(samples);
+        self.waveform.push_samples(samples);
+    }
+
+    /// Levels of the most recently pushed block.
+    pub fn levels(&self) -> AudioLevels {
+        self.levels
+    }
+
+    /// Levels over the whole running (or last) recording.
+    pub fn total_levels(&self) -> AudioLevels {
+        self.meter.total()
+    }
+
+    /// Waveform bars, oldest first, each in `0.0..=1.0`.
+    pub fn waveform(&self) -> Vec<f32> {
+        self.waveform.bars()
+    }
+}
+
+/// Platform audio capture driving a [`RecordingSession`]: I2S on the device, the
+/// sound card or a file on desktop.
+pub trait Recorder {
+    /// Start capturing; the recording is saved in `format` once it stops. Fails if
+    /// nothing was started, e.g. because the last recording is still being saved.
+    fn start(&mut self, format: RecordingFormat) -> std::io::Result<()>;
+    fn stop(&mut self);
+    /// Pick up captured audio and stop at the time limit; called from a UI timer.
+    fn poll(&mut self);
+    fn session(&self) -> &RecordingSession;
+    fn session_mut(&mut self) -> &mut RecordingSession;
+}
+
+impl<R: Recorder + ?Sized> Recorder for Box<R> {
+    fn start(&mut self, format: RecordingFormat) -> std::io::Result<()> {
+        (**self).start(format)
+    }
+
+    fn stop(&mut self) {
+        (**self).stop()
+    }
+
+    fn poll(&mut self) {
+        (**self).poll()
+    }
+
+    fn session(&self) -> &RecordingSession {
+        (**self).session()
+    }
+
+    fn session_mut(&mut self) -> &mut RecordingSession {
+        (**self).session_mut()
+    }
+}
+
+/// Scrolling peak envelope used for the waveform preview.
+#[derive(Debug)]
+struct Waveform {
+    bars: VecDeque<f32>,
+    capacity: usize,
+    samples_per_bar: usize,
+    pending_peak: f32,
+    pending_samples: usize,
+}
+
+impl Waveform {
+    fn new(capacity: usize, samples_per_bar: usize) -> Self {
+        Self {
+            bars: VecDeque::with_capacity(capacity),
+            capacity,
+            samples_per_bar,
+            pending_peak: 0.0,
+            pending_samples: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.bars.clear();
+        self.pending_peak = 0.0;
+        self.pending_samples = 0;
+    }
+
+    fn push_samples(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            self.pending_peak = self.pending_peak.max(normalized_peak(&[sample]));
+            self.pending_samples += 1;
+            if self.pending_samples == self.samples_per_bar {
+                if self.bars.len() == self.capacity {
+                    self.bars.pop_front();
+                }
+                self.bars.push_back(self.pending_peak);
+                self.pending_peak = 0.0;
+                self.pending_samples = 0;
+            }
+        }
+    }
+
+    fn bars(&self) -> Vec<f32> {
+        self.bars.iter().copied().collect()
+    }
+}
+
+fn normalized_peak(samples: &[i16]) -> f32 {
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+    (peak as f32 / i16::MAX as f32).min(1.0)
+}
+
+/// Sidecar stored next to each recording, e.g. `rec_1.json` for `rec_1.wav`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingMetadata {
+    pub sample_rate: u32,
+    pub duration_ms: u64,
+    pub levels: AudioLevels,
+    /// Missing in sidecars written before recordings could be compressed.
+    #[serde(default)]
+    pub format: RecordingFormat,
+}
+
+impl RecordingMetadata {
+    /// Measure a finished recording.
+    pub fn from_samples(samples: &[i16], sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            duration_ms: samples.len() as u64 * 1000 / u64::from(sample_rate.max(1)),
+            levels: AudioLevels::from_samples(samples),
+            format: RecordingFormat::default(),
+        }
+    }
+
+    /// `rec_1.wav.json` for `rec_1.wav`, so `rec_1.opus` gets a sidecar of its own.
+    pub fn sidecar_path(recording: &Path) -> PathBuf {
+        let mut path = recording.as_os_str().to_owned();
+        path.push(".json");
+        PathBuf::from(path)
+    }
+
+    /// Write the metadata next to `recording`.
+    pub fn save(&self, recording: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        crate::recordings::write_atomic(&Self::sidecar_path(recording), &json)
+    }
+
+    /// Read the metadata stored next to `recording`.
+    pub fn load(recording: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read(Self::sidecar_path(recording))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+/// Convert little-endian 16-bit PCM bytes, as delivered by I2S, into samples.
+pub fn pcm_bytes_to_samples(bytes: &[u8]) -> Vec<i16> {
+    bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_start_stop() {
+        let t0 = Instant::now();
+        let mut session = RecordingSession::new(16000, Duration::from_secs(10));
+        assert_eq!(session.state(), RecordingState::Idle);
+        assert_eq!(session.stop(t0), None);
+
+        assert!(session.start(t0));
+        assert!(!session.start(t0 + Duration::from_secs(1)));
+        assert_eq!(
+            session.elapsed(t0 + Duration::from_secs(3)),
+            Duration::from_secs(3)
+        );
+
+        assert_eq!(
+            session.stop(t0 + Duration::from_secs(4)),
+            Some(Duration::from_secs(4))
+        );
+        assert!(!session.is_recording());
+        assert_eq!(session.elapsed(t0 + Duration::from_secs(5)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_auto_stop_at_max_duration() {
+        let t0 = Instant::now();
+        let mut session = RecordingSession::new(16000, Duration::from_secs(10));
+        assert!(!session.should_stop(t0));
+        session.start(t0);
+        assert!(!session.should_stop(t0 + Duration::from_secs(9)));
+        assert!(session.should_stop(t0 + Duration::from_secs(10)));
+        assert_eq!(
+            session.elapsed(t0 + Duration::from_secs(12)),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn test_waveform_scrolls() {
+        let t0 = Instant::now();
+        let mut session = RecordingSession::new(100, Duration::from_secs(60));
+        // Samples pushed while idle are ignored.
+        session.push_samples(&[i16::MAX; 10]);
+        assert!(session.waveform().is_empty());
+
+        session.start(t0);
+        session.push_samples(&[i16::MAX / 2; 10]);
+        assert_eq!(session.waveform().len(), 1);
+        assert!((session.levels().peak - 0.5).abs() < 0.01);
+
+        for _ in 0..WAVEFORM_BARS {
+            session.push_samples(&[i16::MIN; 10]);
+        }
+        let bars = session.waveform();
+        assert_eq!(bars.len(), WAVEFORM_BARS);
+        assert!(bars.iter().all(|&b| b == 1.0));
+
+        assert_eq!(session.total_levels().clip_count, WAVEFORM_BARS as u32 * 10);
+
+        session.stop(t0);
+        session.start(t0);
+        assert!(session.waveform().is_empty());
+        assert_eq!(session.total_levels(), AudioLevels::default());
+    }
+
+    #[test]
+    fn test_metadata_sidecar() {
+        let dir = TempDir::new("rec-meta");
+        let recording = dir.0.join("rec_1.wav");
+
+        let metadata = RecordingMetadata::from_samples(&[i16::MAX; 8000], 16000);
+        assert_eq!(metadata.duration_ms, 500);
+        assert_eq!(metadata.levels.clip_count, 8000);
+
+        metadata.save(&recording).unwrap();
+        assert_eq!(metadata.format, RecordingFormat::Pcm);
+        assert!(dir.0.join("rec_1.wav.json").exists());
+        assert_eq!(RecordingMetadata::load(&recording).unwrap(), metadata);
+
+        let opus = dir.0.join("rec_1.opus");
+        let mut opus_metadata = metadata.clone();
+        opus_metadata.format = RecordingFormat::Opus;
+        opus_metadata.save(&opus).unwrap();
+        assert_eq!(RecordingMetadata::load(&opus).unwrap(), opus_metadata);
+        assert_eq!(RecordingMetadata::load(&recording).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_pcm_bytes_to_samples() {
+        assert_eq!(
+            pcm_bytes_to_samples(&[0x01, 0x00, 0xff, 0xff, 0x7f]),
+            vec![1, -1]
+        );
+    }
+}
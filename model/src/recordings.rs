@@ -0,0 +1,678 @@
+//! Index of the recordings stored on the SD card, with deletion and a retention policy.
+//!
+//! The index lives in `index.json` next to the recordings and is kept in the order the
+//! recordings were made. That order, not the timestamp, decides what is oldest: before
+//! the clock is synced the timestamps restart near zero on every boot.
+//!
+//! Files are written under a temporary name and renamed once complete. Opening the
+//! index repairs what a power cut left behind.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{is_valid_unix, PosixTz};
+use crate::codec::{wav, RecordingFormat};
+use crate::recording::RecordingMetadata;
+use crate::storage::StorageInfo;
+
+pub const INDEX_FILE: &str = "index.json";
+const FILE_PREFIX: &str = "rec_";
+/// Appended to the name of a file while it is written.
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// Write `bytes` to `path` through a temporary file, so a power cut leaves either the
+/// old contents or the new ones but never a truncated file. FATFS can't rename over a
+/// file, so for a moment only the temporary file is left; [`recover_atomic`] puts it
+/// back.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    match std::fs::rename(&temp, path) {
+        // FATFS won't rename over an existing file.
+        Err(_) if path.exists() => {
+            std::fs::remove_file(path)?;
+            std::fs::rename(&temp, path)
+        }
+        result => result,
+    }
+}
+
+/// Finish a [`write_atomic`] to `path` that a power cut interrupted. The temporary file
+/// takes the place of a missing `path` and is deleted otherwise, keeping the old
+/// contents.
+pub fn recover_atomic(path: &Path) -> io::Result<()> {
+    let temp = temp_path(path);
+    if !temp.exists() {
+        return Ok(());
+    }
+    if path.exists() {
+        log::warn!("Deleting unfinished {}", temp.display());
+        std::fs::remove_file(&temp)
+    } else {
+        log::warn!("Restoring {} from {}", path.display(), temp.display());
+        std::fs::rename(&temp, path)
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(TEMP_SUFFIX);
+    path.with_file_name(temp_name)
+}
+
+/// One recording as listed in the index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingEntry {
+    pub file_name: String,
+    pub duration_ms: u64,
+    pub size_bytes: u64,
+    pub format: RecordingFormat,
+    /// Unix seconds when the recording was made; see [`is_valid_unix`].
+    pub created_at: u64,
+    /// Peak level, normalized to full scale.
+    pub peak: f32,
+    #[serde(default)]
+    pub uploaded: bool,
+}
+
+impl RecordingEntry {
+    /// `m:ss`, rounded up so short clips don't show as zero.
+    pub fn duration_label(&self) -> String {
+        let secs = self.duration_ms.div_ceil(1000);
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+
+    /// Creation time in `tz`, or a placeholder while the clock wasn't synced.
+    pub fn created_label(&self, tz: &PosixTz) -> String {
+        if !is_valid_unix(self.created_at) {
+            return "Time unknown".to_string();
+        }
+        tz.to_local(self.created_at as i64).datetime_label()
+    }
+
+    pub fn size_label(&self) -> String {
+        match self.size_bytes {
+            bytes if bytes < 1024 => format!("{} B", bytes),
+            bytes if bytes < 1024 * 1024 => format!("{:.1} KB", bytes as f64 / 1024.0),
+            bytes => format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)),
+        }
+    }
+}
+
+/// Limits applied by [`RecordingIndex::prune`]. `None` disables a limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub max_count: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Also let the recordings of `index` take no more than they do now plus the free
+    /// space of `storage`, minus `reserve` bytes for the other files written there.
+    pub fn within_free_space(
+        mut self,
+        index: &RecordingIndex,
+        storage: &StorageInfo,
+        reserve: u64,
+    ) -> Self {
+        let budget = (index.total_bytes() + storage.free_bytes).saturating_sub(reserve);
+        self.max_bytes = Some(self.max_bytes.map_or(budget, |max| max.min(budget)));
+        self
+    }
+}
+
+/// The recordings in a directory, oldest first.
+#[derive(Debug)]
+pub struct RecordingIndex {
+    dir: PathBuf,
+    entries: Vec<RecordingEntry>,
+}
+
+impl RecordingIndex {
+    /// Load the index of `dir`, reconciling it with the files actually present.
+    ///
+    /// A missing or unreadable index is rebuilt from the recordings and their sidecars.
+    /// Recordings cut short by a power cut are repaired first.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        repair_recordings(&dir)?;
+        let entries = match std::fs::read(dir.join(INDEX_FILE)) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                log::warn!("Rebuilding corrupt recordings index: {}", e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut index = Self { dir, entries };
+        if index.reconcile()? {
+            index.save()?;
+        }
+        Ok(index)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn entries(&self) -> &[RecordingEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, file_name: &str) -> Option<&RecordingEntry> {
+        self.entries.iter().find(|e| e.file_name == file_name)
+    }
+
+    pub fn path(&self, file_name: &str) -> PathBuf {
+        self.dir.join(file_name)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.size_bytes).sum()
+    }
+
+    /// A path for a new recording that clashes with no existing file.
+    ///
+    /// Names are `rec_<unix>.<ext>`, with a `_<n>` suffix when the clock repeats itself.
+    pub fn next_path(&self, created_at: u64, format: RecordingFormat) -> PathBuf {
+        let extension = format.extension();
+        (0..)
+            .map(|n| match n {
+                0 => format!("{}{}.{}", FILE_PREFIX, created_at, extension),
+                n => format!("{}{}_{}.{}", FILE_PREFIX, created_at, n, extension),
+            })
+            .find(|name| self.get(name).is_none() && !self.dir.join(name).exists())
+            .map(|name| self.dir.join(name))
+            .unwrap()
+    }
+
+    /// Record a newly written file, measuring its size on disk.
+    pub fn add(
+        &mut self,
+        path: &Path,
+        created_at: u64,
+        metadata: &RecordingMetadata,
+    ) -> io::Result<&RecordingEntry> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?
+            .to_string();
+        let size_bytes = std::fs::metadata(self.dir.join(&file_name))?.len();
+
+        self.entries.retain(|e| e.file_name != file_name);
+        self.entries.push(RecordingEntry {
+            file_name,
+            duration_ms: metadata.duration_ms,
+            size_bytes,
+            format: metadata.format,
+            created_at,
+            peak: metadata.levels.peak,
+            uploaded: false,
+        });
+        self.save()?;
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Delete a recording and its sidecar. Returns `false` if it wasn't indexed.
+    pub fn remove(&mut self, file_name: &str) -> io::Result<bool> {
+        let Some(position) = self.entries.iter().position(|e| e.file_name == file_name) else {
+            return Ok(false);
+        };
+        self.delete_files(file_name)?;
+        self.entries.remove(position);
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn mark_uploaded(&mut self, file_name: &str) -> io::Result<bool> {
+        let Some(entry) = self.entries.iter_mut().find(|e| e.file_name == file_name) else {
+            return Ok(false);
+        };
+        entry.uploaded = true;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Delete the recordings older than `policy` allows, then the oldest ones until the
+    /// rest fits, returning the deleted names.
+    ///
+    /// `now` is unix seconds; age limits are skipped while it or a recording's
+    /// timestamp is not trustworthy, so an expired recording can follow one that is kept.
+    pub fn prune(&mut self, policy: &RetentionPolicy, now: u64) -> io::Result<Vec<String>> {
+        let mut removed = Vec::new();
+
+        if let Some(max_age) = policy.max_age.filter(|_| is_valid_unix(now)) {
+            let mut i = 0;
+            while i < self.entries.len() {
+                let created_at = self.entries[i].created_at;
+                if is_valid_unix(created_at) && now.saturating_sub(created_at) > max_age.as_secs() {
+                    let expired = self.entries.remove(i);
+                    self.delete_files(&expired.file_name)?;
+                    removed.push(expired.file_name);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        let mut total_bytes = self.total_bytes();
+        while !self.entries.is_empty() {
+            let too_many = policy.max_count.is_some_and(|max| self.entries.len() > max);
+            let too_big = policy.max_bytes.is_some_and(|max| total_bytes > max);
+            if !(too_many || too_big) {
+                break;
+            }
+
+            let oldest = self.entries.remove(0);
+            self.delete_files(&oldest.file_name)?;
+            total_bytes -= oldest.size_bytes;
+            removed.push(oldest.file_name);
+        }
+
+        if !removed.is_empty() {
+            log::info!("Pruned {} recordings: {:?}", removed.len(), removed);
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn delete_files(&self, file_name: &str) -> io::Result<()> {
+        let path = self.dir.join(file_name);
+        for path in [RecordingMetadata::sidecar_path(&path), path] {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        write_atomic(
+            &self.dir.join(INDEX_FILE),
+            &serde_json::to_vec(&self.entries)?,
+        )
+    }
+
+    /// Drop entries whose file is gone and index recordings the index doesn't know about.
+    /// Returns whether anything changed.
+    fn reconcile(&mut self) -> io::Result<bool> {
+        let before = self.entries.len();
+        self.entries
+            .retain(|e| self.dir.join(&e.file_name).is_file());
+        let mut changed = self.entries.len() != before;
+
+        let mut unknown = Vec::new();
+        for dir_entry in std::fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let Ok(file_name) = dir_entry.file_name().into_string() else {
+                continue;
+            };
+            let Some(format) = format_from_name(&file_name) else {
+                continue;
+            };
+            if self.get(&file_name).is_none() {
+                unknown.push((file_name, format, dir_entry.metadata()?.len()));
+            }
+        }
+
+        unknown.sort_by_key(|(name, _, _)| (created_from_name(name), name.clone()));
+        for (file_name, format, size_bytes) in unknown {
+            let metadata = RecordingMetadata::load(&self.dir.join(&file_name)).ok();
+            self.entries.push(RecordingEntry {
+                created_at: created_from_name(&file_name),
+                duration_ms: metadata.as_ref().map_or(0, |m| m.duration_ms),
+                peak: metadata.as_ref().map_or(0.0, |m| m.levels.peak),
+                format: metadata.map_or(format, |m| m.format),
+                size_bytes,
+                uploaded: false,
+                file_name,
+            });
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+/// Fix the WAV headers of truncated recordings and deal with files whose write never
+/// finished: WAV recordings are kept as far as they got, other recordings and sidecars
+/// are deleted, and other files such as the index go through [`recover_atomic`].
+///
+/// Returns the names of the recordings that were repaired or recovered.
+pub fn repair_recordings(dir: &Path) -> io::Result<Vec<String>> {
+    let mut repaired = Vec::new();
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let Ok(file_name) = dir_entry.file_name().into_string() else {
+            continue;
+        };
+        let path = dir_entry.path();
+        if !file_name.starts_with(FILE_PREFIX) {
+            if let Some(final_name) = file_name.strip_suffix(TEMP_SUFFIX) {
+                recover_atomic(&dir.join(final_name))?;
+            }
+            continue;
+        }
+
+        if let Some(final_name) = file_name.strip_suffix(TEMP_SUFFIX) {
+            let final_path = dir.join(final_name);
+            let recovered = final_name.ends_with(".wav")
+                && !final_path.exists()
+                && wav::repair_file(&path).is_ok();
+            if recovered {
+                std::fs::rename(&path, &final_path)?;
+                log::warn!("Recovered unfinished recording {}", final_name);
+                repaired.push(final_name.to_string());
+            } else {
+                log::warn!("Deleting unfinished {}", file_name);
+                std::fs::remove_file(&path)?;
+            }
+        } else if file_name.ends_with(".wav") {
+            match wav::repair_file(&path) {
+                Ok(true) => {
+                    log::warn!("Repaired truncated recording {}", file_name);
+                    repaired.push(file_name);
+                }
+                Ok(false) => {}
+                // Not even the header made it; there is nothing to play.
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    log::warn!("Deleting unreadable recording {}: {}", file_name, e);
+                    std::fs::remove_file(&path)?;
+                    match std::fs::remove_file(RecordingMetadata::sidecar_path(&path)) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(repaired)
+}
+
+/// The format implied by a recording's file name, or `None` for other files. The
+/// sidecar, when present, tells PCM and ADPCM WAV files apart.
+fn format_from_name(file_name: &str) -> Option<RecordingFormat> {
+    if !file_name.starts_with(FILE_PREFIX) {
+        return None;
+    }
+    match Path::new(file_name).extension()?.to_str()? {
+        "wav" => Some(RecordingFormat::Pcm),
+        "opus" => Some(RecordingFormat::Opus),
+        _ => None,
+    }
+}
+
+fn created_from_name(file_name: &str) -> u64 {
+    let digits = file_name[FILE_PREFIX.len()..]
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .unwrap_or_default();
+    digits.parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MIN_VALID_UNIX;
+    use crate::level::AudioLevels;
+    use crate::test_util::TempDir;
+
+    fn metadata(duration_ms: u64) -> RecordingMetadata {
+        RecordingMetadata {
+            sample_rate: 16000,
+            duration_ms,
+            levels: AudioLevels {
+                rms: 0.1,
+                peak: 0.5,
+                clip_count: 0,
+            },
+            format: RecordingFormat::ImaAdpcm,
+        }
+    }
+
+    /// A silent PCM WAV file of `size` bytes, at least the 44 of the header.
+    fn wav_file(size: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        wav::write_pcm(&mut bytes, &vec![0; (size - 44) / 2], 16000).unwrap();
+        bytes
+    }
+
+    fn record(index: &mut RecordingIndex, created_at: u64, size: usize) -> String {
+        let path = index.next_path(created_at, RecordingFormat::ImaAdpcm);
+        std::fs::write(&path, wav_file(size)).unwrap();
+        metadata(1500).save(&path).unwrap();
+        index
+            .add(&path, created_at, &metadata(1500))
+            .unwrap()
+            .file_name
+            .clone()
+    }
+
+    #[test]
+    fn test_add_and_reopen() {
+        let dir = TempDir::new("reopen");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        assert!(index.entries().is_empty());
+
+        let name = record(&mut index, MIN_VALID_UNIX + 10, 2048);
+        let entry = index.get(&name).unwrap();
+        assert_eq!(entry.size_bytes, 2048);
+        assert_eq!(entry.format, RecordingFormat::ImaAdpcm);
+        assert_eq!(entry.duration_label(), "0:02");
+        assert_eq!(entry.size_label(), "2.0 KB");
+        assert_eq!(entry.created_label(&PosixTz::utc()), "2020-01-01 00:00 UTC");
+        assert!(index.mark_uploaded(&name).unwrap());
+
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        assert_eq!(index.entries().len(), 1);
+        assert!(index.get(&name).unwrap().uploaded);
+    }
+
+    #[test]
+    fn test_names_do_not_collide() {
+        let dir = TempDir::new("collide");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        let first = record(&mut index, 5, 100);
+        let second = record(&mut index, 5, 100);
+        assert_eq!(first, "rec_5.wav");
+        assert_eq!(
+            index.get(&first).unwrap().created_label(&PosixTz::utc()),
+            "Time unknown"
+        );
+        assert_eq!(second, "rec_5_1.wav");
+        assert_eq!(index.entries().len(), 2);
+    }
+
+    #[test]
+    fn test_remove_deletes_sidecar() {
+        let dir = TempDir::new("remove");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        let name = record(&mut index, 1, 100);
+
+        assert!(index.remove(&name).unwrap());
+        assert!(!dir.0.join(&name).exists());
+        assert!(!dir.0.join("rec_1.wav.json").exists());
+        assert!(!index.remove(&name).unwrap());
+    }
+
+    #[test]
+    fn test_rebuilds_from_files() {
+        let dir = TempDir::new("rebuild");
+        std::fs::write(dir.0.join("rec_20.wav"), wav_file(100)).unwrap();
+        std::fs::write(dir.0.join("rec_10.opus"), [0u8; 50]).unwrap();
+        metadata(3000).save(&dir.0.join("rec_20.wav")).unwrap();
+        std::fs::write(dir.0.join("notes.txt"), "ignored").unwrap();
+        std::fs::write(dir.0.join(INDEX_FILE), "not json").unwrap();
+
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        let names: Vec<_> = index.entries().iter().map(|e| &e.file_name).collect();
+        assert_eq!(names, ["rec_10.opus", "rec_20.wav"]);
+        assert_eq!(index.entries()[0].format, RecordingFormat::Opus);
+        assert_eq!(index.entries()[1].duration_ms, 3000);
+        assert_eq!(index.total_bytes(), 150);
+
+        // Files deleted behind the index's back are dropped on the next open.
+        std::fs::remove_file(dir.0.join("rec_10.opus")).unwrap();
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        assert_eq!(index.entries().len(), 1);
+    }
+
+    #[test]
+    fn test_repairs_on_open() {
+        let dir = TempDir::new("repair");
+        let mut truncated = wav_file(1000);
+        truncated.truncate(501);
+        std::fs::write(dir.0.join("rec_1.wav"), &truncated).unwrap();
+        // Power cut while writing: a partial recording, sidecar and index.
+        std::fs::write(dir.0.join("rec_2.wav.tmp"), &truncated).unwrap();
+        std::fs::write(dir.0.join("rec_2.wav.json.tmp"), "{\"sample").unwrap();
+        std::fs::write(dir.0.join("rec_3.opus.tmp"), [0u8; 10]).unwrap();
+        std::fs::write(dir.0.join("rec_4.wav"), []).unwrap();
+
+        assert_eq!(
+            repair_recordings(&dir.0).unwrap().len(),
+            2,
+            "rec_1.wav and rec_2.wav"
+        );
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        let names: Vec<_> = index.entries().iter().map(|e| &e.file_name).collect();
+        assert_eq!(names, ["rec_1.wav", "rec_2.wav"]);
+        for name in names {
+            let (_, samples) = wav::read(std::fs::File::open(dir.0.join(name)).unwrap()).unwrap();
+            assert_eq!(samples.len(), (500 - 44) / 2);
+        }
+        let mut files: Vec<_> = std::fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, [INDEX_FILE, "rec_1.wav", "rec_2.wav"]);
+    }
+
+    #[test]
+    fn test_recovers_interrupted_writes() {
+        let dir = TempDir::new("interrupted");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        let name = record(&mut index, MIN_VALID_UNIX, 100);
+        index.mark_uploaded(&name).unwrap();
+        // Cut off after the old index was deleted, before the new one was renamed.
+        let index_path = dir.0.join(INDEX_FILE);
+        std::fs::rename(&index_path, dir.0.join("index.json.tmp")).unwrap();
+        // Cut off while writing: the old queue is still there.
+        std::fs::write(dir.0.join("uploads.json"), "old").unwrap();
+        std::fs::write(dir.0.join("uploads.json.tmp"), "new").unwrap();
+
+        let index = RecordingIndex::open(&dir.0).unwrap();
+        assert!(
+            index.get(&name).unwrap().uploaded,
+            "not rebuilt from the sidecars"
+        );
+        assert!(!dir.0.join("index.json.tmp").exists());
+        assert_eq!(std::fs::read(dir.0.join("uploads.json")).unwrap(), b"old");
+        assert!(!dir.0.join("uploads.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_write_atomic_replaces() {
+        let dir = TempDir::new("atomic");
+        let path = dir.0.join("file.json");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!dir.0.join("file.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_prune_by_count_and_bytes() {
+        let dir = TempDir::new("prune");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        // Unsynced timestamps go backwards; insertion order still decides the oldest.
+        let names: Vec<_> = [30, 20, 10, 40]
+            .into_iter()
+            .map(|t| record(&mut index, t, 100))
+            .collect();
+
+        let policy = RetentionPolicy {
+            max_count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(index.prune(&policy, 0).unwrap(), [names[0].clone()]);
+        assert!(!dir.0.join(&names[0]).exists());
+
+        let policy = RetentionPolicy {
+            max_bytes: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(index.prune(&policy, 0).unwrap(), names[1..3]);
+        assert_eq!(index.entries().len(), 1);
+        assert_eq!(index.total_bytes(), 100);
+    }
+
+    #[test]
+    fn test_within_free_space() {
+        let dir = TempDir::new("free-space");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        let name = record(&mut index, 0, 1000);
+        let total = index.total_bytes();
+        let storage = StorageInfo {
+            total_bytes: 1 << 20,
+            free_bytes: 3000,
+        };
+
+        let policy = RetentionPolicy::default().within_free_space(&index, &storage, 1000);
+        assert_eq!(policy.max_bytes, Some(total + 2000));
+        let capped = RetentionPolicy {
+            max_bytes: Some(total),
+            ..Default::default()
+        };
+        assert_eq!(
+            capped.within_free_space(&index, &storage, 1000).max_bytes,
+            Some(total)
+        );
+
+        // Nearly full: the recordings have to make room for the reserve.
+        let full = StorageInfo {
+            free_bytes: 0,
+            ..storage
+        };
+        let policy = RetentionPolicy::default().within_free_space(&index, &full, 1);
+        assert_eq!(index.prune(&policy, 0).unwrap(), [name]);
+    }
+
+    #[test]
+    fn test_prune_by_age() {
+        let dir = TempDir::new("age");
+        let mut index = RecordingIndex::open(&dir.0).unwrap();
+        let day = 24 * 60 * 60;
+        let now = MIN_VALID_UNIX + 30 * day;
+        let old = record(&mut index, now - 10 * day, 100);
+        record(&mut index, now - day, 100);
+
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(7 * day)),
+            ..Default::default()
+        };
+        // Without a synced clock nothing is considered too old.
+        assert!(index.prune(&policy, 100).unwrap().is_empty());
+        assert_eq!(index.prune(&policy, now).unwrap(), [old]);
+        assert_eq!(index.entries().len(), 1);
+
+        // Recordings kept for being recent or of unknown age do not shield expired
+        // ones added after them.
+        let recent = index.entries()[0].file_name.clone();
+        let unsynced = record(&mut index, 100, 100);
+        let old = record(&mut index, now - 8 * day, 100);
+        assert_eq!(index.prune(&policy, now).unwrap(), [old]);
+        let names: Vec<_> = index.entries().iter().map(|e| &e.file_name).collect();
+        assert_eq!(names, [&recent, &unsynced]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Aosong AHT20, and the AHT30 on the ESP32-S3-BOX-3's sensor dock, which speaks the
+//! same protocol.
+
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+
+use super::{crc8, I2cBus, IndoorReading, SensorSource};
+
+pub const ADDRESS: u8 = 0x38;
+
+const CMD_INITIALIZE: [u8; 3] = [0xBE, 0x08, 0x00];
+const CMD_MEASURE: [u8; 3] = [0xAC, 0x33, 0x00];
+const STATUS_BUSY: u8 = 0x80;
+const STATUS_CALIBRATED: u8 = 0x08;
+const MEASUREMENT_TIME: Duration = Duration::from_millis(80);
+
+/// Relative humidity in percent and °C from the 20-bit raw values.
+pub fn convert(raw_humidity: u32, raw_temperature: u32) -> (f64, f64) {
+    let scale = f64::from(1u32 << 20);
+    (
+        f64::from(raw_humidity) / scale * 100.0,
+        f64::from(raw_temperature) / scale * 200.0 - 50.0,
+    )
+}
+
+/// Split the five data bytes after the status into the raw humidity and temperature,
+/// which share the middle byte.
+pub fn unpack(data: &[u8; 5]) -> (u32, u32) {
+    let humidity =
+        (u32::from(data[0]) << 12) | (u32::from(data[1]) << 4) | (u32::from(data[2]) >> 4);
+    let temperature =
+        (u32::from(data[2] & 0x0F) << 16) | (u32::from(data[3]) << 8) | u32::from(data[4]);
+    (humidity, temperature)
+}
+
+/// Whether anything acknowledges a status read at the AHT20's address.
+pub fn probe<B: I2cBus>(bus: &mut B) -> bool {
+    let mut status = [0];
+    bus.read(ADDRESS, &mut status).is_ok()
+}
+
+pub struct Aht20<B> {
+    bus: B,
+}
+
+impl<B: I2cBus> Aht20<B> {
+    /// Load the calibration if the chip hasn't done so since power-up.
+    pub fn new(mut bus: B) -> io::Result<Self> {
+        let mut status = [0];
+        bus.read(ADDRESS, &mut status)?;
+        if status[0] & STATUS_CALIBRATED == 0 {
+            bus.write(ADDRESS, &CMD_INITIALIZE)?;
+            sleep(Duration::from_millis(10));
+        }
+        Ok(Self { bus })
+    }
+}
+
+impl<B: I2cBus> SensorSource for Aht20<B> {
+    fn name(&self) -> &'static str {
+        "AHT20"
+    }
+
+    fn read(&mut self) -> io::Result<IndoorReading> {
+        self.bus.write(ADDRESS, &CMD_MEASURE)?;
+        sleep(MEASUREMENT_TIME);
+        let mut data = [0; 7];
+        for _ in 0..5 {
+            self.bus.read(ADDRESS, &mut data)?;
+            if data[0] & STATUS_BUSY == 0 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        if data[0] & STATUS_BUSY != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "measurement did not finish",
+            ));
+        }
+        if crc8(&data[..6]) != data[6] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "CRC mismatch"));
+        }
+        let (raw_humidity, raw_temperature) = unpack(data[1..6].try_into().unwrap());
+        let (humidity, temperature) = convert(raw_humidity, raw_temperature);
+        Ok(IndoorReading {
+            temperature,
+            humidity: Some(humidity),
+            pressure: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::MockI2c;
+    use super::*;
+
+    #[test]
+    fn test_conversion() {
+        // The formulas of the datasheet, section 6.1, at the ends of the range.
+        assert_eq!(convert(0, 0), (0.0, -50.0));
+        assert_eq!(convert(1 << 19, 0x60000), (50.0, 25.0));
+        let (humidity, temperature) = convert(0xFFFFF, 0xFFFFF);
+        assert!((humidity - 100.0).abs() < 0.001 && (temperature - 150.0).abs() < 0.001);
+
+        assert_eq!(unpack(&[0x80, 0x00, 0x06, 0x00, 0x00]), (0x80000, 0x60000));
+        assert_eq!(unpack(&[0x12, 0x34, 0x5A, 0xBC, 0xDE]), (0x12345, 0xABCDE));
+    }
+
+    #[test]
+    fn test_read() {
+        let mut bus = MockI2c::new(ADDRESS);
+        // Not calibrated yet, so initialization is sent.
+        bus.responses.push_back(vec![0x10]);
+        let mut sensor = Aht20::new(&mut bus).unwrap();
+
+        let mut data = vec![0x1C, 0x80, 0x00, 0x06, 0x00, 0x00];
+        data.push(crc8(&data));
+        // Still busy on the first read.
+        sensor.bus.responses.push_back(vec![0x9C, 0, 0, 0, 0, 0, 0]);
+        sensor.bus.responses.push_back(data.clone());
+        let reading = sensor.read().unwrap();
+        assert_eq!(reading.temperature, 25.0);
+        assert_eq!(reading.humidity, Some(50.0));
+
+        data[6] ^= 1;
+        sensor.bus.responses.push_back(data);
+        assert_eq!(
+            sensor.read().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(bus.writes, [&CMD_INITIALIZE, &CMD_MEASURE, &CMD_MEASURE]);
+    }
+}
@@ -0,0 +1,388 @@
+//! Bosch BME280 (temperature, humidity, pressure) and BMP280 (no humidity), run in
+//! forced mode: one measurement per read, sleeping in between.
+
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+
+use super::{I2cBus, IndoorReading, SensorSource};
+
+/// SDO to ground, or to VDD.
+pub const ADDRESSES: [u8; 2] = [0x76, 0x77];
+
+const REG_CALIB_TP: u8 = 0x88;
+const REG_CALIB_H1: u8 = 0xA1;
+const REG_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_DATA: u8 = 0xF7;
+
+const BME280_ID: u8 = 0x60;
+const BMP280_ID: u8 = 0x58;
+const RESET: u8 = 0xB6;
+/// Oversampling x1 for temperature (bits 7..5) and pressure (4..2), forced mode (1..0).
+const CTRL_MEAS_FORCED: u8 = 0b0010_0101;
+/// Humidity oversampling x1.
+const CTRL_HUM_X1: u8 = 0b001;
+const STATUS_MEASURING: u8 = 0x08;
+/// Typical conversion time with x1 oversampling; the status bit covers the rest.
+const MEASUREMENT_TIME: Duration = Duration::from_millis(10);
+
+/// Trimming parameters from the chip's NVM, named as in the datasheet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Calibration {
+    pub t1: u16,
+    pub t2: i16,
+    pub t3: i16,
+    pub p1: u16,
+    pub p2: i16,
+    pub p3: i16,
+    pub p4: i16,
+    pub p5: i16,
+    pub p6: i16,
+    pub p7: i16,
+    pub p8: i16,
+    pub p9: i16,
+    pub h1: u8,
+    pub h2: i16,
+    pub h3: u8,
+    pub h4: i16,
+    pub h5: i16,
+    pub h6: i8,
+}
+
+impl Calibration {
+    /// Decode registers 0x88..=0x9F, 0xA1 and, on the BME280, 0xE1..=0xE7.
+    pub fn parse(tp: &[u8; 24], h1: u8, h: &[u8; 7]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([tp[i], tp[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([tp[i], tp[i + 1]]);
+        Self {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p1: u16_at(6),
+            p2: i16_at(8),
+            p3: i16_at(10),
+            p4: i16_at(12),
+            p5: i16_at(14),
+            p6: i16_at(16),
+            p7: i16_at(18),
+            p8: i16_at(20),
+            p9: i16_at(22),
+            h1,
+            h2: i16::from_le_bytes([h[0], h[1]]),
+            h3: h[2],
+            // Two 12-bit values packed into three bytes, sharing the middle one.
+            h4: (i16::from(h[3] as i8) << 4) | i16::from(h[4] & 0x0F),
+            h5: (i16::from(h[5] as i8) << 4) | i16::from(h[4] >> 4),
+            h6: h[6] as i8,
+        }
+    }
+
+    /// The fine temperature carried into the other two formulas, and the temperature
+    /// in 0.01 °C.
+    pub fn compensate_temperature(&self, adc_t: i32) -> (i32, i32) {
+        let t1 = i32::from(self.t1);
+        let var1 = (((adc_t >> 3) - (t1 << 1)) * i32::from(self.t2)) >> 11;
+        let var2 = (((((adc_t >> 4) - t1) * ((adc_t >> 4) - t1)) >> 12) * i32::from(self.t3)) >> 14;
+        let t_fine = var1 + var2;
+        (t_fine, (t_fine * 5 + 128) >> 8)
+    }
+
+    /// Pressure in Pa as Q24.8, i.e. 1/256 Pa.
+    pub fn compensate_pressure(&self, adc_p: i32, t_fine: i32) -> u32 {
+        let mut var1 = i64::from(t_fine) - 128000;
+        let mut var2 = var1 * var1 * i64::from(self.p6);
+        var2 += (var1 * i64::from(self.p5)) << 17;
+        var2 += i64::from(self.p4) << 35;
+        var1 = ((var1 * var1 * i64::from(self.p3)) >> 8) + ((var1 * i64::from(self.p2)) << 12);
+        var1 = (((1i64 << 47) + var1) * i64::from(self.p1)) >> 33;
+        if var1 == 0 {
+            // Avoid dividing by zero with a blank calibration.
+            return 0;
+        }
+        let mut p = 1048576 - i64::from(adc_p);
+        p = (((p << 31) - var2) * 3125) / var1;
+        let var1 = (i64::from(self.p9) * (p >> 13) * (p >> 13)) >> 25;
+        let var2 = (i64::from(self.p8) * p) >> 19;
+        (((p + var1 + var2) >> 8) + (i64::from(self.p7) << 4)) as u32
+    }
+
+    /// Relative humidity in percent as Q22.10, i.e. 1/1024 %.
+    pub fn compensate_humidity(&self, adc_h: i32, t_fine: i32) -> u32 {
+        let mut v = t_fine - 76800;
+        v = ((((adc_h << 14) - (i32::from(self.h4) << 20) - (i32::from(self.h5) * v)) + 16384)
+            >> 15)
+            * (((((((v * i32::from(self.h6)) >> 10)
+                * (((v * i32::from(self.h3)) >> 11) + 32768))
+                >> 10)
+                + 2097152)
+                * i32::from(self.h2)
+                + 8192)
+                >> 14);
+        v -= ((((v >> 15) * (v >> 15)) >> 7) * i32::from(self.h1)) >> 4;
+        (v.clamp(0, 419430400) >> 12) as u32
+    }
+}
+
+/// Whether a BME280 or BMP280 answers at `address`.
+pub fn probe<B: I2cBus>(bus: &mut B, address: u8) -> bool {
+    let mut id = [0];
+    bus.write_read(address, &[REG_ID], &mut id).is_ok() && matches!(id[0], BME280_ID | BMP280_ID)
+}
+
+pub struct Bme280<B> {
+    bus: B,
+    address: u8,
+    calibration: Calibration,
+    /// A BMP280 has no humidity sensor.
+    has_humidity: bool,
+}
+
+impl<B: I2cBus> Bme280<B> {
+    /// Reset the chip and read its calibration.
+    pub fn new(mut bus: B, address: u8) -> io::Result<Self> {
+        let mut id = [0];
+        bus.write_read(address, &[REG_ID], &mut id)?;
+        let has_humidity = match id[0] {
+            BME280_ID => true,
+            BMP280_ID => false,
+            id => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected chip ID 0x{:02X}", id),
+                ))
+            }
+        };
+        bus.write(address, &[REG_RESET, RESET])?;
+        // Copying the NVM takes 2 ms after a reset.
+        sleep(Duration::from_millis(2));
+
+        let mut tp = [0; 24];
+        bus.write_read(address, &[REG_CALIB_TP], &mut tp)?;
+        let mut h1 = [0];
+        let mut h = [0; 7];
+        if has_humidity {
+            bus.write_read(address, &[REG_CALIB_H1], &mut h1)?;
+            bus.write_read(address, &[REG_CALIB_H], &mut h)?;
+        }
+        Ok(Self {
+            bus,
+            address,
+            calibration: Calibration::parse(&tp, h1[0], &h),
+            has_humidity,
+        })
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Turn raw burst data from 0xF7 into a reading.
+    fn decode(&self, data: &[u8; 8]) -> io::Result<IndoorReading> {
+        let adc_p =
+            (i32::from(data[0]) << 12) | (i32::from(data[1]) << 4) | (i32::from(data[2]) >> 4);
+        let adc_t =
+            (i32::from(data[3]) << 12) | (i32::from(data[4]) << 4) | (i32::from(data[5]) >> 4);
+        let adc_h = (i32::from(data[6]) << 8) | i32::from(data[7]);
+        // 0x80000 is what a skipped measurement reads as.
+        if adc_t == 0x80000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no temperature measured",
+            ));
+        }
+        let (t_fine, temperature) = self.calibration.compensate_temperature(adc_t);
+        let pressure = (adc_p != 0x80000).then(|| {
+            f64::from(self.calibration.compensate_pressure(adc_p, t_fine)) / 256.0 / 100.0
+        });
+        let humidity = (self.has_humidity && adc_h != 0x8000)
+            .then(|| f64::from(self.calibration.compensate_humidity(adc_h, t_fine)) / 1024.0);
+        Ok(IndoorReading {
+            temperature: f64::from(temperature) / 100.0,
+            humidity,
+            pressure,
+        })
+    }
+}
+
+impl<B: I2cBus> SensorSource for Bme280<B> {
+    fn name(&self) -> &'static str {
+        if self.has_humidity {
+            "BME280"
+        } else {
+            "BMP280"
+        }
+    }
+
+    fn read(&mut self) -> io::Result<IndoorReading> {
+        if self.has_humidity {
+            // Only takes effect with the following write to ctrl_meas.
+            self.bus.write(self.address, &[REG_CTRL_HUM, CTRL_HUM_X1])?;
+        }
+        self.bus
+            .write(self.address, &[REG_CTRL_MEAS, CTRL_MEAS_FORCED])?;
+        sleep(MEASUREMENT_TIME);
+        let mut status = [STATUS_MEASURING];
+        for _ in 0..10 {
+            self.bus
+                .write_read(self.address, &[REG_STATUS], &mut status)?;
+            if status[0] & STATUS_MEASURING == 0 {
+                break;
+            }
+            sleep(Duration::from_millis(2));
+        }
+        if status[0] & STATUS_MEASURING != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "measurement did not finish",
+            ));
+        }
+        let mut data = [0; 8];
+        self.bus.write_read(self.address, &[REG_DATA], &mut data)?;
+        self.decode(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::MockI2c;
+    use super::*;
+
+    /// The worked example of the BMP280 datasheet, section 3.12, whose temperature and
+    /// pressure compensation the BME280 shares.
+    fn datasheet_calibration() -> Calibration {
+        Calibration {
+            t1: 27504,
+            t2: 26435,
+            t3: -1000,
+            p1: 36477,
+            p2: -10685,
+            p3: 3024,
+            p4: 2855,
+            p5: 140,
+            p6: -7,
+            p7: 15500,
+            p8: -14600,
+            p9: 6000,
+            h1: 75,
+            h2: 362,
+            h3: 0,
+            h4: 313,
+            h5: 50,
+            h6: 30,
+        }
+    }
+
+    /// The floating-point humidity formula from the BME280 datasheet, section 8.1.
+    fn humidity_f64(c: &Calibration, adc_h: i32, t_fine: i32) -> f64 {
+        let var = f64::from(t_fine) - 76800.0;
+        let var = (f64::from(adc_h) - (f64::from(c.h4) * 64.0 + f64::from(c.h5) / 16384.0 * var))
+            * (f64::from(c.h2) / 65536.0
+                * (1.0
+                    + f64::from(c.h6) / 67108864.0
+                        * var
+                        * (1.0 + f64::from(c.h3) / 67108864.0 * var)));
+        (var * (1.0 - f64::from(c.h1) * var / 524288.0)).clamp(0.0, 100.0)
+    }
+
+    #[test]
+    fn test_datasheet_example() {
+        let calibration = datasheet_calibration();
+        let (t_fine, temperature) = calibration.compensate_temperature(519888);
+        assert_eq!(t_fine, 128422);
+        assert_eq!(temperature, 2508);
+        let pressure = f64::from(calibration.compensate_pressure(415148, t_fine)) / 256.0;
+        assert!((pressure - 100653.27).abs() < 0.5, "{}", pressure);
+    }
+
+    #[test]
+    fn test_humidity_matches_float_formula() {
+        let calibration = datasheet_calibration();
+        for adc_h in [20000, 26000, 30000, 36000] {
+            for t_fine in [50000, 128422, 150000] {
+                let fixed = f64::from(calibration.compensate_humidity(adc_h, t_fine)) / 1024.0;
+                let float = humidity_f64(&calibration, adc_h, t_fine);
+                assert!(
+                    (fixed - float).abs() < 0.01,
+                    "adc_h {}: {} vs {}",
+                    adc_h,
+                    fixed,
+                    float
+                );
+            }
+        }
+        // Clamped at both ends.
+        assert_eq!(calibration.compensate_humidity(0, 128422), 0);
+        assert_eq!(calibration.compensate_humidity(65535, 128422), 100 << 10);
+    }
+
+    #[test]
+    fn test_parse_calibration() {
+        let c = datasheet_calibration();
+        let mut tp = Vec::new();
+        tp.extend_from_slice(&c.t1.to_le_bytes());
+        for value in [c.t2, c.t3] {
+            tp.extend_from_slice(&value.to_le_bytes());
+        }
+        tp.extend_from_slice(&c.p1.to_le_bytes());
+        for value in [c.p2, c.p3, c.p4, c.p5, c.p6, c.p7, c.p8, c.p9] {
+            tp.extend_from_slice(&value.to_le_bytes());
+        }
+        // H4 = 313 = 0x139 and H5 = 50 = 0x032 share 0xE5.
+        let h = [0x6A, 0x01, 0x00, 0x13, 0x29, 0x03, 30];
+        assert_eq!(Calibration::parse(&tp.try_into().unwrap(), 75, &h), c);
+
+        let negative = Calibration::parse(&[0; 24], 0, &[0, 0, 0, 0xFF, 0xEF, 0xFF, 0xF6]);
+        assert_eq!((negative.h4, negative.h5, negative.h6), (-1, -2, -10));
+    }
+
+    #[test]
+    fn test_read() {
+        let mut bus = MockI2c::new(0x77);
+        bus.registers[REG_ID as usize] = BMP280_ID;
+        assert!(!probe(&mut bus, 0x76));
+        assert!(probe(&mut bus, 0x77));
+
+        let c = datasheet_calibration();
+        let mut tp = Vec::new();
+        for value in [
+            c.t1 as i16,
+            c.t2,
+            c.t3,
+            c.p1 as i16,
+            c.p2,
+            c.p3,
+            c.p4,
+            c.p5,
+            c.p6,
+            c.p7,
+            c.p8,
+            c.p9,
+        ] {
+            tp.extend_from_slice(&value.to_le_bytes());
+        }
+        bus.registers[0x88..0xA0].copy_from_slice(&tp);
+        // adc_P = 415148 = 0x655AC and adc_T = 519888 = 0x7EED0.
+        bus.registers[0xF7..0xFF]
+            .copy_from_slice(&[0x65, 0x5A, 0xC0, 0x7E, 0xED, 0x00, 0x80, 0x00]);
+
+        let mut sensor = Bme280::new(&mut bus, 0x77).unwrap();
+        assert_eq!(sensor.name(), "BMP280");
+        let reading = sensor.read().unwrap();
+        assert_eq!(reading.temperature, 25.08);
+        assert!((reading.pressure.unwrap() - 1006.53).abs() < 0.01);
+        assert_eq!(reading.humidity, None);
+        assert_eq!(bus.writes[0], [REG_RESET, RESET]);
+        assert_eq!(bus.writes[1], [REG_CTRL_MEAS, CTRL_MEAS_FORCED]);
+
+        bus.registers[REG_ID as usize] = 0x55;
+        assert_eq!(
+            Bme280::new(&mut bus, 0x77).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
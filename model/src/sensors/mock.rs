@@ -0,0 +1,66 @@
+//! A single device on a fake bus, for testing the drivers.
+
+use std::collections::VecDeque;
+use std::io;
+
+use super::I2cBus;
+
+pub(crate) struct MockI2c {
+    pub address: u8,
+    /// Register file of a register-based chip like the BME280, read with `write_read`
+    /// and written with two-byte writes.
+    pub registers: Vec<u8>,
+    /// Answers to plain reads of a command-based chip, oldest first.
+    pub responses: VecDeque<Vec<u8>>,
+    pub writes: Vec<Vec<u8>>,
+}
+
+impl MockI2c {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            registers: vec![0; 256],
+            responses: VecDeque::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    fn check_address(&self, address: u8) -> io::Result<()> {
+        if address == self.address {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no ACK from 0x{:02X}", address),
+            ))
+        }
+    }
+}
+
+impl I2cBus for MockI2c {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()> {
+        self.check_address(address)?;
+        if let [register, value] = *bytes {
+            self.registers[register as usize] = value;
+        }
+        self.writes.push(bytes.to_vec());
+        Ok(())
+    }
+
+    fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+        self.check_address(address)?;
+        let response = self
+            .responses
+            .pop_front()
+            .ok_or_else(|| io::Error::other("no response queued"))?;
+        buf.copy_from_slice(&response[..buf.len()]);
+        Ok(())
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> io::Result<()> {
+        self.check_address(address)?;
+        let start = bytes[0] as usize;
+        buf.copy_from_slice(&self.registers[start..start + buf.len()]);
+        Ok(())
+    }
+}
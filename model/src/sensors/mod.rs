@@ -0,0 +1,137 @@
+//! Indoor temperature, humidity and pressure from I2C sensors.
+//!
+//! The drivers only need an [`I2cBus`]; the ESP32 build implements it on top of
+//! `I2cDriver` and the desktop on Linux's `/dev/i2c-*`. The register decoding is plain
+//! Rust, so it is tested on the host against the datasheets.
+
+use std::io;
+
+pub mod aht20;
+pub mod bme280;
+pub mod sht3x;
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+pub use aht20::Aht20;
+pub use bme280::Bme280;
+pub use sht3x::Sht3x;
+
+/// An I2C master, addressed with 7-bit addresses.
+pub trait I2cBus {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()>;
+
+    fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Write then read in one transaction, as register reads need.
+    fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> io::Result<()>;
+}
+
+impl<B: I2cBus + ?Sized> I2cBus for &mut B {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()> {
+        (**self).write(address, bytes)
+    }
+
+    fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read(address, buf)
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> io::Result<()> {
+        (**self).write_read(address, bytes, buf)
+    }
+}
+
+/// One reading of an indoor sensor; `None` for what it doesn't measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndoorReading {
+    /// °C.
+    pub temperature: f64,
+    /// Relative humidity in percent.
+    pub humidity: Option<f64>,
+    /// hPa.
+    pub pressure: Option<f64>,
+}
+
+/// Something that measures the room the device is in.
+pub trait SensorSource {
+    /// The chip, for the diagnostics page.
+    fn name(&self) -> &'static str;
+
+    /// Take a measurement; blocks for as long as the chip needs, up to about 100 ms.
+    fn read(&mut self) -> io::Result<IndoorReading>;
+}
+
+/// Probe the known addresses and return a driver for the first sensor that answers.
+pub fn detect<B: I2cBus + Send + 'static>(mut bus: B) -> io::Result<Box<dyn SensorSource + Send>> {
+    for address in bme280::ADDRESSES {
+        if bme280::probe(&mut bus, address) {
+            return Ok(Box::new(Bme280::new(bus, address)?));
+        }
+    }
+    for address in sht3x::ADDRESSES {
+        if sht3x::probe(&mut bus, address) {
+            return Ok(Box::new(Sht3x::new(bus, address)?));
+        }
+    }
+    if aht20::probe(&mut bus) {
+        return Ok(Box::new(Aht20::new(bus)?));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no BME280, SHT3x or AHT20 on the bus",
+    ))
+}
+
+/// CRC-8 with polynomial 0x31 and initial value 0xFF, used by the SHT3x and AHT20.
+pub(crate) fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Check the CRC byte following each two-byte word, as the SHT3x sends them.
+pub(crate) fn check_words(data: &[u8]) -> io::Result<()> {
+    for word in data.chunks(3) {
+        if word.len() != 3 || crc8(&word[..2]) != word[2] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "CRC mismatch"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockI2c;
+    use super::*;
+
+    #[test]
+    fn test_crc8() {
+        // The example from the SHT3x datasheet, section 4.12.
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+        assert!(check_words(&[0xBE, 0xEF, 0x92, 0x00, 0x00, 0x81]).is_ok());
+        assert!(check_words(&[0xBE, 0xEF, 0x93]).is_err());
+        assert!(check_words(&[0xBE, 0xEF]).is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut bus = MockI2c::new(sht3x::ADDRESSES[1]);
+        // The status register, read by the probe.
+        bus.responses
+            .push_back(vec![0x80, 0x10, crc8(&[0x80, 0x10])]);
+        let sensor = detect(bus).unwrap();
+        assert_eq!(sensor.name(), "SHT3x");
+
+        let error = detect(MockI2c::new(0x50)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
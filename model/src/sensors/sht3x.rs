@@ -0,0 +1,112 @@
+//! Sensirion SHT30/SHT31/SHT35, read with single-shot measurements.
+
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+
+use super::{check_words, I2cBus, IndoorReading, SensorSource};
+
+/// ADDR to ground, or to VDD.
+pub const ADDRESSES: [u8; 2] = [0x44, 0x45];
+
+const CMD_SOFT_RESET: [u8; 2] = [0x30, 0xA2];
+const CMD_READ_STATUS: [u8; 2] = [0xF3, 0x2D];
+/// High repeatability, no clock stretching.
+const CMD_MEASURE: [u8; 2] = [0x24, 0x00];
+/// 15 ms at high repeatability.
+const MEASUREMENT_TIME: Duration = Duration::from_millis(16);
+
+/// °C from the raw temperature word.
+pub fn temperature(raw: u16) -> f64 {
+    -45.0 + 175.0 * f64::from(raw) / 65535.0
+}
+
+/// Relative humidity in percent from the raw humidity word.
+pub fn humidity(raw: u16) -> f64 {
+    100.0 * f64::from(raw) / 65535.0
+}
+
+/// Whether an SHT3x answers at `address` with a valid status register.
+pub fn probe<B: I2cBus>(bus: &mut B, address: u8) -> bool {
+    let mut status = [0; 3];
+    bus.write(address, &CMD_READ_STATUS).is_ok()
+        && bus.read(address, &mut status).is_ok()
+        && check_words(&status).is_ok()
+}
+
+pub struct Sht3x<B> {
+    bus: B,
+    address: u8,
+}
+
+impl<B: I2cBus> Sht3x<B> {
+    pub fn new(mut bus: B, address: u8) -> io::Result<Self> {
+        bus.write(address, &CMD_SOFT_RESET)?;
+        sleep(Duration::from_millis(2));
+        Ok(Self { bus, address })
+    }
+}
+
+impl<B: I2cBus> SensorSource for Sht3x<B> {
+    fn name(&self) -> &'static str {
+        "SHT3x"
+    }
+
+    fn read(&mut self) -> io::Result<IndoorReading> {
+        self.bus.write(self.address, &CMD_MEASURE)?;
+        sleep(MEASUREMENT_TIME);
+        let mut data = [0; 6];
+        self.bus.read(self.address, &mut data)?;
+        check_words(&data)?;
+        Ok(IndoorReading {
+            temperature: temperature(u16::from_be_bytes([data[0], data[1]])),
+            humidity: Some(humidity(u16::from_be_bytes([data[3], data[4]]))),
+            pressure: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crc8;
+    use super::super::mock::MockI2c;
+    use super::*;
+
+    #[test]
+    fn test_conversion() {
+        // The ends of the ranges in the datasheet, section 4.13.
+        assert_eq!(temperature(0), -45.0);
+        assert_eq!(temperature(0xFFFF), 130.0);
+        assert_eq!(humidity(0), 0.0);
+        assert_eq!(humidity(0xFFFF), 100.0);
+        assert!((temperature(0x6666) - 25.0).abs() < 0.01);
+        assert!((humidity(0x8000) - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut bus = MockI2c::new(0x44);
+        let mut sensor = Sht3x::new(&mut bus, 0x44).unwrap();
+        let mut data = vec![
+            0x66,
+            0x66,
+            crc8(&[0x66, 0x66]),
+            0x80,
+            0x00,
+            crc8(&[0x80, 0x00]),
+        ];
+        sensor.bus.responses.push_back(data.clone());
+        let reading = sensor.read().unwrap();
+        assert!((reading.temperature - 25.0).abs() < 0.01);
+        assert!((reading.humidity.unwrap() - 50.0).abs() < 0.01);
+        assert_eq!(reading.pressure, None);
+
+        data[5] ^= 1;
+        sensor.bus.responses.push_back(data);
+        assert_eq!(
+            sensor.read().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(bus.writes, [CMD_SOFT_RESET, CMD_MEASURE, CMD_MEASURE]);
+    }
+}
@@ -0,0 +1,426 @@
+//! User-tunable settings, persisted as JSON in NVS on the device and in a file on desktop.
+//!
+//! Fields added later must carry a default so older files still load; renames and unit
+//! changes go through [`MIGRATIONS`] instead.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::codec::RecordingFormat;
+
+/// One step per format change: `MIGRATIONS[n]` upgrades a version `n + 1` document.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[];
+
+/// Version written by this build.
+pub const VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+const FETCH_INTERVALS: [u32; 8] = [15, 30, 60, 120, 300, 600, 1800, 3600];
+/// Zero turns automatic recordings off.
+const RECORDING_INTERVALS: [u32; 8] = [0, 30, 60, 120, 300, 600, 1800, 3600];
+const RECORDING_LENGTHS: [u32; 6] = [3, 5, 10, 15, 20, 30];
+const SPI_CLOCKS: [u32; 6] = [
+    5_000_000, 10_000_000, 20_000_000, 26_000_000, 40_000_000, 80_000_000,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Locations selectable on the device, which has no keyboard.
+    pub const PRESETS: [(&'static str, f64, f64); 6] = [
+        ("Kitchener", 43.45, -80.49),
+        ("Toronto", 43.65, -79.38),
+        ("Vancouver", 49.28, -123.12),
+        ("New York", 40.71, -74.01),
+        ("London", 51.51, -0.13),
+        ("Berlin", 52.52, 13.40),
+    ];
+
+    pub fn new(name: &str, latitude: f64, longitude: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            latitude,
+            longitude,
+        }
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        let (name, latitude, longitude) = Self::PRESETS[0];
+        Self::new(name, latitude, longitude)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub fetch_interval_secs: u32,
+    /// Seconds between automatic recordings, or zero for none.
+    pub recording_interval_secs: u32,
+    pub recording_length_secs: u32,
+    pub recording_format: RecordingFormat,
+    /// Display and SD card bus clock; applied on the next boot.
+    pub spi_clock_hz: u32,
+    pub location: Location,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            fetch_interval_secs: 30,
+            recording_interval_secs: 60,
+            recording_length_secs: 10,
+            // A quarter of the PCM size without taxing the CPU.
+            recording_format: RecordingFormat::ImaAdpcm,
+            spi_clock_hz: 10_000_000,
+            location: Location::default(),
+        }
+    }
+}
+
+/// A row of the settings page, in display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingField {
+    FetchInterval,
+    RecordingInterval,
+    RecordingLength,
+    RecordingFormat,
+    SpiClock,
+    Location,
+}
+
+impl SettingField {
+    pub const ALL: [SettingField; 6] = [
+        SettingField::FetchInterval,
+        SettingField::RecordingInterval,
+        SettingField::RecordingLength,
+        SettingField::RecordingFormat,
+        SettingField::SpiClock,
+        SettingField::Location,
+    ];
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::FetchInterval => "Weather update",
+            Self::RecordingInterval => "Auto recording",
+            Self::RecordingLength => "Recording length",
+            Self::RecordingFormat => "Recording format",
+            Self::SpiClock => "SPI clock",
+            Self::Location => "Location",
+        }
+    }
+
+    /// Whether a change only takes effect after a restart.
+    pub fn needs_restart(self) -> bool {
+        self == Self::SpiClock
+    }
+}
+
+impl Settings {
+    /// Parse a stored document, upgrading older versions and filling in new fields.
+    pub fn from_json(data: &[u8]) -> io::Result<Self> {
+        let value: Value = serde_json::from_slice(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let value = migrate(value, MIGRATIONS)?;
+        let mut settings: Self = serde_json::from_value(value)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        settings.version = VERSION;
+        Ok(settings.sanitized())
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("settings serialize")
+    }
+
+    /// Load from `store`, falling back to the defaults when nothing was saved yet.
+    pub fn load(store: &dyn SettingsStore) -> io::Result<Self> {
+        match store.load()? {
+            Some(data) => Self::from_json(&data),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, store: &dyn SettingsStore) -> io::Result<()> {
+        store.save(&self.to_json())
+    }
+
+    pub fn fetch_interval(&self) -> Duration {
+        Duration::from_secs(self.fetch_interval_secs.into())
+    }
+
+    /// `None` when automatic recordings are off.
+    pub fn recording_interval(&self) -> Option<Duration> {
+        (self.recording_interval_secs > 0)
+            .then(|| Duration::from_secs(self.recording_interval_secs.into()))
+    }
+
+    pub fn recording_length(&self) -> Duration {
+        Duration::from_secs(self.recording_length_secs.into())
+    }
+
+    /// Move `field` `delta` steps through its choices, stopping at either end.
+    pub fn adjust(&mut self, field: SettingField, delta: i32) {
+        match field {
+            SettingField::FetchInterval => {
+                self.fetch_interval_secs = step(&FETCH_INTERVALS, self.fetch_interval_secs, delta)
+            }
+            SettingField::RecordingInterval => {
+                self.recording_interval_secs =
+                    step(&RECORDING_INTERVALS, self.recording_interval_secs, delta)
+            }
+            SettingField::RecordingLength => {
+                self.recording_length_secs =
+                    step(&RECORDING_LENGTHS, self.recording_length_secs, delta)
+            }
+            SettingField::RecordingFormat => {
+                let formats: Vec<RecordingFormat> = RecordingFormat::ALL
+                    .into_iter()
+                    .filter(|format| format.is_available())
+                    .collect();
+                let index = formats
+                    .iter()
+                    .position(|format| *format == self.recording_format)
+                    .map_or(0, |index| index as i64 + i64::from(delta));
+                self.recording_format = formats[index.clamp(0, formats.len() as i64 - 1) as usize];
+            }
+            SettingField::SpiClock => {
+                self.spi_clock_hz = step(&SPI_CLOCKS, self.spi_clock_hz, delta)
+            }
+            SettingField::Location => {
+                let last = Location::PRESETS.len() as i64 - 1;
+                let index = match Location::PRESETS
+                    .iter()
+                    .position(|(name, ..)| *name == self.location.name)
+                {
+                    Some(index) => (index as i64 + i64::from(delta)).clamp(0, last),
+                    // A custom location from the settings file; start over at the presets.
+                    None => 0,
+                };
+                let (name, latitude, longitude) = Location::PRESETS[index as usize];
+                self.location = Location::new(name, latitude, longitude);
+            }
+        }
+    }
+
+    /// The value of `field` as shown on the settings page.
+    pub fn value_label(&self, field: SettingField) -> String {
+        match field {
+            SettingField::FetchInterval => duration_label(self.fetch_interval_secs),
+            SettingField::RecordingInterval if self.recording_interval_secs == 0 => {
+                "Off".to_string()
+            }
+            SettingField::RecordingInterval => duration_label(self.recording_interval_secs),
+            SettingField::RecordingLength => duration_label(self.recording_length_secs),
+            SettingField::RecordingFormat => match self.recording_format {
+                RecordingFormat::Pcm => "PCM".to_string(),
+                RecordingFormat::ImaAdpcm => "ADPCM".to_string(),
+                RecordingFormat::Opus => "Opus".to_string(),
+            },
+            SettingField::SpiClock => format!("{} MHz", self.spi_clock_hz / 1_000_000),
+            SettingField::Location => self.location.name.clone(),
+        }
+    }
+
+    /// Clamp hand-edited or corrupted values into the supported ranges.
+    fn sanitized(mut self) -> Self {
+        let clamp =
+            |options: &[u32], value: u32| value.clamp(options[0], options[options.len() - 1]);
+        self.fetch_interval_secs = clamp(&FETCH_INTERVALS, self.fetch_interval_secs);
+        self.recording_interval_secs = clamp(&RECORDING_INTERVALS, self.recording_interval_secs);
+        self.recording_length_secs = clamp(&RECORDING_LENGTHS, self.recording_length_secs);
+        self.spi_clock_hz = clamp(&SPI_CLOCKS, self.spi_clock_hz);
+        if !self.recording_format.is_available() {
+            self.recording_format = Self::default().recording_format;
+        }
+        self
+    }
+}
+
+/// The option `delta` steps from `current`; values between two options count as either.
+fn step(options: &[u32], current: u32, delta: i32) -> u32 {
+    let index = match options.binary_search(&current) {
+        Ok(index) => index as i64 + i64::from(delta),
+        Err(index) if delta > 0 => index as i64 + i64::from(delta) - 1,
+        Err(index) => index as i64 + i64::from(delta),
+    };
+    options[index.clamp(0, options.len() as i64 - 1) as usize]
+}
+
+fn duration_label(secs: u32) -> String {
+    match secs {
+        s if s >= 3600 && s % 3600 == 0 => format!("{} h", s / 3600),
+        s if s >= 60 && s % 60 == 0 => format!("{} min", s / 60),
+        s => format!("{} s", s),
+    }
+}
+
+/// Run the migrations a document needs; documents without a version are version 1.
+fn migrate(mut value: Value, migrations: &[fn(&mut Map<String, Value>)]) -> io::Result<Value> {
+    let Value::Object(map) = &mut value else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "settings are not an object",
+        ));
+    };
+    let version = map.get("version").and_then(Value::as_u64).unwrap_or(1) as usize;
+    if version > migrations.len() + 1 {
+        log::warn!("Settings version {} is newer than this build", version);
+    }
+    for migration in migrations.iter().skip(version.saturating_sub(1)) {
+        migration(map);
+    }
+    Ok(value)
+}
+
+/// Where the serialized settings live.
+pub trait SettingsStore {
+    /// The stored document, or `None` if nothing was saved yet.
+    fn load(&self) -> io::Result<Option<Vec<u8>>>;
+    fn save(&self, data: &[u8]) -> io::Result<()>;
+}
+
+/// Settings in a JSON file, used on desktop.
+pub struct FileSettingsStore {
+    path: PathBuf,
+}
+
+impl FileSettingsStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SettingsStore for FileSettingsStore {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Replace the old file in one step so a crash never leaves half a document.
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, data)?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let dir = TempDir::new("round-trip");
+        let store = FileSettingsStore::new(dir.0.join("config/settings.json"));
+        assert_eq!(Settings::load(&store).unwrap(), Settings::default());
+
+        let mut settings = Settings::default();
+        settings.adjust(SettingField::FetchInterval, 1);
+        settings.adjust(SettingField::Location, 2);
+        settings.save(&store).unwrap();
+        let loaded = Settings::load(&store).unwrap();
+        assert_eq!(loaded, settings);
+        assert_eq!(loaded.fetch_interval(), Duration::from_secs(60));
+        assert_eq!(loaded.location.name, "Vancouver");
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let settings =
+            Settings::from_json(br#"{"fetch_interval_secs": 120, "unknown": true}"#).unwrap();
+        assert_eq!(settings.fetch_interval_secs, 120);
+        assert_eq!(settings.recording_length_secs, 10);
+        assert_eq!(settings.location, Location::default());
+        assert_eq!(settings.version, VERSION);
+
+        // Out of range values are clamped rather than rejected.
+        let settings = Settings::from_json(br#"{"spi_clock_hz": 0}"#).unwrap();
+        assert_eq!(settings.spi_clock_hz, 5_000_000);
+        let settings = Settings::from_json(br#"{"recording_format": "opus"}"#).unwrap();
+        assert!(settings.recording_format.is_available());
+        assert!(Settings::from_json(b"[1, 2]").is_err());
+        assert!(Settings::from_json(b"{").is_err());
+    }
+
+    #[test]
+    fn test_migrations() {
+        fn rename_interval(map: &mut Map<String, Value>) {
+            if let Some(value) = map.remove("interval") {
+                map.insert("fetch_interval_secs".to_string(), value);
+            }
+        }
+        fn minutes_to_seconds(map: &mut Map<String, Value>) {
+            if let Some(minutes) = map.get("fetch_interval_secs").and_then(Value::as_u64) {
+                map.insert("fetch_interval_secs".into(), (minutes * 60).into());
+            }
+        }
+        let migrations: &[fn(&mut Map<String, Value>)] = &[rename_interval, minutes_to_seconds];
+
+        let v1 = migrate(serde_json::json!({"interval": 2}), migrations).unwrap();
+        assert_eq!(v1["fetch_interval_secs"], 120);
+        let v2 = serde_json::json!({"version": 2, "fetch_interval_secs": 2});
+        assert_eq!(migrate(v2, migrations).unwrap()["fetch_interval_secs"], 120);
+        let v3 = serde_json::json!({"version": 3, "fetch_interval_secs": 2});
+        assert_eq!(migrate(v3, migrations).unwrap()["fetch_interval_secs"], 2);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let mut settings = Settings::default();
+        settings.adjust(SettingField::RecordingInterval, -1);
+        assert_eq!(settings.recording_interval(), Some(Duration::from_secs(30)));
+        settings.adjust(SettingField::RecordingInterval, -5);
+        assert_eq!(settings.recording_interval(), None);
+        assert_eq!(settings.value_label(SettingField::RecordingInterval), "Off");
+
+        settings.adjust(SettingField::SpiClock, 100);
+        assert_eq!(settings.value_label(SettingField::SpiClock), "80 MHz");
+
+        assert_eq!(settings.value_label(SettingField::RecordingFormat), "ADPCM");
+        settings.adjust(SettingField::RecordingFormat, -5);
+        assert_eq!(settings.recording_format, RecordingFormat::Pcm);
+        settings.adjust(SettingField::RecordingFormat, 5);
+        assert!(settings.recording_format.is_available());
+        assert_ne!(settings.recording_format, RecordingFormat::Pcm);
+
+        // Hand-edited values between two choices step to the neighbour.
+        settings.fetch_interval_secs = 45;
+        settings.adjust(SettingField::FetchInterval, 1);
+        assert_eq!(settings.value_label(SettingField::FetchInterval), "1 min");
+        settings.fetch_interval_secs = 45;
+        settings.adjust(SettingField::FetchInterval, -1);
+        assert_eq!(settings.value_label(SettingField::FetchInterval), "30 s");
+
+        settings.location = Location::new("Home", 1.0, 2.0);
+        settings.adjust(SettingField::Location, 1);
+        assert_eq!(settings.location, Location::default());
+        settings.adjust(SettingField::Location, -1);
+        assert_eq!(settings.location, Location::default());
+    }
+}
@@ -0,0 +1,282 @@
+//! Removable storage: the SD card on the device, or a plain directory in tests.
+//!
+//! [`StorageManager`] owns the mount for the life of the app. Polling it notices a
+//! card being pulled and mounts it again once one is back.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Capacity of a mounted filesystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageInfo {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+}
+
+impl StorageInfo {
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.free_bytes)
+    }
+
+    /// "1.2 GB free of 7.4 GB"
+    pub fn label(&self) -> String {
+        format!(
+            "{} free of {}",
+            bytes_label(self.free_bytes),
+            bytes_label(self.total_bytes)
+        )
+    }
+}
+
+fn bytes_label(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    match bytes as f64 {
+        b if b < KB => format!("{} B", bytes),
+        b if b < KB * KB => format!("{:.1} KB", b / KB),
+        b if b < KB * KB * KB => format!("{:.1} MB", b / (KB * KB)),
+        b => format!("{:.1} GB", b / (KB * KB * KB)),
+    }
+}
+
+/// A filesystem that can go away while the app runs.
+pub trait Storage: Send {
+    /// Where the filesystem is, or will be, mounted.
+    fn root(&self) -> &Path;
+
+    fn is_mounted(&self) -> bool;
+
+    /// Whether the mounted medium still responds. Called regularly, so it has to be cheap.
+    fn is_present(&mut self) -> bool;
+
+    fn mount(&mut self) -> io::Result<()>;
+
+    /// Drop the mount, e.g. after the medium was removed.
+    fn unmount(&mut self);
+
+    /// Capacity and free space while mounted.
+    fn info(&self) -> io::Result<StorageInfo>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageEvent {
+    Mounted(StorageInfo),
+    Removed,
+}
+
+/// Keeps a [`Storage`] mounted whenever its medium is there.
+pub struct StorageManager {
+    storage: Box<dyn Storage>,
+    /// Time between mount attempts while nothing is mounted.
+    retry_interval: Duration,
+    next_attempt: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl StorageManager {
+    pub fn new(storage: impl Storage + 'static, retry_interval: Duration) -> Self {
+        Self {
+            storage: Box::new(storage),
+            retry_interval,
+            next_attempt: None,
+            last_error: None,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.storage.root()
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.storage.is_mounted()
+    }
+
+    /// `None` while nothing is mounted or the filesystem can't be queried.
+    pub fn info(&self) -> Option<StorageInfo> {
+        if !self.storage.is_mounted() {
+            return None;
+        }
+        self.storage
+            .info()
+            .map_err(|e| log::warn!("Failed to query storage: {}", e))
+            .ok()
+    }
+
+    /// Unmount a removed medium, or try mounting one if the retry interval has passed.
+    pub fn poll(&mut self, now: Instant) -> Option<StorageEvent> {
+        if self.storage.is_mounted() {
+            if self.storage.is_present() {
+                return None;
+            }
+            log::info!("Storage at {} removed", self.root().display());
+            self.storage.unmount();
+            self.next_attempt = Some(now + self.retry_interval);
+            return Some(StorageEvent::Removed);
+        }
+
+        if self.next_attempt.is_some_and(|at| now < at) {
+            return None;
+        }
+        match self.storage.mount() {
+            Ok(()) => {
+                self.next_attempt = None;
+                self.last_error = None;
+                let info = self.info().unwrap_or_default();
+                log::info!(
+                    "Storage mounted at {}: {}",
+                    self.root().display(),
+                    info.label()
+                );
+                Some(StorageEvent::Mounted(info))
+            }
+            Err(e) => {
+                // Retried until a medium shows up, so only log when something changes.
+                let error = e.to_string();
+                if self.last_error.as_ref() != Some(&error) {
+                    log::info!(
+                        "Storage at {} not mounted: {}",
+                        self.root().display(),
+                        error
+                    );
+                    self.last_error = Some(error);
+                }
+                self.next_attempt = Some(now + self.retry_interval);
+                None
+            }
+        }
+    }
+}
+
+/// A directory standing in for a card of `capacity` bytes. Deleting the directory
+/// removes the card.
+#[derive(Debug)]
+pub struct DirStorage {
+    root: PathBuf,
+    capacity: u64,
+    mounted: bool,
+}
+
+impl DirStorage {
+    pub fn new(root: impl Into<PathBuf>, capacity: u64) -> Self {
+        Self {
+            root: root.into(),
+            capacity,
+            mounted: false,
+        }
+    }
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+impl Storage for DirStorage {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn is_mounted(&self) -> bool {
+        self.mounted
+    }
+
+    fn is_present(&mut self) -> bool {
+        self.root.is_dir()
+    }
+
+    fn mount(&mut self) -> io::Result<()> {
+        if !self.root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", self.root.display()),
+            ));
+        }
+        self.mounted = true;
+        Ok(())
+    }
+
+    fn unmount(&mut self) {
+        self.mounted = false;
+    }
+
+    fn info(&self) -> io::Result<StorageInfo> {
+        let used = dir_size(&self.root)?;
+        Ok(StorageInfo {
+            total_bytes: self.capacity,
+            free_bytes: self.capacity.saturating_sub(used),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const RETRY: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_info() {
+        let dir = TempDir::new("info");
+        std::fs::write(dir.0.join("a.wav"), vec![0u8; 1000]).unwrap();
+        std::fs::create_dir(dir.0.join("sub")).unwrap();
+        std::fs::write(dir.0.join("sub/b.wav"), vec![0u8; 24]).unwrap();
+
+        let mut manager = StorageManager::new(DirStorage::new(&dir.0, 4096), RETRY);
+        assert_eq!(manager.info(), None);
+        let info = StorageInfo {
+            total_bytes: 4096,
+            free_bytes: 3072,
+        };
+        assert_eq!(
+            manager.poll(Instant::now()),
+            Some(StorageEvent::Mounted(info))
+        );
+        assert_eq!(manager.info(), Some(info));
+        assert_eq!(info.used_bytes(), 1024);
+        assert_eq!(info.label(), "3.0 KB free of 4.0 KB");
+
+        let card = StorageInfo {
+            total_bytes: 8 * 1024 * 1024 * 1024,
+            free_bytes: 1536 * 1024 * 1024,
+        };
+        assert_eq!(card.label(), "1.5 GB free of 8.0 GB");
+    }
+
+    #[test]
+    fn test_removal_and_remount() {
+        let dir = TempDir::new("remount");
+        let mut manager = StorageManager::new(DirStorage::new(&dir.0, 1 << 20), RETRY);
+        let start = Instant::now();
+        assert!(matches!(
+            manager.poll(start),
+            Some(StorageEvent::Mounted(_))
+        ));
+        assert_eq!(manager.poll(start), None);
+        assert!(manager.is_mounted());
+
+        std::fs::remove_dir(&dir.0).unwrap();
+        assert_eq!(manager.poll(start), Some(StorageEvent::Removed));
+        assert!(!manager.is_mounted());
+        assert_eq!(manager.info(), None);
+        assert_eq!(manager.poll(start + RETRY), None);
+
+        // Reinserted, but only mounted once the retry interval has passed again.
+        std::fs::create_dir(&dir.0).unwrap();
+        assert_eq!(manager.poll(start + RETRY + Duration::from_secs(1)), None);
+        assert!(matches!(
+            manager.poll(start + RETRY * 2),
+            Some(StorageEvent::Mounted(_))
+        ));
+        assert!(manager.is_mounted());
+    }
+}
@@ -0,0 +1,137 @@
+//! Speech-to-text through a Whisper-compatible `/v1/audio/transcriptions` endpoint.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::RecordingFormat;
+use crate::http::{join_url, HttpClient, Multipart, Request};
+
+pub const TRANSCRIPTIONS_PATH: &str = "/v1/audio/transcriptions";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SttConfig {
+    /// Server root, e.g. `https://192.168.1.10:8080` for a local whisper.cpp server.
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    /// ISO-639-1 language hint; the server detects the language when unset.
+    pub language: Option<String>,
+}
+
+impl Default for SttConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.openai.com".to_string(),
+            api_key: None,
+            model: "whisper-1".to_string(),
+            language: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+pub struct SttClient<C> {
+    http: C,
+    config: SttConfig,
+}
+
+impl<C: HttpClient> SttClient<C> {
+    pub fn new(http: C, config: SttConfig) -> Self {
+        Self { http, config }
+    }
+
+    pub fn config(&self) -> &SttConfig {
+        &self.config
+    }
+
+    /// Upload a recording and return its transcript, trimmed of surrounding whitespace.
+    pub fn transcribe(
+        &self,
+        file_name: &str,
+        format: RecordingFormat,
+        audio: &[u8],
+    ) -> io::Result<String> {
+        let mut form = Multipart::new()
+            .file("file", file_name, format.mime_type(), audio)
+            .text("model", &self.config.model)
+            .text("response_format", "json");
+        if let Some(language) = &self.config.language {
+            form = form.text("language", language);
+        }
+        let (content_type, body) = form.finish();
+
+        let request = Request::post(join_url(&self.config.base_url, TRANSCRIPTIONS_PATH))
+            .bearer_auth(self.config.api_key.as_deref())
+            .header("Content-Type", content_type)
+            .body(body);
+        let response = self.http.execute(request)?.error_for_status()?;
+        let transcription: TranscriptionResponse = response.json()?;
+        Ok(transcription.text.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock::MockServer;
+    use crate::http::StdHttpClient;
+
+    fn client(server: &MockServer) -> SttClient<StdHttpClient> {
+        SttClient::new(
+            MockServer::client(),
+            SttConfig {
+                base_url: format!("{}/", server.url()),
+                api_key: Some("secret".to_string()),
+                language: Some("en".to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_transcribe() {
+        let server = MockServer::start(vec![MockServer::json(
+            200,
+            r#"{"text": " What's the weather like? "}"#,
+        )]);
+        let text = client(&server)
+            .transcribe("rec_1.wav", RecordingFormat::ImaAdpcm, b"RIFF....WAVE")
+            .unwrap();
+        assert_eq!(text, "What's the weather like?");
+
+        let requests = server.finish();
+        let request = &requests[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, TRANSCRIPTIONS_PATH);
+        assert_eq!(request.header("Authorization"), Some("Bearer secret"));
+        assert!(request
+            .header("Content-Type")
+            .unwrap()
+            .starts_with("multipart/form-data; boundary="));
+
+        let body = request.body_text();
+        assert!(body.contains(
+            "name=\"file\"; filename=\"rec_1.wav\"\r\nContent-Type: audio/wav\r\n\r\nRIFF....WAVE\r\n"
+        ));
+        assert!(body.contains("name=\"model\"\r\n\r\nwhisper-1\r\n"));
+        assert!(body.contains("name=\"language\"\r\n\r\nen\r\n"));
+    }
+
+    #[test]
+    fn test_transcribe_error() {
+        let server = MockServer::start(vec![MockServer::json(
+            400,
+            r#"{"error": {"message": "Invalid file format."}}"#,
+        )]);
+        let error = client(&server)
+            .transcribe("rec_1.opus", RecordingFormat::Opus, b"OggS")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 400: Invalid file format.");
+        server.finish();
+    }
+}
@@ -0,0 +1,510 @@
+//! Publishes weather, device health and recording events over MQTT, announces them to
+//! Home Assistant and takes commands.
+//!
+//! Everything lives under `<base topic>`, `slint-workshop/<device id>` by default:
+//!
+//! | Topic                  | Payload                                             |
+//! |------------------------|-----------------------------------------------------|
+//! | `status`               | `online`, or `offline` as the will (retained)       |
+//! | `weather`              | `{"temperature", "humidity", "wind_speed"}` (retained) |
+//! | `health`               | [`DeviceHealth`] as JSON (retained)                 |
+//! | `recording`            | [`RecordingEvent`] as JSON                          |
+//! | `brightness`           | Backlight in percent (retained)                     |
+//! | `command/record`       | Any payload starts a recording                      |
+//! | `command/brightness`   | `0` to `100`                                        |
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::capture::SavedRecording;
+use crate::mqtt::{MqttClient, MqttEvent, Will};
+use crate::WeatherData;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryConfig {
+    /// Unique per device; part of the topics and Home Assistant's entity IDs.
+    pub device_id: String,
+    /// Shown in Home Assistant.
+    pub device_name: String,
+    /// E.g. the board profile's name.
+    pub model: String,
+    pub base_topic: String,
+    /// Home Assistant's discovery prefix; `None` skips discovery.
+    pub discovery_prefix: Option<String>,
+}
+
+impl TelemetryConfig {
+    pub fn new(device_id: impl Into<String>) -> Self {
+        let device_id = device_id.into();
+        Self {
+            device_name: format!("Slint Workshop {}", device_id),
+            model: String::new(),
+            base_topic: format!("slint-workshop/{}", device_id),
+            discovery_prefix: Some("homeassistant".to_string()),
+            device_id,
+        }
+    }
+
+    pub fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.base_topic, name)
+    }
+
+    /// Marks the device offline when it drops off; pass it to the MQTT client.
+    pub fn will(&self) -> Will {
+        Will {
+            topic: self.topic("status"),
+            payload: b"offline".to_vec(),
+            retain: true,
+        }
+    }
+}
+
+/// Free memory, signal and uptime; `None` where the platform has no such thing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DeviceHealth {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rssi: Option<i8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_heap: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_free_heap: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_psram: Option<u64>,
+    pub uptime: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordingEvent {
+    Started,
+    Saved {
+        file_name: String,
+        duration_ms: u64,
+        size_bytes: u64,
+    },
+}
+
+impl From<&SavedRecording> for RecordingEvent {
+    fn from(saved: &SavedRecording) -> Self {
+        RecordingEvent::Saved {
+            file_name: saved.file_name.clone(),
+            duration_ms: saved.duration.as_millis() as u64,
+            size_bytes: saved.encoded.len() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Record,
+    /// Backlight in percent.
+    Brightness(u8),
+}
+
+/// A Home Assistant sensor reading one field of a JSON state topic.
+struct Sensor {
+    id: &'static str,
+    name: &'static str,
+    state: &'static str,
+    device_class: Option<&'static str>,
+    unit: Option<&'static str>,
+    /// Hidden under diagnostics rather than shown with the readings.
+    diagnostic: bool,
+}
+
+const SENSORS: &[Sensor] = &[
+    Sensor {
+        id: "temperature",
+        name: "Temperature",
+        state: "weather",
+        device_class: Some("temperature"),
+        unit: Some("°C"),
+        diagnostic: false,
+    },
+    Sensor {
+        id: "humidity",
+        name: "Humidity",
+        state: "weather",
+        device_class: Some("humidity"),
+        unit: Some("%"),
+        diagnostic: false,
+    },
+    Sensor {
+        id: "wind_speed",
+        name: "Wind speed",
+        state: "weather",
+        device_class: Some("wind_speed"),
+        unit: Some("km/h"),
+        diagnostic: false,
+    },
+    Sensor {
+        id: "rssi",
+        name: "Wi-Fi signal",
+        state: "health",
+        device_class: Some("signal_strength"),
+        unit: Some("dBm"),
+        diagnostic: true,
+    },
+    Sensor {
+        id: "free_heap",
+        name: "Free heap",
+        state: "health",
+        device_class: Some("data_size"),
+        unit: Some("B"),
+        diagnostic: true,
+    },
+    Sensor {
+        id: "free_psram",
+        name: "Free PSRAM",
+        state: "health",
+        device_class: Some("data_size"),
+        unit: Some("B"),
+        diagnostic: true,
+    },
+    Sensor {
+        id: "uptime",
+        name: "Uptime",
+        state: "health",
+        device_class: Some("duration"),
+        unit: Some("s"),
+        diagnostic: true,
+    },
+];
+
+pub struct Telemetry<C: MqttClient> {
+    client: C,
+    config: TelemetryConfig,
+    connected: AtomicBool,
+}
+
+impl<C: MqttClient> Telemetry<C> {
+    /// `client` should have been set up with [`TelemetryConfig::will`].
+    pub fn new(client: C, config: TelemetryConfig) -> Self {
+        Self {
+            client,
+            config,
+            connected: AtomicBool::new(false),
+        }
+    }
+
+    pub fn config(&self) -> &TelemetryConfig {
+        &self.config
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Handle the client's events: announce the device after every (re)connect and
+    /// return the commands received.
+    pub fn poll(&self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for event in self.client.take_events() {
+            match event {
+                MqttEvent::Connected => {
+                    self.connected.store(true, Ordering::Relaxed);
+                    if let Err(e) = self.announce() {
+                        log::info!("Failed to announce telemetry: {}", e);
+                    }
+                }
+                MqttEvent::Disconnected => self.connected.store(false, Ordering::Relaxed),
+                MqttEvent::Message { topic, payload } => {
+                    match self.parse_command(&topic, &payload) {
+                        Some(command) => commands.push(command),
+                        None => log::info!("Ignoring MQTT message on {}", topic),
+                    }
+                }
+            }
+        }
+        commands
+    }
+
+    fn announce(&self) -> io::Result<()> {
+        if let Some(prefix) = &self.config.discovery_prefix {
+            for (component, id, config) in self.discovery() {
+                let topic = format!(
+                    "{}/{}/{}/{}/config",
+                    prefix, component, self.config.device_id, id
+                );
+                self.client
+                    .publish(&topic, config.to_string().as_bytes(), true)?;
+            }
+        }
+        self.client.subscribe(&self.config.topic("command/+"))?;
+        self.client
+            .publish(&self.config.topic("status"), b"online", true)
+    }
+
+    /// Component, object ID and config of every Home Assistant entity.
+    fn discovery(&self) -> Vec<(&'static str, &'static str, Value)> {
+        let config = &self.config;
+        let entity = |id: &str, name: &str| {
+            json!({
+                "name": name,
+                "unique_id": format!("{}_{}", config.device_id, id),
+                "object_id": format!("{}_{}", config.device_id, id),
+                "availability_topic": config.topic("status"),
+                "device": {
+                    "identifiers": [config.device_id],
+                    "name": config.device_name,
+                    "model": config.model,
+                    "manufacturer": "Slint Workshop",
+                },
+            })
+        };
+
+        let mut entities = Vec::new();
+        for sensor in SENSORS {
+            let mut value = entity(sensor.id, sensor.name);
+            value["state_topic"] = config.topic(sensor.state).into();
+            value["value_template"] = format!("{{{{ value_json.{} }}}}", sensor.id).into();
+            if let Some(device_class) = sensor.device_class {
+                value["device_class"] = device_class.into();
+                value["state_class"] = "measurement".into();
+            }
+            if let Some(unit) = sensor.unit {
+                value["unit_of_measurement"] = unit.into();
+            }
+            if sensor.diagnostic {
+                value["entity_category"] = "diagnostic".into();
+            }
+            entities.push(("sensor", sensor.id, value));
+        }
+
+        let mut record = entity("record", "Record");
+        record["command_topic"] = config.topic("command/record").into();
+        entities.push(("button", "record", record));
+
+        let mut brightness = entity("brightness", "Brightness");
+        brightness["command_topic"] = config.topic("command/brightness").into();
+        brightness["state_topic"] = config.topic("brightness").into();
+        brightness["min"] = 0.into();
+        brightness["max"] = 100.into();
+        brightness["unit_of_measurement"] = "%".into();
+        brightness["entity_category"] = "config".into();
+        entities.push(("number", "brightness", brightness));
+
+        let mut last_recording = entity("last_recording", "Last recording");
+        last_recording["state_topic"] = config.topic("recording").into();
+        last_recording["value_template"] = "{{ value_json.event }}".into();
+        entities.push(("sensor", "last_recording", last_recording));
+        entities
+    }
+
+    fn parse_command(&self, topic: &str, payload: &[u8]) -> Option<Command> {
+        let name = topic
+            .strip_prefix(&self.config.base_topic)?
+            .strip_prefix("/command/")?;
+        match name {
+            "record" => Some(Command::Record),
+            "brightness" => {
+                let percent: f64 = std::str::from_utf8(payload).ok()?.trim().parse().ok()?;
+                (0.0..=100.0)
+                    .contains(&percent)
+                    .then(|| Command::Brightness(percent.round() as u8))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn publish_weather(&self, weather: &WeatherData) -> io::Result<()> {
+        let payload = json!({
+            "temperature": weather.temperature,
+            "humidity": weather.humidity,
+            "wind_speed": weather.wind_speed,
+        });
+        self.publish("weather", &payload, true)
+    }
+
+    pub fn publish_health(&self, health: &DeviceHealth) -> io::Result<()> {
+        self.publish("health", health, true)
+    }
+
+    pub fn publish_recording(&self, event: &RecordingEvent) -> io::Result<()> {
+        self.publish("recording", event, false)
+    }
+
+    pub fn publish_brightness(&self, percent: u8) -> io::Result<()> {
+        self.client.publish(
+            &self.config.topic("brightness"),
+            percent.to_string().as_bytes(),
+            true,
+        )
+    }
+
+    fn publish(&self, name: &str, payload: &impl Serialize, retain: bool) -> io::Result<()> {
+        self.client.publish(
+            &self.config.topic(name),
+            &serde_json::to_vec(payload)?,
+            retain,
+        )
+    }
+}
+
+impl<C: MqttClient> Drop for Telemetry<C> {
+    fn drop(&mut self) {
+        // The will only covers dropping off; say goodbye when shutting down cleanly.
+        if self.is_connected() {
+            let _ = self
+                .client
+                .publish(&self.config.topic("status"), b"offline", true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::mock::MockBroker;
+    use crate::mqtt::{MqttConfig, StdMqttClient};
+    use std::time::{Duration, Instant};
+
+    fn start(broker: &MockBroker) -> Telemetry<StdMqttClient> {
+        let config = TelemetryConfig::new("kitchen");
+        let mut mqtt = MqttConfig::new(broker.url(), "kitchen");
+        mqtt.will = Some(config.will());
+        mqtt.reconnect_delay = Duration::from_millis(20);
+        Telemetry::new(StdMqttClient::start(mqtt).unwrap(), config)
+    }
+
+    fn poll_until(
+        telemetry: &Telemetry<StdMqttClient>,
+        done: impl Fn(&[Command]) -> bool,
+    ) -> Vec<Command> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut commands = Vec::new();
+        loop {
+            commands.extend(telemetry.poll());
+            if done(&commands) {
+                return commands;
+            }
+            assert!(Instant::now() < deadline, "got only {:?}", commands);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_announce_and_publish() {
+        let broker = MockBroker::start();
+        let telemetry = start(&broker);
+        poll_until(&telemetry, |_| telemetry.is_connected());
+        let state =
+            broker.wait(|state| !state.messages("slint-workshop/kitchen/status").is_empty());
+
+        let discovery = state.messages("homeassistant/sensor/kitchen/temperature/config");
+        assert!(discovery[0].retain);
+        let config: Value = serde_json::from_slice(&discovery[0].payload).unwrap();
+        assert_eq!(config["state_topic"], "slint-workshop/kitchen/weather");
+        assert_eq!(config["value_template"], "{{ value_json.temperature }}");
+        assert_eq!(config["unique_id"], "kitchen_temperature");
+        assert_eq!(
+            config["availability_topic"],
+            "slint-workshop/kitchen/status"
+        );
+        assert_eq!(config["device"]["identifiers"][0], "kitchen");
+        let number = state.messages("homeassistant/number/kitchen/brightness/config");
+        let config: Value = serde_json::from_slice(&number[0].payload).unwrap();
+        assert_eq!(
+            config["command_topic"],
+            "slint-workshop/kitchen/command/brightness"
+        );
+        assert_eq!(
+            state
+                .messages("homeassistant/button/kitchen/record/config")
+                .len(),
+            1
+        );
+        assert_eq!(state.subscriptions, ["slint-workshop/kitchen/command/+"]);
+        assert_eq!(
+            state.messages("slint-workshop/kitchen/status")[0].payload_text(),
+            "online"
+        );
+        drop(state);
+
+        let weather = WeatherData {
+            temperature: 21.5,
+            humidity: 40.0,
+            wind_speed: 3.0,
+        };
+        telemetry.publish_weather(&weather).unwrap();
+        telemetry
+            .publish_health(&DeviceHealth {
+                rssi: Some(-61),
+                free_heap: Some(120_000),
+                uptime: 42,
+                ..Default::default()
+            })
+            .unwrap();
+        telemetry
+            .publish_recording(&RecordingEvent::Saved {
+                file_name: "rec-1.wav".to_string(),
+                duration_ms: 1500,
+                size_bytes: 48_044,
+            })
+            .unwrap();
+        let state = broker.wait(|state| {
+            !state
+                .messages("slint-workshop/kitchen/recording")
+                .is_empty()
+        });
+        assert_eq!(
+            state.messages("slint-workshop/kitchen/weather")[0].payload_text(),
+            r#"{"humidity":40.0,"temperature":21.5,"wind_speed":3.0}"#
+        );
+        assert_eq!(
+            state.messages("slint-workshop/kitchen/health")[0].payload_text(),
+            r#"{"rssi":-61,"free_heap":120000,"uptime":42}"#
+        );
+        let recording = &state.messages("slint-workshop/kitchen/recording")[0];
+        assert!(!recording.retain);
+        assert_eq!(
+            recording.payload_text(),
+            r#"{"event":"saved","file_name":"rec-1.wav","duration_ms":1500,"size_bytes":48044}"#
+        );
+        drop(state);
+
+        drop(telemetry);
+        let state = broker.wait(|state| state.disconnects == 1);
+        let status = state.messages("slint-workshop/kitchen/status");
+        assert_eq!(status.last().unwrap().payload_text(), "offline");
+    }
+
+    #[test]
+    fn test_commands_after_reconnect() {
+        let broker = MockBroker::start();
+        let telemetry = start(&broker);
+        poll_until(&telemetry, |_| telemetry.is_connected());
+        drop(broker.wait(|state| !state.subscriptions.is_empty()));
+
+        broker.kick();
+        // Subscribes and announces again on the new connection; the will went out.
+        let state = broker.wait(|state| state.connects.len() == 2);
+        assert_eq!(
+            state.messages("slint-workshop/kitchen/status")[1].payload_text(),
+            "offline"
+        );
+        drop(state);
+        poll_until(&telemetry, |_| {
+            broker.state().subscriptions.len() == 1 && telemetry.is_connected()
+        });
+
+        broker.send("slint-workshop/kitchen/command/record", b"PRESS");
+        broker.send("slint-workshop/kitchen/command/brightness", b"42.6");
+        broker.send("slint-workshop/kitchen/command/brightness", b"150");
+        broker.send("slint-workshop/kitchen/command/brightness", b"dim");
+        broker.send("slint-workshop/kitchen/command/reboot", b"");
+        broker.send("slint-workshop/kitchen/command/brightness", b"10");
+        let commands = poll_until(&telemetry, |commands| commands.len() == 3);
+        assert_eq!(
+            commands,
+            [
+                Command::Record,
+                Command::Brightness(43),
+                Command::Brightness(10)
+            ]
+        );
+    }
+}
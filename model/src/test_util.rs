@@ -0,0 +1,31 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An empty directory under the system's temporary directory, removed when dropped,
+/// even if the test panics.
+pub(crate) struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// `name` is for whoever finds a leftover; a counter keeps directories of tests
+    /// running in parallel apart.
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "slint-workshop-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
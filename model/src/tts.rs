@@ -0,0 +1,190 @@
+//! Text-to-speech through an OpenAI-compatible `/v1/audio/speech` endpoint.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::{join_url, HttpClient, Request};
+use crate::playback::Clip;
+
+pub const SPEECH_PATH: &str = "/v1/audio/speech";
+
+/// Audio encoding requested from the server. Both decode without a codec library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechFormat {
+    #[default]
+    Wav,
+    /// Headerless 16-bit little-endian mono at [`TtsConfig::pcm_sample_rate`].
+    Pcm,
+}
+
+impl SpeechFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Pcm => "pcm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtsConfig {
+    /// Server root, e.g. `https://192.168.1.10:8880` for a local Kokoro or Piper server.
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub voice: String,
+    pub format: SpeechFormat,
+    /// Sample rate of `pcm` responses, which carry no header; OpenAI uses 24 kHz.
+    pub pcm_sample_rate: u32,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.openai.com".to_string(),
+            api_key: None,
+            model: "tts-1".to_string(),
+            voice: "alloy".to_string(),
+            format: SpeechFormat::Wav,
+            pcm_sample_rate: 24000,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SpeechRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+    voice: &'a str,
+    response_format: &'a str,
+}
+
+pub struct TtsClient<C> {
+    http: C,
+    config: TtsConfig,
+}
+
+impl<C: HttpClient> TtsClient<C> {
+    pub fn new(http: C, config: TtsConfig) -> Self {
+        Self { http, config }
+    }
+
+    pub fn config(&self) -> &TtsConfig {
+        &self.config
+    }
+
+    /// Synthesize `text` into a clip ready for a [`PlaybackQueue`](crate::playback::PlaybackQueue).
+    pub fn synthesize(&self, text: &str) -> io::Result<Clip> {
+        let request = Request::post(join_url(&self.config.base_url, SPEECH_PATH))
+            .bearer_auth(self.config.api_key.as_deref())
+            .json(&SpeechRequest {
+                model: &self.config.model,
+                input: text,
+                voice: &self.config.voice,
+                response_format: self.config.format.as_str(),
+            })?;
+        let response = self.http.execute(request)?.error_for_status()?;
+
+        // Trust the payload over the requested format; some servers only speak WAV.
+        if response.body.starts_with(b"RIFF") {
+            Clip::from_wav(&response.body)
+        } else {
+            let sample_rate = response
+                .header("Content-Type")
+                .and_then(pcm_rate)
+                .unwrap_or(self.config.pcm_sample_rate);
+            Ok(Clip::from_pcm(&response.body, sample_rate))
+        }
+    }
+}
+
+/// The `rate` parameter of an `audio/L16; rate=22050` content type.
+fn pcm_rate(content_type: &str) -> Option<u32> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("rate"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::wav;
+    use crate::http::mock::MockServer;
+
+    fn audio_response(content_type: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let samples: Vec<i16> = (0..480).map(|i| (i * 50) as i16).collect();
+        let mut wav_bytes = Vec::new();
+        wav::write_pcm(&mut wav_bytes, &samples, 22050).unwrap();
+        let pcm_bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let server = MockServer::start(vec![
+            audio_response("audio/wav", &wav_bytes),
+            audio_response("audio/L16; rate=16000", &pcm_bytes),
+            audio_response("application/octet-stream", &pcm_bytes),
+        ]);
+        let client = TtsClient::new(
+            MockServer::client(),
+            TtsConfig {
+                base_url: server.url().to_string(),
+                api_key: Some("secret".to_string()),
+                format: SpeechFormat::Pcm,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            client.synthesize("Hello").unwrap(),
+            Clip::new(22050, samples.clone())
+        );
+        assert_eq!(client.synthesize("Hello").unwrap().sample_rate, 16000);
+        assert_eq!(
+            client.synthesize("Hello").unwrap(),
+            Clip::new(24000, samples)
+        );
+
+        let requests = server.finish();
+        assert_eq!(requests[0].path, SPEECH_PATH);
+        assert_eq!(requests[0].header("Authorization"), Some("Bearer secret"));
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["input"], "Hello");
+        assert_eq!(body["voice"], "alloy");
+        assert_eq!(body["response_format"], "pcm");
+    }
+
+    #[test]
+    fn test_synthesize_error() {
+        let server = MockServer::start(vec![MockServer::json(
+            404,
+            r#"{"detail": "voice not found"}"#,
+        )]);
+        let client = TtsClient::new(
+            MockServer::client(),
+            TtsConfig {
+                base_url: server.url().to_string(),
+                ..Default::default()
+            },
+        );
+        let error = client.synthesize("Hello").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"HTTP 404: {"detail": "voice not found"}"#
+        );
+        server.finish();
+    }
+}
@@ -0,0 +1,810 @@
+//! Pushes finished recordings to an HTTP endpoint.
+//!
+//! The queue lives in `uploads.json` next to the recordings, so pending uploads survive
+//! a reboot. Chunked uploads remember how much the server acknowledged and resume
+//! there; failed attempts are retried with exponential backoff.
+//!
+//! Every read and write of the card happens under the storage lock, which is taken
+//! before the recordings index, as saving a recording does.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::http::{join_url, HttpClient, Method, Multipart, Request};
+use crate::recordings::{write_atomic, RecordingEntry, RecordingIndex};
+
+pub const QUEUE_FILE: &str = "uploads.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadMode {
+    /// One `multipart/form-data` POST to the URL per recording, with the file in `file`.
+    Multipart,
+    /// `PUT <url>/<file name>` in pieces of `chunk_size` bytes, each with a
+    /// `Content-Range` header. Resumes at the last acknowledged piece.
+    ChunkedPut { chunk_size: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub mode: UploadMode,
+    /// Delete recordings from the card once the server has them.
+    pub delete_after_upload: bool,
+    /// Wait after the first failure; doubled on every further one up to `max_retry`.
+    pub min_retry: Duration,
+    pub max_retry: Duration,
+}
+
+impl UploadConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            api_key: None,
+            mode: UploadMode::ChunkedPut {
+                chunk_size: 64 * 1024,
+            },
+            delete_after_upload: false,
+            min_retry: Duration::from_secs(5),
+            max_retry: Duration::from_secs(10 * 60),
+        }
+    }
+
+    /// Time to wait after `failures` failed attempts in a row.
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u32 << failures.saturating_sub(1).min(16);
+        self.min_retry.saturating_mul(factor).min(self.max_retry)
+    }
+}
+
+/// A recording waiting to be uploaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedUpload {
+    pub file_name: String,
+    /// Bytes the server has acknowledged.
+    #[serde(default)]
+    pub offset: u64,
+}
+
+/// The recordings of one directory still to be uploaded, oldest first.
+#[derive(Debug)]
+pub struct UploadQueue {
+    path: PathBuf,
+    entries: Vec<QueuedUpload>,
+    /// Failed attempts in a row.
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl UploadQueue {
+    /// Load the queue stored in `dir`. A missing or unreadable one starts out empty.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(QUEUE_FILE);
+        let entries = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                log::warn!("Discarding corrupt upload queue: {}", e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            entries,
+            failures: 0,
+            retry_at: None,
+        })
+    }
+
+    pub fn entries(&self) -> &[QueuedUpload] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn push(&mut self, file_name: &str) -> io::Result<()> {
+        if self.entries.iter().any(|e| e.file_name == file_name) {
+            return Ok(());
+        }
+        self.entries.push(QueuedUpload {
+            file_name: file_name.to_string(),
+            offset: 0,
+        });
+        self.save()
+    }
+
+    /// Queue the recordings `index` doesn't have as uploaded and forget the ones it
+    /// no longer lists.
+    pub fn sync(&mut self, index: &RecordingIndex) -> io::Result<()> {
+        let before = self.entries.clone();
+        self.entries
+            .retain(|e| index.get(&e.file_name).is_some_and(|r| !r.uploaded));
+        for entry in index.entries() {
+            if !entry.uploaded && !self.entries.iter().any(|e| e.file_name == entry.file_name) {
+                self.entries.push(QueuedUpload {
+                    file_name: entry.file_name.clone(),
+                    offset: 0,
+                });
+            }
+        }
+        if self.entries != before {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// When the next attempt may be made after a failure.
+    pub fn retry_at(&self) -> Option<Instant> {
+        self.retry_at
+    }
+
+    /// Whether there is something to upload and no backoff pending.
+    pub fn is_due(&self, now: Instant) -> bool {
+        !self.entries.is_empty() && self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    fn pop_front(&mut self) -> io::Result<()> {
+        self.entries.remove(0);
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        write_atomic(&self.path, &serde_json::to_vec(&self.entries)?)
+    }
+}
+
+fn lock_storage(lock: &Option<Arc<Mutex<()>>>) -> Option<MutexGuard<'_, ()>> {
+    lock.as_ref().map(|lock| lock.lock().unwrap())
+}
+
+/// What a call to [`UploadClient::upload_next`] achieved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadStep {
+    /// Part of a recording was sent; `sent` of its `total` bytes are on the server.
+    Sent {
+        file_name: String,
+        sent: u64,
+        total: u64,
+    },
+    /// A recording is on the server.
+    Finished(String),
+    /// A queued recording was deleted or uploaded some other way.
+    Dropped(String),
+}
+
+pub struct UploadClient<C> {
+    http: C,
+    config: UploadConfig,
+    /// Held while using the card, for storage that shares a bus.
+    storage_lock: Option<Arc<Mutex<()>>>,
+}
+
+impl<C: HttpClient> UploadClient<C> {
+    pub fn new(http: C, config: UploadConfig) -> Self {
+        Self {
+            http,
+            config,
+            storage_lock: None,
+        }
+    }
+
+    pub fn with_storage_lock(mut self, lock: Arc<Mutex<()>>) -> Self {
+        self.storage_lock = Some(lock);
+        self
+    }
+
+    pub fn config(&self) -> &UploadConfig {
+        &self.config
+    }
+
+    /// Send the oldest queued recording, or its next chunk. Returns `None` when the
+    /// queue is empty.
+    ///
+    /// A failure postpones the queue by the backoff; the caller checks
+    /// [`UploadQueue::is_due`] before trying again.
+    pub fn upload_next(
+        &self,
+        queue: &mut UploadQueue,
+        index: &Mutex<RecordingIndex>,
+        now: Instant,
+    ) -> io::Result<Option<UploadStep>> {
+        let Some(queued) = queue.entries.first().cloned() else {
+            return Ok(None);
+        };
+        let found = {
+            let index = index.lock().unwrap();
+            index
+                .get(&queued.file_name)
+                .filter(|entry| !entry.uploaded)
+                .map(|entry| (index.path(&entry.file_name), entry.clone()))
+        };
+        let Some((path, recording)) = found else {
+            let _storage = lock_storage(&self.storage_lock);
+            queue.pop_front()?;
+            return Ok(Some(UploadStep::Dropped(queued.file_name)));
+        };
+
+        let result = match self.config.mode {
+            UploadMode::Multipart => self.send_multipart(&path, &recording),
+            UploadMode::ChunkedPut { chunk_size } => {
+                self.send_chunk(&path, &recording, queued.offset, chunk_size)
+            }
+        };
+        let (sent, total) = match result {
+            Ok(progress) => progress,
+            Err(e) => {
+                queue.failures += 1;
+                let delay = self.config.backoff(queue.failures);
+                queue.retry_at = Some(now + delay);
+                log::info!(
+                    "Upload of {} failed, retrying in {:?}: {}",
+                    queued.file_name,
+                    delay,
+                    e
+                );
+                return Err(e);
+            }
+        };
+        queue.failures = 0;
+        queue.retry_at = None;
+
+        let _storage = lock_storage(&self.storage_lock);
+        if sent < total {
+            queue.entries[0].offset = sent;
+            queue.save()?;
+            return Ok(Some(UploadStep::Sent {
+                file_name: queued.file_name,
+                sent,
+                total,
+            }));
+        }
+        queue.pop_front()?;
+        let mut index = index.lock().unwrap();
+        if self.config.delete_after_upload {
+            index.remove(&queued.file_name)?;
+        } else {
+            index.mark_uploaded(&queued.file_name)?;
+        }
+        log::info!("Uploaded {}", queued.file_name);
+        Ok(Some(UploadStep::Finished(queued.file_name)))
+    }
+
+    /// Up to `max` bytes of `path` from `offset`, and the file's length.
+    fn read_range(&self, path: &Path, offset: u64, max: usize) -> io::Result<(Vec<u8>, u64)> {
+        let _storage = lock_storage(&self.storage_lock);
+        let mut file = std::fs::File::open(path)?;
+        let total = file.metadata()?.len();
+        file.seek(SeekFrom::Start(offset.min(total)))?;
+        let mut data = Vec::new();
+        file.take(max as u64).read_to_end(&mut data)?;
+        Ok((data, total))
+    }
+
+    fn send_multipart(&self, path: &Path, recording: &RecordingEntry) -> io::Result<(u64, u64)> {
+        let (data, total) = self.read_range(path, 0, usize::MAX)?;
+        let (content_type, body) = Multipart::new()
+            .file(
+                "file",
+                &recording.file_name,
+                recording.format.mime_type(),
+                &data,
+            )
+            .text("created_at", &recording.created_at.to_string())
+            .text("duration_ms", &recording.duration_ms.to_string())
+            .finish();
+        let request = Request::post(&self.config.url)
+            .bearer_auth(self.config.api_key.as_deref())
+            .header("Content-Type", content_type)
+            .body(body);
+        self.http.execute(request)?.error_for_status()?;
+        Ok((total, total))
+    }
+
+    fn send_chunk(
+        &self,
+        path: &Path,
+        recording: &RecordingEntry,
+        offset: u64,
+        chunk_size: usize,
+    ) -> io::Result<(u64, u64)> {
+        let (data, total) = self.read_range(path, offset, chunk_size.max(1))?;
+        // The file shrank, e.g. when it was repaired; start over.
+        if offset > total {
+            return self.send_chunk(path, recording, 0, chunk_size);
+        }
+        let end = offset + data.len() as u64;
+        let mut request = Request::new(
+            Method::Put,
+            join_url(&self.config.url, &recording.file_name),
+        )
+        .bearer_auth(self.config.api_key.as_deref())
+        .header("Content-Type", recording.format.mime_type());
+        if total > 0 {
+            request = request.header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", offset, end - 1, total),
+            );
+        }
+        self.http.execute(request.body(data))?.error_for_status()?;
+        Ok((end, total))
+    }
+}
+
+/// Upload progress for the UI.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadStatus {
+    pub pending: usize,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    /// `None` while the storage isn't mounted, or while the worker is uploading.
+    queue: Option<UploadQueue>,
+    index: Option<Arc<Mutex<RecordingIndex>>>,
+    /// Recordings finished while the worker had the queue.
+    pending: Vec<String>,
+    /// Bumped by `set_index` so the worker drops a queue that was replaced.
+    generation: u64,
+    online: bool,
+    status: UploadStatus,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+/// Runs an [`UploadClient`] on a background thread whenever the network is up.
+pub struct Uploader {
+    shared: Arc<Shared>,
+    /// The client's, for writing the queue.
+    storage_lock: Option<Arc<Mutex<()>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Uploader {
+    pub fn start<C: HttpClient + Send + 'static>(client: UploadClient<C>) -> io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let storage_lock = client.storage_lock.clone();
+        let handle = std::thread::Builder::new()
+            .name("upload".into())
+            .stack_size(16 * 1024)
+            .spawn({
+                let shared = shared.clone();
+                move || upload_loop(&client, &shared)
+            })?;
+        Ok(Self {
+            shared,
+            storage_lock,
+            handle: Some(handle),
+        })
+    }
+
+    /// Upload the recordings of `index`, or stop while there is no storage.
+    pub fn set_index(&self, index: Option<Arc<Mutex<RecordingIndex>>>) {
+        let queue = index.as_ref().and_then(|index| {
+            let _storage = lock_storage(&self.storage_lock);
+            let index = index.lock().unwrap();
+            let queue = UploadQueue::open(index.dir()).and_then(|mut queue| {
+                queue.sync(&index)?;
+                Ok(queue)
+            });
+            queue
+                .map_err(|e| log::warn!("Failed to open upload queue: {}", e))
+                .ok()
+        });
+        let mut state = self.shared.state.lock().unwrap();
+        state.generation += 1;
+        state.status.pending = queue.as_ref().map_or(0, |queue| queue.entries().len());
+        state.queue = queue;
+        state.index = index;
+        state.pending.clear();
+        self.shared.wake.notify_one();
+    }
+
+    /// Queue a recording that was just saved.
+    pub fn enqueue(&self, file_name: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        let state = &mut *state;
+        if state.index.is_none() {
+            return;
+        }
+        match state.queue.as_mut() {
+            Some(queue) => {
+                let _storage = lock_storage(&self.storage_lock);
+                if let Err(e) = queue.push(file_name) {
+                    log::warn!("Failed to queue {} for upload: {}", file_name, e);
+                }
+                state.status.pending = queue.entries().len();
+            }
+            // Counted again from the queue once the worker hands it back.
+            None if !state.pending.iter().any(|name| name == file_name) => {
+                state.pending.push(file_name.to_string());
+                state.status.pending += 1;
+            }
+            None => {}
+        }
+        self.shared.wake.notify_one();
+    }
+
+    /// Uploads only run while online; coming back online retries right away.
+    pub fn set_online(&self, online: bool) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.online == online {
+            return;
+        }
+        state.online = online;
+        if let (true, Some(queue)) = (online, state.queue.as_mut()) {
+            queue.retry_at = None;
+        }
+        self.shared.wake.notify_one();
+    }
+
+    pub fn status(&self) -> UploadStatus {
+        self.shared.state.lock().unwrap().status.clone()
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.wake.notify_one();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn upload_loop<C: HttpClient>(client: &UploadClient<C>, shared: &Shared) {
+    loop {
+        let (mut queue, index, generation) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if state.closed {
+                    return;
+                }
+                let now = Instant::now();
+                let wait = match (&state.queue, &state.index) {
+                    (Some(queue), Some(_)) if state.online && queue.is_due(now) => break,
+                    (Some(queue), Some(_)) if state.online && !queue.is_empty() => {
+                        queue.retry_at().map(|at| at - now)
+                    }
+                    _ => None,
+                };
+                state = match wait {
+                    Some(wait) => shared.wake.wait_timeout(state, wait).unwrap().0,
+                    None => shared.wake.wait(state).unwrap(),
+                };
+            }
+            (
+                state.queue.take().unwrap(),
+                state.index.clone().unwrap(),
+                state.generation,
+            )
+        };
+
+        let result = client.upload_next(&mut queue, &index, Instant::now());
+
+        let mut state = shared.state.lock().unwrap();
+        match result {
+            Ok(_) => state.status.last_error = None,
+            Err(e) => state.status.last_error = Some(e.to_string()),
+        }
+        if state.generation == generation {
+            let _storage = lock_storage(&client.storage_lock);
+            for file_name in std::mem::take(&mut state.pending) {
+                if let Err(e) = queue.push(&file_name) {
+                    log::warn!("Failed to queue {} for upload: {}", file_name, e);
+                }
+            }
+            state.status.pending = queue.entries().len();
+            state.queue = Some(queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::save_recording;
+    use crate::clock::MIN_VALID_UNIX;
+    use crate::codec::{wav, RecordingFormat};
+    use crate::http::mock::MockServer;
+    use crate::level::AudioLevels;
+    use crate::recording::RecordingMetadata;
+    use crate::recordings::RetentionPolicy;
+    use crate::test_util::TempDir;
+
+    /// An index holding one PCM recording of `samples` samples.
+    fn index_with_recording(dir: &Path, samples: usize) -> (RecordingIndex, String, Vec<u8>) {
+        let mut index = RecordingIndex::open(dir).unwrap();
+        let path = index.next_path(MIN_VALID_UNIX, RecordingFormat::Pcm);
+        let mut bytes = Vec::new();
+        wav::write_pcm(&mut bytes, &vec![7; samples], 16000).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+        let metadata = RecordingMetadata {
+            sample_rate: 16000,
+            duration_ms: 1000,
+            levels: AudioLevels::default(),
+            format: RecordingFormat::Pcm,
+        };
+        let name = index
+            .add(&path, MIN_VALID_UNIX, &metadata)
+            .unwrap()
+            .file_name
+            .clone();
+        (index, name, bytes)
+    }
+
+    fn config(url: &str, mode: UploadMode) -> UploadConfig {
+        UploadConfig {
+            mode,
+            min_retry: Duration::from_secs(2),
+            max_retry: Duration::from_secs(5),
+            ..UploadConfig::new(url)
+        }
+    }
+
+    /// A URL nothing listens on, like a server while Wi-Fi is down.
+    fn unreachable_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    #[test]
+    fn test_queue_persists() {
+        let dir = TempDir::new("persist");
+        let (index, name, _) = index_with_recording(&dir.0, 10);
+
+        let mut queue = UploadQueue::open(&dir.0).unwrap();
+        assert!(queue.is_empty());
+        queue.sync(&index).unwrap();
+        queue.push(&name).unwrap();
+        assert_eq!(queue.entries().len(), 1);
+        queue.push("rec_2.wav").unwrap();
+
+        let mut queue = UploadQueue::open(&dir.0).unwrap();
+        assert_eq!(queue.entries().len(), 2);
+        assert!(queue.is_due(Instant::now()));
+        // rec_2.wav is not in the index.
+        queue.sync(&index).unwrap();
+        assert_eq!(
+            UploadQueue::open(&dir.0).unwrap().entries(),
+            [QueuedUpload {
+                file_name: name,
+                offset: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chunked_upload_resumes() {
+        let dir = TempDir::new("chunked");
+        let (index, name, bytes) = index_with_recording(&dir.0, 1000);
+        let index = Mutex::new(index);
+        assert_eq!(bytes.len(), 2044);
+
+        let server = MockServer::start(vec![
+            MockServer::json(200, "{}"),
+            MockServer::json(503, r#"{"error": "busy"}"#),
+        ]);
+        let mode = UploadMode::ChunkedPut { chunk_size: 1000 };
+        let client = UploadClient::new(MockServer::client(), config(server.url(), mode));
+        let mut queue = UploadQueue::open(&dir.0).unwrap();
+        queue.sync(&index.lock().unwrap()).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(
+            client.upload_next(&mut queue, &index, start).unwrap(),
+            Some(UploadStep::Sent {
+                file_name: name.clone(),
+                sent: 1000,
+                total: 2044
+            })
+        );
+        let error = client.upload_next(&mut queue, &index, start).unwrap_err();
+        assert!(error.to_string().contains("503"), "{}", error);
+        assert!(!queue.is_due(start + Duration::from_secs(1)));
+        assert!(queue.is_due(start + Duration::from_secs(2)));
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].path, format!("/{}", name));
+        assert_eq!(
+            requests[0].header("Content-Range"),
+            Some("bytes 0-999/2044")
+        );
+        assert_eq!(requests[0].header("Content-Type"), Some("audio/wav"));
+        assert_eq!(requests[0].body, bytes[..1000]);
+        assert_eq!(
+            requests[1].header("Content-Range"),
+            Some("bytes 1000-1999/2044")
+        );
+
+        // After a reboot the upload continues at the acknowledged offset.
+        let server = MockServer::start(vec![
+            MockServer::json(200, "{}"),
+            MockServer::json(201, "{}"),
+        ]);
+        let mut config = config(server.url(), mode);
+        config.delete_after_upload = true;
+        let client = UploadClient::new(MockServer::client(), config);
+        let mut queue = UploadQueue::open(&dir.0).unwrap();
+        assert_eq!(queue.entries()[0].offset, 1000);
+        assert!(matches!(
+            client.upload_next(&mut queue, &index, start),
+            Ok(Some(UploadStep::Sent { sent: 2000, .. }))
+        ));
+        assert_eq!(
+            client.upload_next(&mut queue, &index, start).unwrap(),
+            Some(UploadStep::Finished(name.clone()))
+        );
+        assert_eq!(client.upload_next(&mut queue, &index, start).unwrap(), None);
+        let requests = server.finish();
+        assert_eq!(
+            requests[1].header("Content-Range"),
+            Some("bytes 2000-2043/2044")
+        );
+        assert_eq!(requests[1].body, bytes[2000..]);
+
+        assert!(index.lock().unwrap().entries().is_empty());
+        assert!(!dir.0.join(&name).exists());
+        assert!(UploadQueue::open(&dir.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_multipart_upload_with_backoff() {
+        let dir = TempDir::new("multipart");
+        let (index, name, bytes) = index_with_recording(&dir.0, 100);
+        let index = Mutex::new(index);
+        let mut queue = UploadQueue::open(&dir.0).unwrap();
+        queue.sync(&index.lock().unwrap()).unwrap();
+
+        let offline = UploadClient::new(
+            MockServer::client(),
+            config(&unreachable_url(), UploadMode::Multipart),
+        );
+        let start = Instant::now();
+        for delay in [2, 4, 5, 5] {
+            assert!(offline.upload_next(&mut queue, &index, start).is_err());
+            assert_eq!(queue.retry_at(), Some(start + Duration::from_secs(delay)));
+        }
+
+        let server = MockServer::start(vec![MockServer::json(200, "{}")]);
+        let mut upload_config = config(&format!("{}/upload", server.url()), UploadMode::Multipart);
+        upload_config.api_key = Some("secret".to_string());
+        let client = UploadClient::new(MockServer::client(), upload_config);
+        assert_eq!(
+            client
+                .upload_next(&mut queue, &index, start + Duration::from_secs(5))
+                .unwrap(),
+            Some(UploadStep::Finished(name.clone()))
+        );
+        assert_eq!(queue.retry_at(), None);
+
+        let request = &server.finish()[0];
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/upload")
+        );
+        assert_eq!(request.header("Authorization"), Some("Bearer secret"));
+        let body = request.body_text();
+        assert!(body.contains(&format!("filename=\"{}\"", name)));
+        assert!(body.contains(&format!("{}", MIN_VALID_UNIX)));
+        assert!(request
+            .body
+            .windows(bytes.len())
+            .any(|window| window == bytes.as_slice()));
+
+        // Kept, but not queued again.
+        let index = index.into_inner().unwrap();
+        assert!(index.get(&name).unwrap().uploaded);
+        queue.sync(&index).unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_delete_while_saving() {
+        const ROUNDS: usize = 20;
+        let dir = TempDir::new("upload-delete");
+        let index = Arc::new(Mutex::new(RecordingIndex::open(&dir.0).unwrap()));
+        let storage_lock = Arc::new(Mutex::new(()));
+        let server = MockServer::start(vec![MockServer::json(200, "{}"); ROUNDS]);
+        let mut config = config(server.url(), UploadMode::Multipart);
+        config.delete_after_upload = true;
+        let client =
+            UploadClient::new(MockServer::client(), config).with_storage_lock(storage_lock.clone());
+
+        // Saving holds the storage lock while it takes the index, as the capture
+        // thread does; deleting an uploaded recording must take them in that order.
+        let (done, finished) = std::sync::mpsc::channel();
+        let saver = std::thread::spawn({
+            let index = index.clone();
+            let done = done.clone();
+            move || {
+                for _ in 0..ROUNDS {
+                    save_recording(
+                        &[100; 1600],
+                        16000,
+                        RecordingFormat::Pcm,
+                        Some(&index),
+                        &RetentionPolicy::default(),
+                        Some(&storage_lock),
+                    )
+                    .unwrap();
+                }
+                done.send(()).unwrap();
+            }
+        });
+        let uploader = std::thread::spawn({
+            let index = index.clone();
+            let dir = dir.0.clone();
+            move || {
+                let mut queue = UploadQueue::open(&dir).unwrap();
+                let mut uploaded = 0;
+                while uploaded < ROUNDS {
+                    queue.sync(&index.lock().unwrap()).unwrap();
+                    match client.upload_next(&mut queue, &index, Instant::now()) {
+                        Ok(Some(UploadStep::Finished(_))) => uploaded += 1,
+                        Ok(_) => std::thread::sleep(Duration::from_millis(1)),
+                        Err(e) => panic!("upload failed: {}", e),
+                    }
+                }
+                done.send(()).unwrap();
+            }
+        });
+        for _ in 0..2 {
+            finished
+                .recv_timeout(Duration::from_secs(10))
+                .expect("saving and uploading deadlocked");
+        }
+        saver.join().unwrap();
+        uploader.join().unwrap();
+        assert_eq!(server.finish().len(), ROUNDS);
+        assert!(index.lock().unwrap().entries().is_empty());
+    }
+
+    #[test]
+    fn test_uploader() {
+        let dir = TempDir::new("uploader");
+        let (index, name, _) = index_with_recording(&dir.0, 100);
+        let index = Arc::new(Mutex::new(index));
+
+        let server = MockServer::start(vec![MockServer::json(200, "{}")]);
+        let mode = UploadMode::ChunkedPut { chunk_size: 4096 };
+        let uploader = Uploader::start(UploadClient::new(
+            MockServer::client(),
+            config(server.url(), mode),
+        ))
+        .unwrap();
+        uploader.set_index(Some(index.clone()));
+        assert_eq!(uploader.status().pending, 1);
+        // Already queued.
+        uploader.enqueue(&name);
+        assert_eq!(uploader.status().pending, 1);
+
+        // Nothing happens while offline.
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!index.lock().unwrap().get(&name).unwrap().uploaded);
+
+        uploader.set_online(true);
+        let requests = server.finish();
+        assert_eq!(requests.len(), 1);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while uploader.status().pending > 0 {
+            assert!(Instant::now() < deadline, "upload did not finish");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(index.lock().unwrap().get(&name).unwrap().uploaded);
+        assert_eq!(uploader.status().last_error, None);
+    }
+}
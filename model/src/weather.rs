@@ -0,0 +1,99 @@
+//! Current conditions from the Open-Meteo forecast API.
+
+use std::io;
+
+use serde::Deserialize;
+
+use crate::http::{join_url, HttpClient, Request};
+use crate::settings::Location;
+use crate::WeatherData;
+
+pub const OPEN_METEO_URL: &str = "https://api.open-meteo.com";
+/// A current-conditions forecast is well under 1 KB.
+const MAX_FORECAST_SIZE: usize = 16 * 1024;
+
+/// Where the weather comes from, injected into [`Model`](crate::Model).
+pub trait WeatherSource {
+    fn current(&self, location: &Location) -> io::Result<WeatherData>;
+}
+
+pub struct OpenMeteo<C> {
+    http: C,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    current: Current,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    temperature_2m: f64,
+    relative_humidity_2m: f64,
+    wind_speed_10m: f64,
+}
+
+impl<C: HttpClient> OpenMeteo<C> {
+    pub fn new(http: C) -> Self {
+        Self::with_base_url(http, OPEN_METEO_URL)
+    }
+
+    pub fn with_base_url(http: C, base_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.into(),
+        }
+    }
+}
+
+impl<C: HttpClient> WeatherSource for OpenMeteo<C> {
+    fn current(&self, location: &Location) -> io::Result<WeatherData> {
+        let url = format!(
+            "{}?latitude={:.2}&longitude={:.2}&current=temperature_2m,relative_humidity_2m,wind_speed_10m",
+            join_url(&self.base_url, "/v1/forecast"),
+            location.latitude,
+            location.longitude
+        );
+        let request = Request::get(url).max_response_size(MAX_FORECAST_SIZE);
+        let response = self.http.execute(request)?.error_for_status()?;
+        let forecast: ForecastResponse = serde_json::from_slice(&response.body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(WeatherData {
+            temperature: forecast.current.temperature_2m,
+            humidity: forecast.current.relative_humidity_2m,
+            wind_speed: forecast.current.wind_speed_10m,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::mock::MockServer;
+
+    #[test]
+    fn test_current() {
+        let server = MockServer::start(vec![
+            MockServer::json(
+                200,
+                r#"{"latitude": 43.45, "current": {"time": "2025-06-01T12:00",
+                    "temperature_2m": 21.4, "relative_humidity_2m": 55, "wind_speed_10m": 3.2}}"#,
+            ),
+            MockServer::json(200, r#"{"current": {}}"#),
+        ]);
+        let source = OpenMeteo::with_base_url(MockServer::client(), server.url());
+
+        let weather = source.current(&Location::default()).unwrap();
+        assert_eq!(weather.temperature, 21.4);
+        assert_eq!(weather.humidity, 55.0);
+        assert_eq!(weather.wind_speed, 3.2);
+        let error = source.current(&Location::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let requests = server.finish();
+        assert!(requests[0]
+            .path
+            .starts_with("/v1/forecast?latitude=43.45&longitude=-80.49"));
+    }
+}
@@ -0,0 +1,64 @@
+//! Linux's `/dev/i2c-*` character devices, e.g. on a Raspberry Pi or a USB adapter.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use slint_workshop_model::sensors::I2cBus;
+
+/// `I2C_SLAVE` from `linux/i2c-dev.h`.
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+pub struct LinuxI2c {
+    file: File,
+    address: Option<u8>,
+}
+
+impl LinuxI2c {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self {
+            file,
+            address: None,
+        })
+    }
+
+    fn select(&mut self, address: u8) -> io::Result<()> {
+        if self.address == Some(address) {
+            return Ok(());
+        }
+        // SAFETY: I2C_SLAVE takes the address as its argument and keeps no pointers.
+        let result = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                I2C_SLAVE as _,
+                libc::c_ulong::from(address),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.address = Some(address);
+        Ok(())
+    }
+}
+
+impl I2cBus for LinuxI2c {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.write_all(bytes)
+    }
+
+    fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.read_exact(buf)
+    }
+
+    /// A write followed by a read, with a stop in between; the supported sensors
+    /// don't need a repeated start.
+    fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> io::Result<()> {
+        self.write(address, bytes)?;
+        self.read(address, buf)
+    }
+}
@@ -1,14 +1,307 @@
 // Prevent console window in addition to Slint window in Windows release builds when, e.g., starting the app via file manager. Ignored on other platforms.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use slint_workshop_model::WifiNetworkProvider;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+#[cfg(target_os = "linux")]
+mod i2c;
+
+use log::info;
+use slint_workshop_model::assistant::{
+    Assistant, AssistantConfig, AssistantWorker, ChatEvent, ChatLog,
+};
+use slint_workshop_model::capture::{AudioSource, CaptureRecorder, WavFileSource};
+use slint_workshop_model::chat::{ChatConfig, Role};
+use slint_workshop_model::clock::{is_valid_unix, unix_now, ClockDisplay, PosixTz};
+use slint_workshop_model::history::{History, Trend, HISTORY_FILE};
+use slint_workshop_model::http::{StdHttpClient, TlsConfig};
+use slint_workshop_model::mqtt::{MqttConfig, StdMqttClient};
+use slint_workshop_model::navigation::Page;
+use slint_workshop_model::playback::{FileSink, PlaybackQueue};
+use slint_workshop_model::recording::{Recorder, RecordingSession};
+use slint_workshop_model::recordings::{RecordingIndex, RetentionPolicy};
+use slint_workshop_model::sensors::SensorSource;
+use slint_workshop_model::settings::{FileSettingsStore, SettingField, Settings};
+use slint_workshop_model::stt::SttConfig;
+use slint_workshop_model::telemetry::{
+    Command, DeviceHealth, RecordingEvent, Telemetry, TelemetryConfig,
+};
+use slint_workshop_model::tts::TtsConfig;
+use slint_workshop_model::upload::{UploadClient, UploadConfig, UploadMode, Uploader};
+use slint_workshop_model::weather::OpenMeteo;
+use slint_workshop_model::{Model, WifiNetworkProvider};
 
 slint::include_modules!();
 
+const MAX_CHAT_MESSAGES: usize = 20;
+/// Hours shown on the history page.
+const HISTORY_HOURS: usize = 24;
+const HEALTH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+const SENSOR_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+type DesktopTelemetry = Telemetry<StdMqttClient>;
+
+type DesktopAssistant = Assistant<StdHttpClient, FileSink>;
+
+/// Lists nearby networks with NetworkManager's `nmcli`, where it is installed.
+struct NmcliWifi;
+
+impl WifiNetworkProvider for NmcliWifi {
+    fn scan_wifi_networks(&self) -> Vec<slint_workshop_model::WifiNetwork> {
+        let output = match std::process::Command::new("nmcli")
+            .args(["--terse", "--fields", "SSID", "device", "wifi", "list"])
+            .output()
+        {
+            Ok(output) if output.status.success() => output.stdout,
+            Ok(output) => {
+                info!(
+                    "nmcli failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                return Vec::new();
+            }
+            Err(e) => {
+                info!("Wi-Fi scan unavailable: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut networks: Vec<slint_workshop_model::WifiNetwork> = Vec::new();
+        for ssid in String::from_utf8_lossy(&output).lines() {
+            if !ssid.is_empty() && !networks.iter().any(|network| network.ssid == ssid) {
+                networks.push(slint_workshop_model::WifiNetwork {
+                    ssid: ssid.to_string(),
+                });
+            }
+        }
+        networks
+    }
+}
+
+/// `$XDG_CONFIG_HOME/slint-workshop/settings.json`, or under `~/.config`.
+fn settings_path() -> PathBuf {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    config_dir.join("slint-workshop").join("settings.json")
+}
+
+/// `$XDG_DATA_HOME/slint-workshop`, or under `~/.local/share`.
+fn data_dir() -> PathBuf {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .unwrap_or_default();
+    data_dir.join("slint-workshop")
+}
+
+/// HTTPS only unless `ALLOW_HTTP=1`; `CA_CERT` names a PEM file with extra CAs to
+/// trust, e.g. for on-prem servers.
+fn http_client() -> anyhow::Result<StdHttpClient> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let ca_certs = match var("CA_CERT") {
+        Some(path) => Some(
+            std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read CA_CERT {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    Ok(StdHttpClient::new(TlsConfig {
+        ca_certs,
+        allow_http: var("ALLOW_HTTP").as_deref() == Some("1"),
+    })?)
+}
+
+/// The assistant's servers, from the variables the ESP32 build reads at compile time.
+fn assistant_config() -> AssistantConfig {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let chat_defaults = ChatConfig::default();
+    let tts_defaults = TtsConfig::default();
+    AssistantConfig {
+        stt: var("STT_URL").map(|url| SttConfig {
+            base_url: url,
+            api_key: var("STT_API_KEY"),
+            ..Default::default()
+        }),
+        chat: var("LLM_URL").map(|url| ChatConfig {
+            base_url: url,
+            api_key: var("LLM_API_KEY"),
+            model: var("LLM_MODEL").unwrap_or(chat_defaults.model.clone()),
+            ..chat_defaults
+        }),
+        tts: var("TTS_URL").map(|url| TtsConfig {
+            base_url: url,
+            api_key: var("TTS_API_KEY"),
+            voice: var("TTS_VOICE").unwrap_or(tts_defaults.voice.clone()),
+            ..tts_defaults
+        }),
+    }
+}
+
+/// Replies are "spoken" into WAV files under the data directory.
+fn new_assistant(http: StdHttpClient) -> DesktopAssistant {
+    let config = assistant_config();
+    let playback = if config.tts.is_some() {
+        let dir = data_dir().join("playback");
+        match std::fs::create_dir_all(&dir).and_then(|_| PlaybackQueue::new(FileSink::new(&dir))) {
+            Ok(playback) => {
+                info!("Spoken replies are written to {}", dir.display());
+                Some(playback)
+            }
+            Err(e) => {
+                info!("Playback unavailable: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    Assistant::new(http, config, playback)
+}
+
+/// Uploads recordings to `UPLOAD_URL`, read like the ESP32 build's variables. The
+/// desktop counts as always online.
+fn new_uploader(http: StdHttpClient) -> Option<Uploader> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let url = var("UPLOAD_URL")?;
+    let mut config = UploadConfig {
+        api_key: var("UPLOAD_API_KEY"),
+        delete_after_upload: var("UPLOAD_DELETE").as_deref() == Some("1"),
+        ..UploadConfig::new(url)
+    };
+    if var("UPLOAD_MODE").as_deref() == Some("multipart") {
+        config.mode = UploadMode::Multipart;
+    }
+    info!("Uploading recordings to {}", config.url);
+    match Uploader::start(UploadClient::new(http, config)) {
+        Ok(uploader) => {
+            uploader.set_online(true);
+            Some(uploader)
+        }
+        Err(e) => {
+            info!("Failed to start uploader: {}", e);
+            None
+        }
+    }
+}
+
+/// Publishes telemetry to `MQTT_URL`, read like the ESP32 build's variables.
+fn new_telemetry() -> Option<DesktopTelemetry> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let url = var("MQTT_URL")?;
+    let mut config = TelemetryConfig::new(var("MQTT_DEVICE_ID").unwrap_or("desktop".to_string()));
+    config.model = "Desktop".to_string();
+    let mqtt = MqttConfig {
+        username: var("MQTT_USERNAME"),
+        password: var("MQTT_PASSWORD"),
+        will: Some(config.will()),
+        ..MqttConfig::new(url, config.device_id.clone())
+    };
+    info!(
+        "Publishing telemetry to {} as {}",
+        mqtt.url, config.base_topic
+    );
+    match StdMqttClient::start(mqtt) {
+        Ok(client) => Some(Telemetry::new(client, config)),
+        Err(e) => {
+            info!("Failed to start MQTT: {}", e);
+            None
+        }
+    }
+}
+
+/// Reads the room from a sensor on the Linux I2C bus named by `SENSOR_I2C`, such as
+/// `/dev/i2c-1` on a Raspberry Pi.
+#[cfg(target_os = "linux")]
+fn new_sensor() -> Option<Box<dyn SensorSource + Send>> {
+    let path = std::env::var_os("SENSOR_I2C").map(PathBuf::from)?;
+    let sensor = i2c::LinuxI2c::open(&path).and_then(slint_workshop_model::sensors::detect);
+    match sensor {
+        Ok(sensor) => {
+            info!("Indoor sensor {} on {}", sensor.name(), path.display());
+            Some(sensor)
+        }
+        Err(e) => {
+            info!("No indoor sensor on {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn new_sensor() -> Option<Box<dyn SensorSource + Send>> {
+    None
+}
+
+/// There is no microphone backend on the desktop, so recordings replay the WAV file
+/// named by `MIC_WAV` in real time. Without it there is no recorder.
+fn new_recorder(
+    assistant: Arc<DesktopAssistant>,
+    uploader: Option<Arc<Uploader>>,
+    telemetry: Option<Arc<DesktopTelemetry>>,
+) -> Option<CaptureRecorder> {
+    let Some(path) = std::env::var_os("MIC_WAV").map(PathBuf::from) else {
+        info!("Set MIC_WAV to a mono WAV file to enable recording");
+        return None;
+    };
+    // Check the file once up front; it is re-read for every recording.
+    let sample_rate = match WavFileSource::open(&path) {
+        Ok(source) => source.sample_rate(),
+        Err(e) => {
+            info!("Cannot record from {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let assistant = match AssistantWorker::new(assistant) {
+        Ok(assistant) => assistant,
+        Err(e) => {
+            info!("Cannot start the assistant: {}", e);
+            return None;
+        }
+    };
+    info!("Recording from {}", path.display());
+
+    let mut recorder = CaptureRecorder::new(
+        RecordingSession::new(sample_rate, Settings::default().recording_length()),
+        move || Ok(Box::new(WavFileSource::open(&path)?) as Box<dyn AudioSource>),
+    );
+    let dir = data_dir().join("recordings");
+    match std::fs::create_dir_all(&dir).and_then(|_| RecordingIndex::open(&dir)) {
+        Ok(index) => recorder = recorder.with_index(index, RetentionPolicy::default()),
+        Err(e) => info!("Recordings will not be saved: {}", e),
+    }
+    if let Some(uploader) = &uploader {
+        uploader.set_index(recorder.index().cloned());
+    }
+    Some(recorder.on_saved(move |saved| {
+        if let Some(uploader) = &uploader {
+            uploader.enqueue(&saved.file_name);
+        }
+        if let Some(telemetry) = &telemetry {
+            let _ = telemetry.publish_recording(&RecordingEvent::from(&saved));
+        }
+        assistant.submit(&saved.file_name, saved.format, saved.encoded)
+    }))
+}
+
 /// Our App struct that holds the UI
 struct App {
     ui: MainWindow,
-    model: slint_workshop_model::Model,
+    model: Rc<RefCell<Model<CaptureRecorder>>>,
+    assistant: Arc<DesktopAssistant>,
+    uploader: Option<Arc<Uploader>>,
+    telemetry: Option<Arc<DesktopTelemetry>>,
+    last_health: Cell<Option<std::time::Instant>>,
+    chat_log: RefCell<ChatLog>,
+    /// Pipeline stage reported by the assistant; recording and speaking take precedence.
+    assistant_state: Cell<AssistantState>,
+    timezone: PosixTz,
+    started: std::time::Instant,
 }
 
 impl App {
@@ -19,17 +312,580 @@ impl App {
         // Make a new AppWindow
         let ui = MainWindow::new()?;
 
+        let store = FileSettingsStore::new(settings_path());
+        let settings = Settings::load(&store).unwrap_or_else(|e| {
+            info!(
+                "Failed to load {}: {}, using defaults",
+                store.path().display(),
+                e
+            );
+            Settings::default()
+        });
+        info!("Settings from {}: {:?}", store.path().display(), settings);
+
+        let http = http_client()?;
+        let mut model = Model::new(Box::new(OpenMeteo::new(http.clone())), Box::new(NmcliWifi))
+            .with_settings(settings, Some(Box::new(store)));
+        let assistant = Arc::new(new_assistant(http.clone()));
+        let uploader = new_uploader(http).map(Arc::new);
+        let telemetry = new_telemetry().map(Arc::new);
+        if let Some(recorder) = new_recorder(assistant.clone(), uploader.clone(), telemetry.clone())
+        {
+            model = model.with_recorder(recorder);
+        }
+        if let Some(sensor) = new_sensor() {
+            model = model.with_sensor(sensor);
+        }
+        let mut history = History::default();
+        let history_path = data_dir().join(HISTORY_FILE);
+        match std::fs::create_dir_all(data_dir()).and_then(|_| history.attach(&history_path)) {
+            Ok(()) => info!(
+                "{} weather samples in {}",
+                history.len(),
+                history_path.display()
+            ),
+            Err(e) => info!("Weather history will not be saved: {}", e),
+        }
+        model = model.with_history(history);
+
         Ok(Self {
             ui,
-            model: slint_workshop_model::Model,
+            model: Rc::new(RefCell::new(model)),
+            assistant,
+            uploader,
+            telemetry,
+            last_health: Cell::new(None),
+            chat_log: RefCell::new(ChatLog::new(MAX_CHAT_MESSAGES)),
+            assistant_state: Cell::new(AssistantState::Idle),
+            timezone: local_timezone(),
+            started: std::time::Instant::now(),
         })
     }
 
     /// Run the App
     fn run(self) -> anyhow::Result<()> {
+        let app = Rc::new(self);
+
+        app.refresh_weather();
+        app.refresh_indoor();
+        app.update_settings_ui();
+        app.show_page();
+
+        // Refresh the weather at the configured interval
+        let weather_timer = Rc::new(slint::Timer::default());
+        let app_weather = app.clone();
+        weather_timer.start(
+            slint::TimerMode::Repeated,
+            app.model.borrow().settings().fetch_interval(),
+            move || app_weather.refresh_weather(),
+        );
+
+        let app_navigate = app.clone();
+        app.ui.on_navigate(move |delta| {
+            let changed = app_navigate.model.borrow_mut().navigate(delta);
+            if changed {
+                app_navigate.show_page();
+            }
+        });
+
+        let app_open = app.clone();
+        app.ui.on_open_page(move |page| {
+            let changed = app_open.model.borrow_mut().open_page(model_page(page));
+            if changed {
+                app_open.show_page();
+            }
+        });
+
+        let app_back = app.clone();
+        app.ui.on_navigate_back(move || {
+            let changed = app_back.model.borrow_mut().navigate_back();
+            if changed {
+                app_back.show_page();
+            }
+        });
+
+        let app_settings = app.clone();
+        let weather_timer_settings = weather_timer.clone();
+        app.ui.on_adjust_setting(move |index, delta| {
+            let Some(field) = SettingField::from_index(index as usize) else {
+                return;
+            };
+            let result = app_settings.model.borrow_mut().adjust_setting(field, delta);
+            let status = match result {
+                Ok(()) if field.needs_restart() => "Saved, used by the device only".to_string(),
+                Ok(()) => "Saved".to_string(),
+                Err(e) => format!("Not saved: {}", e),
+            };
+            app_settings.ui.set_settings_status(status.into());
+            app_settings.update_settings_ui();
+            let interval = app_settings.model.borrow().settings().fetch_interval();
+            if weather_timer_settings.interval() != interval {
+                weather_timer_settings.set_interval(interval);
+            }
+            if field == SettingField::Location {
+                app_settings.refresh_weather();
+            }
+        });
+
+        let app_scan = app.clone();
+        app.ui.on_scan_wifi(move || app_scan.scan_wifi());
+
+        let app_start = app.clone();
+        app.ui
+            .on_start_recording(move || app_start.start_recording());
+
+        let app_stop = app.clone();
+        app.ui
+            .on_stop_recording(move || app_stop.model.borrow_mut().stop_recording());
+
+        let app_cancel = app.clone();
+        app.ui
+            .on_cancel_reply(move || app_cancel.assistant.cancel());
+
+        let app_delete = app.clone();
+        app.ui.on_delete_recording(move |name| {
+            if let Some(index) = app_delete.recordings_index() {
+                match index.lock().unwrap().remove(&name) {
+                    Ok(_) => info!("Deleted recording {}", name),
+                    Err(e) => info!("Failed to delete {}: {}", name, e),
+                }
+            }
+            app_delete.update_recordings_ui();
+        });
+
+        // Read the indoor sensor, if there is one
+        let app_indoor = app.clone();
+        let indoor_timer = slint::Timer::default();
+        if app.model.borrow().sensor_name().is_some() {
+            indoor_timer.start(slint::TimerMode::Repeated, SENSOR_INTERVAL, move || {
+                app_indoor.refresh_indoor()
+            });
+        }
+
+        // Advance the recorder and the assistant and mirror their state
+        let app_recording = app.clone();
+        let recording_timer = slint::Timer::default();
+        recording_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_millis(100),
+            move || app_recording.update_recording_ui(),
+        );
+
+        // Take commands and publish health over MQTT
+        let app_telemetry = app.clone();
+        let telemetry_timer = slint::Timer::default();
+        telemetry_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_millis(500),
+            move || app_telemetry.poll_telemetry(),
+        );
+
+        // Update the clock every second
+        let app_clock = app.clone();
+        let clock_timer = slint::Timer::default();
+        clock_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_secs(1),
+            move || {
+                let display = ClockDisplay::new(unix_now(), &app_clock.timezone);
+                app_clock.ui.set_clock_time(display.time.into());
+                app_clock.ui.set_clock_date(display.date.into());
+                app_clock.ui.set_clock_synced(display.synced);
+                if app_clock.model.borrow().current_page() == Page::Diagnostics {
+                    app_clock.update_diagnostics_ui();
+                }
+            },
+        );
+
         // Run the UI (and map an error to an anyhow::Error).
-        self.ui.run().map_err(|e| e.into())
+        app.ui.run().map_err(|e| e.into())
+    }
+
+    fn show_page(&self) {
+        let page = self.model.borrow().current_page();
+        self.ui.set_current_page(app_page(page));
+        self.ui.set_page_index(page.index() as i32);
+        self.ui.set_page_count(Page::ALL.len() as i32);
+        match page {
+            Page::Recordings => self.update_recordings_ui(),
+            Page::Settings => self.update_settings_ui(),
+            Page::History => self.update_history_ui(),
+            Page::Diagnostics => self.update_diagnostics_ui(),
+            _ => {}
+        }
+    }
+
+    fn start_recording(&self) {
+        let format = self.model.borrow().settings().recording_format;
+        let result = self.model.borrow_mut().start_recording(format);
+        match result {
+            Ok(()) => {
+                // Keep the reply being spoken out of the new recording.
+                self.assistant.stop_speaking();
+                if let Some(telemetry) = &self.telemetry {
+                    let _ = telemetry.publish_recording(&RecordingEvent::Started);
+                }
+            }
+            Err(e) => info!("Recording failed: {}", e),
+        }
+    }
+
+    fn poll_telemetry(&self) {
+        let Some(telemetry) = &self.telemetry else {
+            return;
+        };
+        let was_connected = telemetry.is_connected();
+        for command in telemetry.poll() {
+            match command {
+                Command::Record => self.start_recording(),
+                Command::Brightness(percent) => {
+                    info!(
+                        "Ignoring brightness {}%: no backlight on the desktop",
+                        percent
+                    )
+                }
+            }
+        }
+        if !telemetry.is_connected() {
+            return;
+        }
+        if !was_connected {
+            if let Some(weather) = self.model.borrow().weather() {
+                let _ = telemetry.publish_weather(weather);
+            }
+        }
+        let now = std::time::Instant::now();
+        let due = match self.last_health.get() {
+            Some(last) => now.duration_since(last) >= HEALTH_INTERVAL,
+            None => true,
+        };
+        if !was_connected || due {
+            let health = DeviceHealth {
+                uptime: self.started.elapsed().as_secs(),
+                ..Default::default()
+            };
+            if telemetry.publish_health(&health).is_ok() {
+                self.last_health.set(Some(now));
+            }
+        }
+    }
+
+    fn update_recording_ui(&self) {
+        self.model.borrow_mut().poll_recording();
+        let mut chat_changed = false;
+        for event in self.assistant.take_events() {
+            chat_changed |= self.chat_log.borrow_mut().apply(&event);
+            match event {
+                ChatEvent::Transcribing => {
+                    self.assistant_state.set(AssistantState::Transcribing);
+                    self.ui.set_chat_status("".into());
+                    if self.model.borrow_mut().open_page(Page::Chat) {
+                        self.show_page();
+                    }
+                }
+                ChatEvent::Thinking => self.assistant_state.set(AssistantState::Thinking),
+                ChatEvent::Failed(error) => self.ui.set_chat_status(error.into()),
+                ChatEvent::Done => self.assistant_state.set(AssistantState::Idle),
+                ChatEvent::Message(_) | ChatEvent::Token(_) | ChatEvent::Reply(_) => {}
+            }
+        }
+        if chat_changed {
+            let items: Vec<ChatMessage> = self
+                .chat_log
+                .borrow()
+                .messages()
+                .iter()
+                .map(|m| ChatMessage {
+                    from_user: m.role == Role::User,
+                    text: m.content.as_str().into(),
+                    time: self.message_time(m.timestamp).into(),
+                })
+                .collect();
+            self.ui
+                .set_chat_messages(Rc::new(slint::VecModel::from(items)).into());
+        }
+
+        let model = self.model.borrow();
+        let Some(recorder) = model.recorder() else {
+            return;
+        };
+        let session = recorder.session();
+        self.ui.set_assistant_state(if session.is_recording() {
+            AssistantState::Listening
+        } else if self.assistant.is_speaking() {
+            AssistantState::Speaking
+        } else {
+            self.assistant_state.get()
+        });
+
+        let now = std::time::Instant::now();
+        self.ui.set_recording(session.is_recording());
+        self.ui
+            .set_recording_elapsed(session.elapsed(now).as_secs() as i32);
+        self.ui
+            .set_recording_limit(session.max_duration().as_secs() as i32);
+        let levels = session.levels();
+        self.ui.set_audio_levels(AudioLevels {
+            rms: levels.rms,
+            peak: levels.peak,
+            clip_count: levels.clip_count as i32,
+        });
+        self.ui
+            .set_waveform(Rc::new(slint::VecModel::from(session.waveform())).into());
+    }
+
+    /// "14:05" in the local timezone, or nothing for a bogus timestamp.
+    fn message_time(&self, timestamp: u64) -> String {
+        if is_valid_unix(timestamp) {
+            self.timezone.to_local(timestamp as i64).time_label()
+        } else {
+            String::new()
+        }
+    }
+
+    fn recordings_index(&self) -> Option<Arc<Mutex<RecordingIndex>>> {
+        self.model
+            .borrow()
+            .recorder()
+            .and_then(|recorder| recorder.index().cloned())
+    }
+
+    /// Show the saved recordings, newest first.
+    fn update_recordings_ui(&self) {
+        let Some(index) = self.recordings_index() else {
+            self.ui
+                .set_recordings(Rc::new(slint::VecModel::<RecordingSummary>::default()).into());
+            self.ui.set_recordings_usage("Set MIC_WAV to record".into());
+            return;
+        };
+        let index = index.lock().unwrap();
+        let recordings: Vec<RecordingSummary> = index
+            .entries()
+            .iter()
+            .rev()
+            .map(|entry| RecordingSummary {
+                name: entry.file_name.as_str().into(),
+                duration: entry.duration_label().into(),
+                size: entry.size_label().into(),
+                format: format!("{:?}", entry.format).into(),
+                created: entry.created_label(&self.timezone).into(),
+                peak: entry.peak,
+                uploaded: entry.uploaded,
+            })
+            .collect();
+        self.ui.set_recordings_usage(
+            format!(
+                "{} files, {:.1} MB",
+                recordings.len(),
+                index.total_bytes() as f64 / (1024.0 * 1024.0)
+            )
+            .into(),
+        );
+        self.ui
+            .set_recordings(Rc::new(slint::VecModel::from(recordings)).into());
+    }
+
+    fn refresh_weather(&self) {
+        let mut model = self.model.borrow_mut();
+        match model.refresh_weather() {
+            Ok(weather) => {
+                self.ui.set_weather(WeatherInfo {
+                    temperature: weather.temperature as f32,
+                    humidity: weather.humidity as f32,
+                    wind_speed: weather.wind_speed as f32,
+                });
+                if let Some(telemetry) = &self.telemetry {
+                    let _ = telemetry.publish_weather(weather);
+                }
+                info!("Weather updated");
+            }
+            Err(e) => info!("Weather fetch failed: {}", e),
+        }
+        if model.current_page() == Page::History {
+            drop(model);
+            self.update_history_ui();
+        }
+    }
+
+    fn update_history_ui(&self) {
+        let model = self.model.borrow();
+        let history = model.history();
+        let now = unix_now();
+        let series = |trend: Trend, unit: &str, decimals: usize| TrendSeries {
+            summary: match trend.stats {
+                Some(stats) => stats.label(unit, decimals).into(),
+                None => "No data".into(),
+            },
+            values: Rc::new(slint::VecModel::from(trend.values)).into(),
+        };
+        self.ui.set_history_temperature(series(
+            history.trend(now, HISTORY_HOURS, |a| a.temperature),
+            "°C",
+            1,
+        ));
+        self.ui.set_history_humidity(series(
+            history.trend(now, HISTORY_HOURS, |a| a.humidity),
+            "%",
+            0,
+        ));
+        let status = if !is_valid_unix(now) {
+            "Waiting for the clock".to_string()
+        } else {
+            match history.path() {
+                Some(_) => format!("{} samples saved", history.len()),
+                None => format!("{} samples, not saved", history.len()),
+            }
+        };
+        self.ui.set_history_status(status.into());
+    }
+
+    fn refresh_indoor(&self) {
+        let mut model = self.model.borrow_mut();
+        match model.refresh_indoor() {
+            Ok(reading) => self.ui.set_indoor(IndoorInfo {
+                available: true,
+                temperature: reading.temperature as f32,
+                has_humidity: reading.humidity.is_some(),
+                humidity: reading.humidity.unwrap_or_default() as f32,
+                has_pressure: reading.pressure.is_some(),
+                pressure: reading.pressure.unwrap_or_default() as f32,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => info!("Indoor sensor read failed: {}", e),
+        }
     }
+
+    fn update_settings_ui(&self) {
+        let model = self.model.borrow();
+        let settings = model.settings();
+        let items: Vec<SettingItem> = SettingField::ALL
+            .iter()
+            .map(|&field| SettingItem {
+                label: field.label().into(),
+                value: settings.value_label(field).into(),
+            })
+            .collect();
+        self.ui
+            .set_settings(Rc::new(slint::VecModel::from(items)).into());
+        self.ui.set_location(settings.location.name.as_str().into());
+        self.ui.set_weather_status(
+            format!(
+                "Auto-updating every {}",
+                settings.value_label(SettingField::FetchInterval)
+            )
+            .into(),
+        );
+    }
+
+    fn scan_wifi(&self) {
+        let mut model = self.model.borrow_mut();
+        let networks: Vec<WifiNetwork> = model
+            .scan_wifi()
+            .iter()
+            .map(|network| WifiNetwork {
+                ssid: network.ssid.as_str().into(),
+            })
+            .collect();
+        self.ui
+            .set_wifi_status(format!("{} networks found", networks.len()).into());
+        self.ui
+            .set_wifi_networks(Rc::new(slint::VecModel::from(networks)).into());
+    }
+
+    fn update_diagnostics_ui(&self) {
+        let uptime = self.started.elapsed().as_secs();
+        let model = self.model.borrow();
+        let items = [
+            (
+                "Uptime",
+                format!(
+                    "{}h {:02}m {:02}s",
+                    uptime / 3600,
+                    uptime / 60 % 60,
+                    uptime % 60
+                ),
+            ),
+            ("Weather", model.weather().map_or("-", |_| "OK").to_string()),
+            ("Wi-Fi networks", model.wifi_networks().len().to_string()),
+            (
+                "Weather history",
+                format!("{} samples", model.history().len()),
+            ),
+            (
+                "Indoor sensor",
+                model.sensor_name().unwrap_or("None").to_string(),
+            ),
+            (
+                "Recorder",
+                std::env::var("MIC_WAV").unwrap_or_else(|_| "None".to_string()),
+            ),
+            (
+                "Uploads",
+                match self.uploader.as_ref().map(|uploader| uploader.status()) {
+                    Some(status) => match status.last_error {
+                        Some(e) if status.pending > 0 => {
+                            format!("{} pending ({})", status.pending, e)
+                        }
+                        _ => format!("{} pending", status.pending),
+                    },
+                    None => "Off".to_string(),
+                },
+            ),
+            (
+                "MQTT",
+                match &self.telemetry {
+                    Some(telemetry) if telemetry.is_connected() => "Connected".to_string(),
+                    Some(_) => "Disconnected".to_string(),
+                    None => "Off".to_string(),
+                },
+            ),
+            ("Data", data_dir().display().to_string()),
+            ("Settings", settings_path().display().to_string()),
+        ];
+        let items: Vec<DiagnosticItem> = items
+            .into_iter()
+            .map(|(label, value)| DiagnosticItem {
+                label: label.into(),
+                value: value.into(),
+            })
+            .collect();
+        self.ui
+            .set_diagnostics(Rc::new(slint::VecModel::from(items)).into());
+    }
+}
+
+fn app_page(page: Page) -> AppPage {
+    match page {
+        Page::Weather => AppPage::Weather,
+        Page::Forecast => AppPage::Forecast,
+        Page::History => AppPage::History,
+        Page::Chat => AppPage::Chat,
+        Page::Recordings => AppPage::Recordings,
+        Page::Wifi => AppPage::Wifi,
+        Page::Settings => AppPage::Settings,
+        Page::Diagnostics => AppPage::Diagnostics,
+    }
+}
+
+fn model_page(page: AppPage) -> Page {
+    match page {
+        AppPage::Weather => Page::Weather,
+        AppPage::Forecast => Page::Forecast,
+        AppPage::History => Page::History,
+        AppPage::Chat => Page::Chat,
+        AppPage::Recordings => Page::Recordings,
+        AppPage::Wifi => Page::Wifi,
+        AppPage::Settings => Page::Settings,
+        AppPage::Diagnostics => Page::Diagnostics,
+    }
+}
+
+/// The timezone from a POSIX `TZ` variable, falling back to the system's current offset.
+fn local_timezone() -> PosixTz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|tz| PosixTz::parse(&tz).ok())
+        .unwrap_or_else(|| PosixTz::fixed("local", chrono::Local::now().offset().local_minus_utc()))
 }
 
 /// A minimal main function that initializes the App and runs it.